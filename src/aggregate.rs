@@ -0,0 +1,403 @@
+//! Per-node aggregate values.
+//!
+//! A tree with an [`Aggregate`] type parameter keeps the aggregated value
+//! of each node's subtree in [`Node::aggregate`](crate::node::Node::aggregate).
+//! Values are kept up to date by [`insert`](Octree::insert) and [`remove`](Octree::remove),
+//! so [`aggregate_in`](Octree::aggregate_in) could take the whole-node values
+//! for the nodes, fully contained in the region.
+//!
+//! Each element is aggregated once, at the leaf containing the `min` corner of it's volume,
+//! clamped to the tree's [`Aabb`], so volumes sticking out of the tree are aggregated too.
+//!
+//! ```rust
+//! use oktree::{aggregate::Count, prelude::*};
+//!
+//! let aabb = Aabb::new(TUVec3::splat(8), 8).unwrap();
+//! let mut tree: Octree<u8, TUVec3u8, Count> = Octree::from_aabb_with_aggregate(aabb);
+//! tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(2, 2, 2)).unwrap();
+//! tree.insert(TUVec3u8::new(12, 12, 12)).unwrap();
+//!
+//! assert_eq!(tree.aggregate(), Count(3));
+//!
+//! let region = Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(8));
+//! assert_eq!(tree.aggregate_in(&region), Count(2));
+//! ```
+
+use std::mem::size_of;
+
+use heapless::Vec as HVec;
+use smallvec::SmallVec;
+
+use crate::{
+    bounding::{Aabb, Unsigned},
+    node::NodeType,
+    tree::Octree,
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Value, aggregated over the elements of the tree.
+///
+/// Should form a monoid: `combine` is associative
+/// and [`empty`](Aggregate::empty) is it's identity.
+/// `()` is used by the trees without aggregates and costs nothing.
+pub trait Aggregate<T>: Copy {
+    /// Aggregate of no elements.
+    fn empty() -> Self;
+
+    /// Aggregate of a single element.
+    fn from_element(element: &T) -> Self;
+
+    /// Combines two aggregates.
+    fn combine(&self, other: &Self) -> Self;
+}
+
+impl<T> Aggregate<T> for () {
+    #[inline(always)]
+    fn empty() -> Self {}
+
+    #[inline(always)]
+    fn from_element(_: &T) -> Self {}
+
+    #[inline(always)]
+    fn combine(&self, _: &Self) -> Self {}
+}
+
+/// Number of the elements.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Count(pub usize);
+
+impl<T> Aggregate<T> for Count {
+    #[inline(always)]
+    fn empty() -> Self {
+        Count(0)
+    }
+
+    #[inline(always)]
+    fn from_element(_: &T) -> Self {
+        Count(1)
+    }
+
+    #[inline(always)]
+    fn combine(&self, other: &Self) -> Self {
+        Count(self.0 + other.0)
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Returns the aggregate of all the elements in the tree.
+    pub fn aggregate(&self) -> A {
+        self.nodes[self.root].aggregate
+    }
+
+    /// Returns the aggregate of the elements, whose volume's `min` corner,
+    /// clamped to the tree's [`Aabb`], lies in the `aabb`.
+    ///
+    /// Nodes, fully contained in the `aabb`, contribute their stored aggregate
+    /// without descending into them.
+    pub fn aggregate_in(&self, aabb: &Aabb<U>) -> A {
+        let mut result = A::empty();
+
+        let mut stack: HVec<NodeId, 32> = HVec::new();
+        stack.push(self.root).unwrap();
+
+        // Walk the nodes with a heapless stack. Children that don't fit
+        // into a full stack are aggregated with `raggregate_in`.
+        while let Some(node) = stack.pop() {
+            let n = &self.nodes[node];
+            if !n.aabb.overlaps(aabb) {
+                continue;
+            }
+
+            if aabb.min.le(&n.aabb.min).all() && n.aabb.max.le(&aabb.max).all() {
+                result = result.combine(&n.aggregate);
+                continue;
+            }
+
+            match n.ntype {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    result = result.combine(&self.leaf_aggregate(node, Some(aabb)));
+                }
+
+                NodeType::Branch(branch) => {
+                    for child in branch.children() {
+                        if stack.push(child).is_err() {
+                            result = result.combine(&self.raggregate_in(child, aabb));
+                        }
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    fn raggregate_in(&self, node: NodeId, aabb: &Aabb<U>) -> A {
+        let n = &self.nodes[node];
+        if !n.aabb.overlaps(aabb) {
+            return A::empty();
+        }
+
+        if aabb.min.le(&n.aabb.min).all() && n.aabb.max.le(&aabb.max).all() {
+            return n.aggregate;
+        }
+
+        match n.ntype {
+            NodeType::Empty => A::empty(),
+
+            NodeType::Leaf(_) | NodeType::Bucket => self.leaf_aggregate(node, Some(aabb)),
+
+            NodeType::Branch(branch) => branch.children().iter().fold(A::empty(), |acc, &child| {
+                acc.combine(&self.raggregate_in(child, aabb))
+            }),
+        }
+    }
+
+    /// Recomputes the aggregates on the path to the `element`.
+    ///
+    /// Should be called after the element was changed in place,
+    /// through [`get_element_mut`](Octree::get_element_mut), [`get_mut`](Octree::get_mut)
+    /// or an [`entry`](Octree::entry). The element's volume should stay the same.
+    ///
+    /// Returns [`TreeError::ElementNotFound`] if the `element` doesn't exist.
+    pub fn refresh_aggregate(&mut self, element: ElementId) -> Result<(), TreeError> {
+        let Some(elem) = self.get_element(element) else {
+            return Err(TreeError::ElementNotFound(format!(
+                "Element with id: {} not found",
+                element.0
+            )));
+        };
+
+        let min = self.nodes[self.root].aabb.clipped_min(&elem.volume());
+        let mut node = self.root;
+        while let NodeType::Branch(branch) = self.nodes[node].ntype {
+            node = branch.find_child(&min, self.nodes[node].aabb.center());
+        }
+        self.refresh_up(node);
+        Ok(())
+    }
+
+    /// Recomputes the aggregates of all the nodes.
+    ///
+    /// Should be called after the elements were changed in place
+    /// through [`iter_mut`](Octree::iter_mut).
+    pub fn refresh_aggregates(&mut self) {
+        if size_of::<A>() == 0 {
+            return;
+        }
+
+        // Post-order traversal. Node is computed when it's popped the second time.
+        let mut stack: SmallVec<[(NodeId, bool); 32]> = SmallVec::new();
+        stack.push((self.root, false));
+        while let Some((node, visited)) = stack.pop() {
+            match self.nodes[node].ntype {
+                NodeType::Branch(branch) if !visited => {
+                    stack.push((node, true));
+                    for child in branch.children() {
+                        stack.push((child, false));
+                    }
+                }
+                _ => self.nodes[node].aggregate = self.node_aggregate(node),
+            }
+        }
+    }
+
+    /// Recomputes the aggregate of the `node` and all it's ancestors.
+    pub(crate) fn refresh_up(&mut self, node: NodeId) {
+        if size_of::<A>() == 0 {
+            return;
+        }
+
+        let mut current = Some(node);
+        while let Some(node) = current {
+            self.nodes[node].aggregate = self.node_aggregate(node);
+            current = self.nodes[node].parent;
+        }
+    }
+
+    /// Aggregates the elements of the leaf or bucket `node`, whose clamped min corners
+    /// are in the node and in the `aabb`, if given.
+    fn leaf_aggregate(&self, node: NodeId, aabb: Option<&Aabb<U>>) -> A {
+        let n = &self.nodes[node];
+        let root = &self.nodes[self.root].aabb;
+        self.leaf_elements(node)
+            .iter()
+            .map(|&e| &self.elements[e])
+            .filter(|element| {
+                let min = root.clipped_min(&element.volume());
+                n.aabb.contains(&min) && aabb.is_none_or(|aabb| aabb.contains(&min))
+            })
+            .fold(A::empty(), |acc, element| {
+                acc.combine(&A::from_element(element))
+            })
+    }
+
+    /// Computes the aggregate of the `node` from it's elements or children.
+    fn node_aggregate(&self, node: NodeId) -> A {
+        let n = &self.nodes[node];
+        match n.ntype {
+            NodeType::Empty => A::empty(),
+
+            NodeType::Leaf(_) | NodeType::Bucket => self.leaf_aggregate(node, None),
+
+            NodeType::Branch(branch) => branch.children().iter().fold(A::empty(), |acc, &child| {
+                acc.combine(&self.nodes[child].aggregate)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::{TUVec3, TUVec3u8};
+    use crate::tests::{block, DummyVolume};
+
+    /// Sum and maximum of the weights.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Weight {
+        sum: u32,
+        max: u32,
+    }
+
+    impl Aggregate<DummyVolume<u8>> for Weight {
+        fn empty() -> Self {
+            Weight { sum: 0, max: 0 }
+        }
+
+        fn from_element(element: &DummyVolume<u8>) -> Self {
+            Weight {
+                sum: element.weight,
+                max: element.weight,
+            }
+        }
+
+        fn combine(&self, other: &Self) -> Self {
+            Weight {
+                sum: self.sum + other.sum,
+                max: self.max.max(other.max),
+            }
+        }
+    }
+
+    /// Computes the aggregate of the region directly from the elements.
+    fn expected(tree: &Octree<u8, DummyVolume<u8>, Weight>, aabb: &Aabb<u8>) -> Weight {
+        tree.iter()
+            .filter(|b| aabb.contains(&b.aabb.min))
+            .fold(Weight::empty(), |acc, b| {
+                acc.combine(&Weight::from_element(b))
+            })
+    }
+
+    #[test]
+    fn test_count() {
+        let aabb = Aabb::new_unchecked(TUVec3::splat(8), 8);
+        let mut tree: Octree<u8, TUVec3u8, Count> = Octree::from_aabb_with_aggregate(aabb);
+        let mut ids = Vec::new();
+        for i in 0..16 {
+            ids.push(tree.insert(TUVec3u8::new(i, i, 15 - i)).unwrap());
+        }
+        assert_eq!(tree.aggregate(), Count(16));
+
+        let half = Aabb::from_min_max(TUVec3::splat(0), TUVec3::new(8, 16, 16));
+        assert_eq!(tree.aggregate_in(&half), Count(8));
+
+        for id in ids.iter().take(10) {
+            tree.remove(*id).unwrap();
+        }
+        assert_eq!(tree.aggregate(), Count(6));
+        assert_eq!(tree.aggregate_in(&half), Count(0));
+
+        for id in ids.iter().skip(10) {
+            tree.remove(*id).unwrap();
+        }
+        assert_eq!(tree.aggregate(), Count(0));
+
+        tree.insert(TUVec3u8::new(3, 3, 3)).unwrap();
+        assert_eq!(tree.aggregate_in(&half), Count(1));
+
+        tree.clear();
+        assert_eq!(tree.aggregate(), Count(0));
+    }
+
+    #[test]
+    fn test_volumes() {
+        let aabb = Aabb::new_unchecked(TUVec3::splat(8), 8);
+        let mut tree: Octree<u8, DummyVolume<u8>, Weight> = Octree::from_aabb_with_aggregate(aabb);
+
+        let big = tree.insert(block(0, 4, 10)).unwrap();
+        tree.insert(block(5, 3, 7)).unwrap();
+        tree.insert(block(4, 1, 3)).unwrap();
+        tree.insert(block(12, 2, 5)).unwrap();
+        tree.insert(block(8, 1, 1)).unwrap();
+
+        assert_eq!(tree.aggregate(), Weight { sum: 26, max: 10 });
+
+        let regions = [
+            Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(16)),
+            Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(8)),
+            Aabb::from_min_max(TUVec3::splat(1), TUVec3::splat(5)),
+            Aabb::from_min_max(TUVec3::splat(5), TUVec3::splat(13)),
+            Aabb::from_min_max(TUVec3::new(4, 0, 0), TUVec3::new(16, 16, 6)),
+        ];
+        for region in regions.iter() {
+            assert_eq!(tree.aggregate_in(region), expected(&tree, region));
+        }
+
+        tree.remove(big).unwrap();
+        assert_eq!(tree.aggregate(), Weight { sum: 16, max: 7 });
+        for region in regions.iter() {
+            assert_eq!(tree.aggregate_in(region), expected(&tree, region));
+        }
+
+        let id = tree.find(&TUVec3::splat(12)).unwrap();
+        tree.get_element_mut(id).unwrap().weight = 20;
+        tree.refresh_aggregate(id).unwrap();
+        assert_eq!(tree.aggregate(), Weight { sum: 31, max: 20 });
+
+        tree.iter_mut().for_each(|b| b.weight = 1);
+        tree.refresh_aggregates();
+        assert_eq!(tree.aggregate(), Weight { sum: 4, max: 1 });
+    }
+
+    #[test]
+    fn test_outside_min() {
+        let aabb = Aabb::new_unchecked(TUVec3::splat(24), 8);
+        let mut tree: Octree<u8, DummyVolume<u8>, Weight> = Octree::from_aabb_with_aggregate(aabb);
+        let id = tree.insert(block(10, 10, 3)).unwrap();
+        tree.insert(block(24, 2, 1)).unwrap();
+
+        assert_eq!(tree.aggregate(), Weight { sum: 4, max: 3 });
+        assert_eq!(tree.aggregate_in(&aabb), Weight { sum: 4, max: 3 });
+        let corner = Aabb::from_min_max(TUVec3::splat(16), TUVec3::splat(17));
+        assert_eq!(tree.aggregate_in(&corner), Weight { sum: 3, max: 3 });
+
+        tree.get_element_mut(id).unwrap().weight = 5;
+        tree.refresh_aggregate(id).unwrap();
+        assert_eq!(tree.aggregate(), Weight { sum: 6, max: 5 });
+
+        tree.remove(id).unwrap();
+        assert_eq!(tree.aggregate(), Weight { sum: 1, max: 1 });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_recompute() {
+        let aabb = Aabb::new_unchecked(TUVec3::splat(8), 8);
+        let mut tree: Octree<u8, TUVec3u8, Count> = Octree::from_aabb_with_aggregate(aabb);
+        for i in 0..8 {
+            tree.insert(TUVec3u8::new(i * 2, i, 3)).unwrap();
+        }
+
+        let json = serde_json::to_string(&tree).unwrap();
+        let de: Octree<u8, TUVec3u8, Count> = serde_json::from_str(&json).unwrap();
+        assert_eq!(de.aggregate(), Count(8));
+    }
+}
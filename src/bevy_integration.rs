@@ -65,16 +65,18 @@ use heapless::Vec as HVec;
 use num::cast;
 
 use crate::{
+    aggregate::Aggregate,
     bounding::{Aabb, TUVec3, Unsigned},
     node::NodeType,
     tree::Octree,
     ElementId, NodeId, Volume,
 };
 
-impl<U, T> Octree<U, T>
+impl<U, T, A> Octree<U, T, A>
 where
     U: Unsigned,
     T: Volume<U = U>,
+    A: Aggregate<T>,
 {
     /// Intersects an [`Octree`] with the [`RayCast3d`].
     ///
@@ -119,26 +121,29 @@ where
                 match n.ntype {
                     NodeType::Empty => (),
 
-                    NodeType::Leaf(element) => {
-                        let aabb = self.elements[element].volume().into();
-                        if let Some(dist) = ray.aabb_intersection_at(&aabb) {
-                            match hit.element {
-                                Some(_) => {
-                                    if hit.distance > dist {
+                    NodeType::Leaf(_) | NodeType::Bucket => {
+                        for &element in self.leaf_elements(node) {
+                            let aabb = self.elements[element].volume().into();
+                            if let Some(dist) = ray.aabb_intersection_at(&aabb) {
+                                match hit.element {
+                                    Some(_) => {
+                                        if hit.distance > dist {
+                                            hit.element = Some(element);
+                                            hit.distance = dist;
+                                        }
+                                    }
+                                    None => {
                                         hit.element = Some(element);
                                         hit.distance = dist;
                                     }
                                 }
-                                None => {
-                                    hit.element = Some(element);
-                                    hit.distance = dist;
-                                }
                             }
                         }
                     }
 
                     NodeType::Branch(branch) => {
-                        let mut iter = branch.children.iter();
+                        let children = branch.children();
+                        let mut iter = children.iter();
                         while let Some(child) = iter.next() {
                             // If we can't push to the stack (to be processed on the next loop
                             // iteration) then we fallback to recursive calls.
@@ -201,18 +206,21 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(e) => {
-                    let aabb = self.elements[e].volume().into();
-                    if volume.intersects(&aabb) {
-                        elements.push(e);
-                    };
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &e in self.leaf_elements(node) {
+                        let aabb = self.elements[e].volume().into();
+                        if volume.intersects(&aabb) {
+                            elements.push(e);
+                        };
+                    }
                 }
 
                 NodeType::Branch(branch) => {
                     let aabb: Aabb3d = n.aabb.into();
 
                     if volume.intersects(&aabb) {
-                        let mut iter = branch.children.iter();
+                        let children = branch.children();
+                        let mut iter = children.iter();
                         while let Some(child) = iter.next() {
                             // If we can't push to the stack (to be processed on the next loop
                             // iteration) then we fallback to recursive calls.
@@ -269,10 +277,11 @@ impl<U: Unsigned> From<TUVec3<U>> for Vec3 {
     }
 }
 
-impl<U, T> IntersectsVolume<Aabb3d> for Octree<U, T>
+impl<U, T, A> IntersectsVolume<Aabb3d> for Octree<U, T, A>
 where
     U: Unsigned,
     T: Volume<U = U>,
+    A: Aggregate<T>,
 {
     /// Check if a [`Aabb3d`] volume intersects with the [`Octree`] root node.
     fn intersects(&self, volume: &Aabb3d) -> bool {
@@ -281,10 +290,11 @@ where
     }
 }
 
-impl<U, T> IntersectsVolume<BoundingSphere> for Octree<U, T>
+impl<U, T, A> IntersectsVolume<BoundingSphere> for Octree<U, T, A>
 where
     U: Unsigned,
     T: Volume<U = U>,
+    A: Aggregate<T>,
 {
     /// Check if a [`BoundingSphere`] volume intersects with the [`Octree`] root node.
     fn intersects(&self, volume: &BoundingSphere) -> bool {
@@ -300,20 +310,22 @@ where
     fn intersect(&self, volume: &Volume) -> Vec<ElementId>;
 }
 
-impl<U, T> IntersectVolume<Aabb3d, T> for Octree<U, T>
+impl<U, T, A> IntersectVolume<Aabb3d, T> for Octree<U, T, A>
 where
     U: Unsigned,
     T: Volume<U = U>,
+    A: Aggregate<T>,
 {
     fn intersect(&self, volume: &Aabb3d) -> Vec<ElementId> {
         self.intersect(volume)
     }
 }
 
-impl<U, T> IntersectVolume<BoundingSphere, T> for Octree<U, T>
+impl<U, T, A> IntersectVolume<BoundingSphere, T> for Octree<U, T, A>
 where
     U: Unsigned,
     T: Volume<U = U>,
+    A: Aggregate<T>,
 {
     fn intersect(&self, volume: &BoundingSphere) -> Vec<ElementId> {
         self.intersect(volume)
@@ -0,0 +1,860 @@
+//! Compact binary file format.
+//!
+//! [`Octree::write_to`] stores a tree and [`Octree::read_from`] restores it.
+//!
+//! All numbers are little endian. Counts and [`ElementId`]s are LEB128 varints.
+//!
+//! - Header: magic `OKTR`, format version (`u16`), minimal reader version (`u16`),
+//!   coordinate width in bytes (`u8`), root [`Aabb`] and CRC32 of the header.
+//! - Sections: tag (`u8`), payload length (`u64`), payload and CRC32 of the payload.
+//!   - [`NODES`](SECTION_NODES): node count and node types in pre-order,
+//!     packed by 2 bits: `0` - empty, `1` - leaf, `2` - branch, `3` - bucket.
+//!   - [`LEAVES`](SECTION_LEAVES): element ids of the leaves in pre-order.
+//!     Buckets store the count of their elements before the ids.
+//!   - [`ELEMENTS`](SECTION_ELEMENTS): element count and `(id, element)` pairs,
+//!     encoded by the user's [`Encode`] implementation.
+//!   - [`GARBAGE`](SECTION_GARBAGE): bitmap of the free element slots below the last stored id,
+//!     bit `i % 8` of byte `i / 8` is set for the free slot `i`. Omitted without free slots.
+//!   - [`LIMITS`](SECTION_LIMITS): max depth and min node size of the tree. Since version 2.
+//! - End tag `0`.
+//!
+//! Readers skip sections with unknown tags, so newer versions can add data
+//! without breaking older readers. Breaking changes raise the minimal reader version,
+//! trees with the buckets require version 2.
+//!
+//! [`ElementId`]s are preserved. Node ids and the garbage are not.
+
+use std::io::{Read, Write};
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, TUVec3, TUVec3u128, TUVec3u16, TUVec3u32, TUVec3u64, TUVec3u8, Unsigned},
+    node::{Branch, NodeType},
+    pool::{Pool, PoolItem},
+    tree::Octree,
+    ElementId, TreeError, Volume,
+};
+
+/// Version of the format written by this crate.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Oldest reader version, able to read the files written by this crate.
+///
+/// Files with the [`bucket`](NodeType::Bucket) nodes require the [`FORMAT_VERSION`].
+pub const COMPATIBLE_VERSION: u16 = 1;
+
+const MAGIC: &[u8; 4] = b"OKTR";
+
+const SECTION_END: u8 = 0;
+/// Tag of the node types section.
+pub const SECTION_NODES: u8 = 1;
+/// Tag of the leaf element ids section.
+pub const SECTION_LEAVES: u8 = 2;
+/// Tag of the elements section.
+pub const SECTION_ELEMENTS: u8 = 3;
+/// Tag of the tree limits section.
+pub const SECTION_LIMITS: u8 = 4;
+/// Tag of the free element slots section.
+pub const SECTION_GARBAGE: u8 = 5;
+
+const EMPTY: u8 = 0;
+const LEAF: u8 = 1;
+const BRANCH: u8 = 2;
+const BUCKET: u8 = 3;
+
+/// Serializes a value into the binary format.
+///
+/// Implemented for the unsigned integers, [`TUVec3`] and [`Aabb`].
+pub trait Encode {
+    /// Appends the encoded value to the `out` buffer.
+    fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// Deserializes a value from the binary format.
+///
+/// Implemented for the unsigned integers, [`TUVec3`] and [`Aabb`].
+///
+/// ```rust
+/// use oktree::{binary::{Decode, Encode}, prelude::*};
+///
+/// struct Voxel {
+///     position: TUVec3<u8>,
+///     color: u32,
+/// }
+///
+/// impl Encode for Voxel {
+///     fn encode(&self, out: &mut Vec<u8>) {
+///         self.position.encode(out);
+///         self.color.encode(out);
+///     }
+/// }
+///
+/// impl Decode for Voxel {
+///     fn decode(input: &mut &[u8]) -> Result<Self, TreeError> {
+///         Ok(Voxel {
+///             position: TUVec3::decode(input)?,
+///             color: u32::decode(input)?,
+///         })
+///     }
+/// }
+/// ```
+pub trait Decode: Sized {
+    /// Decodes a value from the front of the `input`, advancing it.
+    fn decode(input: &mut &[u8]) -> Result<Self, TreeError>;
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], TreeError> {
+    if input.len() < n {
+        return Err(TreeError::InvalidData(format!(
+            "Unexpected end of data. Expected {n} bytes, found {}",
+            input.len()
+        )));
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! impl_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(input: &mut &[u8]) -> Result<Self, TreeError> {
+                    let bytes = take(input, std::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(u8, u16, u32, u64, u128);
+
+/// `usize` is always stored as `u64`.
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+}
+
+impl Decode for usize {
+    fn decode(input: &mut &[u8]) -> Result<Self, TreeError> {
+        let value = u64::decode(input)?;
+        usize::try_from(value)
+            .map_err(|_| TreeError::InvalidData(format!("{value} doesn't fit into usize")))
+    }
+}
+
+impl<U: Unsigned + Encode> Encode for TUVec3<U> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.x.encode(out);
+        self.y.encode(out);
+        self.z.encode(out);
+    }
+}
+
+impl<U: Unsigned + Decode> Decode for TUVec3<U> {
+    fn decode(input: &mut &[u8]) -> Result<Self, TreeError> {
+        Ok(TUVec3::new(
+            U::decode(input)?,
+            U::decode(input)?,
+            U::decode(input)?,
+        ))
+    }
+}
+
+impl<U: Unsigned + Encode> Encode for Aabb<U> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.min.encode(out);
+        self.max.encode(out);
+    }
+}
+
+impl<U: Unsigned + Decode> Decode for Aabb<U> {
+    fn decode(input: &mut &[u8]) -> Result<Self, TreeError> {
+        Ok(Aabb::from_min_max(
+            TUVec3::decode(input)?,
+            TUVec3::decode(input)?,
+        ))
+    }
+}
+
+macro_rules! impl_tuvec {
+    ($($t:ident),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    self.0.encode(out);
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(input: &mut &[u8]) -> Result<Self, TreeError> {
+                    Ok($t(TUVec3::decode(input)?))
+                }
+            }
+        )*
+    };
+}
+
+impl_tuvec!(TUVec3u8, TUVec3u16, TUVec3u32, TUVec3u64, TUVec3u128);
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> Result<u64, TreeError> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = take(input, 1)?[0];
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(TreeError::InvalidData("Varint is too long".into()))
+}
+
+fn read_index(input: &mut &[u8]) -> Result<usize, TreeError> {
+    let value = read_varint(input)?;
+    usize::try_from(value)
+        .map_err(|_| TreeError::InvalidData(format!("{value} doesn't fit into usize")))
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC32 (IEEE) checksum.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Number of bytes used to store a single coordinate.
+pub(crate) fn coordinate_width<U: Unsigned + Decode>() -> u8 {
+    let mut bytes: &[u8] = &[0; 16];
+    let _ = U::decode(&mut bytes);
+    (16 - bytes.len()) as u8
+}
+
+fn io_error(err: std::io::Error) -> TreeError {
+    TreeError::Io(err.to_string())
+}
+
+fn write_section(writer: &mut impl Write, tag: u8, payload: &[u8]) -> Result<(), TreeError> {
+    writer.write_all(&[tag]).map_err(io_error)?;
+    writer
+        .write_all(&(payload.len() as u64).to_le_bytes())
+        .map_err(io_error)?;
+    writer.write_all(payload).map_err(io_error)?;
+    writer
+        .write_all(&crc32(payload).to_le_bytes())
+        .map_err(io_error)
+}
+
+fn read_exact<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], TreeError> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf).map_err(io_error)?;
+    Ok(buf)
+}
+
+fn verify(bytes: &[u8], checksum: u32, what: &str) -> Result<(), TreeError> {
+    if crc32(bytes) != checksum {
+        return Err(TreeError::InvalidData(format!("{what} checksum mismatch")));
+    }
+    Ok(())
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Writes the tree in the [`binary`](crate::binary) format.
+    ///
+    /// ```rust
+    /// use oktree::prelude::*;
+    ///
+    /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16u8).unwrap());
+    /// let c1_id = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+    ///
+    /// let mut bytes = Vec::new();
+    /// tree.write_to(&mut bytes).unwrap();
+    ///
+    /// let tree: Octree<u8, TUVec3u8> = Octree::read_from(bytes.as_slice()).unwrap();
+    /// assert_eq!(tree.find(&TUVec3::new(1, 1, 1)), Some(c1_id));
+    /// ```
+    pub fn write_to(&self, mut writer: impl Write) -> Result<(), TreeError>
+    where
+        U: Encode + Decode,
+        T: Encode,
+    {
+        let mut header = Vec::with_capacity(64);
+        header.extend_from_slice(MAGIC);
+        FORMAT_VERSION.encode(&mut header);
+        if self.buckets.is_empty() {
+            COMPATIBLE_VERSION.encode(&mut header);
+        } else {
+            FORMAT_VERSION.encode(&mut header);
+        }
+        coordinate_width::<U>().encode(&mut header);
+        self.nodes[self.root].aabb.encode(&mut header);
+        crc32(&header).encode(&mut header);
+        writer.write_all(&header).map_err(io_error)?;
+
+        let mut types = Vec::new();
+        let mut leaves = Vec::new();
+        let mut count = 0u64;
+        let mut stack = vec![self.root];
+        while let Some(node) = stack.pop() {
+            let code = match self.nodes[node].ntype {
+                NodeType::Empty => EMPTY,
+                NodeType::Leaf(e) => {
+                    write_varint(&mut leaves, usize::from(e) as u64);
+                    LEAF
+                }
+                NodeType::Bucket => {
+                    let bucket = self.bucket(node);
+                    write_varint(&mut leaves, bucket.len() as u64);
+                    for e in bucket {
+                        write_varint(&mut leaves, usize::from(*e) as u64);
+                    }
+                    BUCKET
+                }
+                NodeType::Branch(branch) => {
+                    stack.extend(branch.children().into_iter().rev());
+                    BRANCH
+                }
+            };
+
+            let bit = (count % 4) * 2;
+            if bit == 0 {
+                types.push(0);
+            }
+            *types.last_mut().unwrap() |= code << bit;
+            count += 1;
+        }
+
+        let mut nodes = Vec::with_capacity(types.len() + 10);
+        write_varint(&mut nodes, count);
+        nodes.extend_from_slice(&types);
+        write_section(&mut writer, SECTION_NODES, &nodes)?;
+
+        write_section(&mut writer, SECTION_LEAVES, &leaves)?;
+
+        let mut elements = Vec::new();
+        write_varint(&mut elements, self.elements.len() as u64);
+        for (e, element) in self.elements.iter_elements() {
+            write_varint(&mut elements, usize::from(e) as u64);
+            element.encode(&mut elements);
+        }
+        write_section(&mut writer, SECTION_ELEMENTS, &elements)?;
+
+        // Free slots are listed explicitly, so the reader allocates no more slots,
+        // than the file pays for.
+        let slots = self
+            .elements
+            .iter_elements()
+            .next_back()
+            .map_or(0, |(e, _)| usize::from(e) + 1);
+        if self.elements.len() < slots {
+            let mut garbage = vec![0u8; slots.div_ceil(8)];
+            for slot in (0..slots).filter(|&slot| self.elements.is_garbage(ElementId::from(slot))) {
+                garbage[slot / 8] |= 1 << (slot % 8);
+            }
+            write_section(&mut writer, SECTION_GARBAGE, &garbage)?;
+        }
+
+        let mut limits = Vec::new();
+        write_varint(&mut limits, self.max_depth as u64);
+        self.min_size.encode(&mut limits);
+        write_section(&mut writer, SECTION_LIMITS, &limits)?;
+
+        writer.write_all(&[SECTION_END]).map_err(io_error)?;
+        writer.flush().map_err(io_error)
+    }
+
+    /// Reads a tree, written by [`write_to`](Octree::write_to).
+    ///
+    /// Fails with [`TreeError::InvalidData`] if the data is corrupted
+    /// or requires a newer version of the reader.
+    pub fn read_from(mut reader: impl Read) -> Result<Self, TreeError>
+    where
+        U: Encode + Decode,
+        T: Decode,
+    {
+        let mut header = read_exact::<9>(&mut reader)?.to_vec();
+        if &header[..4] != MAGIC {
+            return Err(TreeError::InvalidData("Not an octree file".into()));
+        }
+
+        let mut fields = &header[4..];
+        let _version = u16::decode(&mut fields)?;
+        let compatible = u16::decode(&mut fields)?;
+        let width = u8::decode(&mut fields)?;
+
+        if compatible > FORMAT_VERSION {
+            return Err(TreeError::InvalidData(format!(
+                "File requires reader version {compatible}, this reader supports {FORMAT_VERSION}"
+            )));
+        }
+
+        if width != coordinate_width::<U>() {
+            return Err(TreeError::InvalidData(format!(
+                "File coordinates are {width} bytes, expected {}",
+                coordinate_width::<U>()
+            )));
+        }
+
+        let mut aabb = vec![0; width as usize * 6];
+        reader.read_exact(&mut aabb).map_err(io_error)?;
+        header.extend_from_slice(&aabb);
+        verify(
+            &header,
+            u32::from_le_bytes(read_exact(&mut reader)?),
+            "Header",
+        )?;
+        let aabb = Aabb::decode(&mut aabb.as_slice())?;
+
+        if !aabb.min.lt(&aabb.max).all() {
+            return Err(TreeError::InvalidData(format!("Root {aabb} is empty")));
+        }
+
+        let size: U = aabb.size();
+        if aabb.max.y - aabb.min.y != size
+            || aabb.max.z - aabb.min.z != size
+            || !(size & size.saturating_sub(U::one())).is_zero()
+        {
+            return Err(TreeError::InvalidData(format!(
+                "Root {aabb} is not a power of 2 cube"
+            )));
+        }
+
+        let mut nodes = None;
+        let mut leaves = None;
+        let mut elements = None;
+        let mut limits = None;
+        let mut garbage = Vec::new();
+        loop {
+            let [tag] = read_exact(&mut reader)?;
+            if tag == SECTION_END {
+                break;
+            }
+
+            let len = u64::from_le_bytes(read_exact(&mut reader)?);
+            let mut payload = Vec::new();
+            (&mut reader)
+                .take(len)
+                .read_to_end(&mut payload)
+                .map_err(io_error)?;
+            if payload.len() as u64 != len {
+                return Err(TreeError::InvalidData(format!(
+                    "Section {tag} is truncated"
+                )));
+            }
+            verify(
+                &payload,
+                u32::from_le_bytes(read_exact(&mut reader)?),
+                "Section",
+            )?;
+
+            match tag {
+                SECTION_NODES => nodes = Some(payload),
+                SECTION_LEAVES => leaves = Some(payload),
+                SECTION_ELEMENTS => elements = Some(payload),
+                SECTION_LIMITS => limits = Some(payload),
+                SECTION_GARBAGE => garbage = payload,
+                _ => (), // Section of a newer version
+            }
+        }
+
+        let missing = |name: &str| TreeError::InvalidData(format!("Missing {name} section"));
+        let nodes = nodes.ok_or_else(|| missing("nodes"))?;
+        let leaves = leaves.ok_or_else(|| missing("leaves"))?;
+        let elements = elements.ok_or_else(|| missing("elements"))?;
+
+        let elements = read_elements(&mut elements.as_slice(), &garbage)?;
+        let mut tree = Octree {
+            aabb: Some(aabb),
+            nodes: Pool::from_aabb_with_capacity(aabb, 0, A::empty()),
+            elements,
+            ..Default::default()
+        };
+
+        if let Some(limits) = limits {
+            let mut limits = limits.as_slice();
+            tree.max_depth = usize::try_from(read_varint(&mut limits)?).unwrap_or(usize::MAX);
+            tree.min_size = U::decode(&mut limits)?;
+            if tree.min_size.is_zero() {
+                return Err(TreeError::InvalidData("Min node size is zero".into()));
+            }
+        }
+        tree.read_nodes(&mut nodes.as_slice(), &mut leaves.as_slice())?;
+        if let Err(mut violations) = tree.validate() {
+            return Err(violations.swap_remove(0));
+        }
+        tree.refresh_aggregates();
+
+        Ok(tree)
+    }
+
+    fn read_nodes(&mut self, nodes: &mut &[u8], leaves: &mut &[u8]) -> Result<(), TreeError> {
+        let count = read_index(nodes)?;
+        if nodes.len() != count.div_ceil(4) {
+            return Err(TreeError::InvalidData(format!(
+                "Expected {count} nodes, found {} bytes",
+                nodes.len()
+            )));
+        }
+        self.nodes.reserve(count);
+
+        let mut stored = vec![false; self.elements.vec.len()];
+        let mut stack = vec![self.root];
+        let mut i = 0;
+        while let Some(node) = stack.pop() {
+            if i == count {
+                return Err(TreeError::InvalidData("Node stream is truncated".into()));
+            }
+            let code = (nodes[i / 4] >> ((i % 4) * 2)) & 0b11;
+            i += 1;
+
+            match code {
+                EMPTY => (),
+
+                LEAF => {
+                    let e = ElementId::try_from_index(read_index(leaves)?)?;
+                    let aabb = self.nodes[node].aabb;
+                    match self.elements.get(e) {
+                        Some(element) if element.volume().overlaps(&aabb) => (),
+                        _ => {
+                            return Err(TreeError::InvalidData(format!(
+                                "Leaf points to an invalid {e}"
+                            )))
+                        }
+                    }
+                    stored[usize::from(e)] = true;
+                    self.nodes[node].ntype = NodeType::Leaf(e);
+                }
+
+                BUCKET => {
+                    let len = read_index(leaves)?;
+                    if len < 2 {
+                        return Err(TreeError::InvalidData(format!(
+                            "Bucket {node} has {len} elements"
+                        )));
+                    }
+
+                    let aabb = self.nodes[node].aabb;
+                    let mut bucket = Vec::with_capacity(len.min(leaves.len()));
+                    for _ in 0..len {
+                        let e = ElementId::try_from_index(read_index(leaves)?)?;
+                        match self.elements.get(e) {
+                            Some(element)
+                                if element.volume().overlaps(&aabb) && !bucket.contains(&e) => {}
+                            _ => {
+                                return Err(TreeError::InvalidData(format!(
+                                    "Bucket points to an invalid {e}"
+                                )))
+                            }
+                        }
+                        stored[usize::from(e)] = true;
+                        bucket.push(e);
+                    }
+                    self.buckets.insert(node, bucket);
+                    self.nodes[node].ntype = NodeType::Bucket;
+                }
+
+                BRANCH => {
+                    if self.nodes[node].aabb.unit() {
+                        return Err(TreeError::InvalidData(format!(
+                            "Unit node {node} can't be a branch"
+                        )));
+                    }
+                    let first_child = self.nodes.branch(node, A::empty())?;
+                    let branch = Branch::new(first_child);
+                    self.nodes[node].ntype = NodeType::Branch(branch);
+                    stack.extend(branch.children().into_iter().rev());
+                }
+
+                _ => return Err(TreeError::InvalidData(format!("Unknown node type {code}"))),
+            }
+        }
+
+        if i != count || !leaves.is_empty() {
+            return Err(TreeError::InvalidData(
+                "Node stream has trailing data".into(),
+            ));
+        }
+
+        if let Some((e, _)) = self
+            .elements
+            .iter_elements()
+            .find(|(e, _)| !stored[usize::from(*e)])
+        {
+            return Err(TreeError::InvalidData(format!(
+                "{e} is not stored in any leaf"
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Missing ids become the garbage, so the stored ids are preserved.
+///
+/// Every missing id has to be marked in the `garbage` bitmap,
+/// so crafted ids can't make the pool larger than 8 slots per byte of the input.
+fn read_elements<T: Decode>(input: &mut &[u8], garbage: &[u8]) -> Result<Pool<T>, TreeError> {
+    let count = read_index(input)?;
+    let mut elements: Pool<T> = Pool::new();
+    for _ in 0..count {
+        let index = read_index(input)?;
+        ElementId::try_from_index(index)?;
+        if index < elements.vec.len() {
+            return Err(TreeError::InvalidData(format!(
+                "Element ids are not ascending at {index}"
+            )));
+        }
+
+        while elements.vec.len() < index {
+            let slot = elements.vec.len();
+            if garbage
+                .get(slot / 8)
+                .is_none_or(|byte| byte & 1 << (slot % 8) == 0)
+            {
+                return Err(TreeError::InvalidData(format!(
+                    "Element id {slot} is neither stored nor free"
+                )));
+            }
+            elements.garbage.push(slot);
+            elements.vec.push(PoolItem::Empty);
+        }
+        elements.vec.push(PoolItem::Filled(T::decode(input)?));
+    }
+
+    if !input.is_empty() {
+        return Err(TreeError::InvalidData(
+            "Elements section has trailing data".into(),
+        ));
+    }
+
+    Ok(elements)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Tree = Octree<u16, TUVec3u16>;
+
+    fn tree() -> Tree {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(32), 32));
+        for i in 0..64 {
+            tree.insert(TUVec3u16::new(i, 63 - i, i / 2)).unwrap();
+        }
+        for i in (0..64).step_by(5) {
+            tree.remove(ElementId(i)).unwrap();
+        }
+        tree
+    }
+
+    fn bytes(tree: &Tree) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        tree.write_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_varint() {
+        let mut buf = Vec::new();
+        for value in [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX] {
+            buf.clear();
+            write_varint(&mut buf, value);
+            assert_eq!(read_varint(&mut buf.as_slice()), Ok(value));
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let tree = tree();
+        let restored = Tree::read_from(bytes(&tree).as_slice()).unwrap();
+
+        assert_eq!(restored.len(), tree.len());
+        assert_eq!(restored.nodes.len(), tree.nodes.len());
+        for (e, element) in tree.iter_elements() {
+            assert_eq!(restored.find(&element.0), Some(e));
+        }
+        for i in (0..64).step_by(5) {
+            assert_eq!(restored.find(&TUVec3::new(i, 63 - i, i / 2)), None);
+        }
+
+        let mut restored = restored;
+        assert_eq!(restored.insert(TUVec3u16::new(0, 63, 0)), Ok(ElementId(60)));
+        assert_eq!(restored.remove(ElementId(1)), Ok(()));
+
+        let tree = Tree::default();
+        let restored = Tree::read_from(bytes(&tree).as_slice()).unwrap();
+        assert!(restored.is_empty());
+    }
+
+    #[test]
+    fn test_buckets() {
+        let mut tree = Tree::from_aabb(Aabb::new_unchecked(TUVec3::splat(32), 32))
+            .with_max_depth(2)
+            .with_min_size(2);
+        for i in 0..64 {
+            tree.insert(TUVec3u16::new(i, 63 - i, i / 2)).unwrap();
+        }
+        tree.remove(ElementId(7)).unwrap();
+
+        let buf = bytes(&tree);
+        // Buckets are unknown to the version 1 readers
+        assert_eq!(buf[6], FORMAT_VERSION as u8);
+
+        let restored = Tree::read_from(buf.as_slice()).unwrap();
+        assert_eq!(restored.max_depth(), 2);
+        assert_eq!(restored.min_size(), 2);
+        let mut buckets: Vec<_> = tree.buckets.values().collect();
+        let mut restored_buckets: Vec<_> = restored.buckets.values().collect();
+        buckets.sort();
+        restored_buckets.sort();
+        assert_eq!(restored_buckets, buckets);
+        for (e, element) in tree.iter_elements() {
+            assert_eq!(restored.find(&element.0), Some(e));
+        }
+
+        assert_eq!(bytes(&self::tree())[6], COMPATIBLE_VERSION as u8);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let bytes = bytes(&tree());
+
+        let mut corrupted = bytes.clone();
+        let last = corrupted.len() - 10;
+        corrupted[last] ^= 1;
+        assert_eq!(
+            Tree::read_from(corrupted.as_slice()).err(),
+            Some(TreeError::InvalidData("Section checksum mismatch".into()))
+        );
+
+        let mut corrupted = bytes.clone();
+        corrupted[12] ^= 1;
+        assert_eq!(
+            Tree::read_from(corrupted.as_slice()).err(),
+            Some(TreeError::InvalidData("Header checksum mismatch".into()))
+        );
+
+        assert!(Tree::read_from(&bytes[..bytes.len() / 2]).is_err());
+        assert!(matches!(
+            Tree::read_from(&bytes[..8]),
+            Err(TreeError::Io(_))
+        ));
+
+        assert!(matches!(
+            Octree::<u8, TUVec3u8>::read_from(bytes.as_slice()),
+            Err(TreeError::InvalidData(_))
+        ));
+
+        // Root is not a power of 2 cube
+        for max in [TUVec3::splat(48), TUVec3::new(32, 32, 64)] {
+            let tree = Tree::from_aabb(Aabb::from_min_max(TUVec3::zero(), max));
+            assert!(matches!(
+                Tree::read_from(self::bytes(&tree).as_slice()),
+                Err(TreeError::InvalidData(_))
+            ));
+        }
+
+        // Branch of empty nodes
+        let mut tree = Tree::from_aabb(Aabb::new_unchecked(TUVec3::splat(32), 32));
+        tree.insert(TUVec3u16::new(1, 1, 1)).unwrap();
+        tree.insert(TUVec3u16::new(63, 63, 63)).unwrap();
+        for node in tree.nodes.iter_mut() {
+            if let NodeType::Leaf(_) = node.ntype {
+                node.ntype = NodeType::Empty;
+            }
+        }
+        tree.elements.clear();
+        assert!(matches!(
+            Tree::read_from(self::bytes(&tree).as_slice()),
+            Err(TreeError::InvalidStructure(_))
+        ));
+    }
+
+    #[test]
+    fn test_element_gaps() {
+        let mut payload = Vec::new();
+        write_varint(&mut payload, 1);
+        write_varint(&mut payload, u16::MAX as u64);
+        TUVec3u16::new(1, 2, 3).encode(&mut payload);
+        assert_eq!(
+            read_elements::<TUVec3u16>(&mut payload.as_slice(), &[0xff]).err(),
+            Some(TreeError::InvalidData(
+                "Element id 8 is neither stored nor free".into()
+            ))
+        );
+
+        let tree = tree();
+        let restored = Tree::read_from(bytes(&tree).as_slice()).unwrap();
+        assert_eq!(restored.elements.garbage, tree.elements.garbage);
+    }
+
+    #[test]
+    fn test_versions() {
+        let bytes = bytes(&tree());
+
+        // Newer file with an unknown section
+        let mut newer = bytes.clone();
+        newer[4] = FORMAT_VERSION as u8 + 1;
+        let header = 9 + 12;
+        let checksum = crc32(&newer[..header]);
+        newer[header..header + 4].copy_from_slice(&checksum.to_le_bytes());
+        newer.pop();
+        write_section(&mut newer, 42, b"future").unwrap();
+        newer.push(SECTION_END);
+        assert!(Tree::read_from(newer.as_slice()).is_ok());
+
+        // Incompatible file
+        newer[6] = FORMAT_VERSION as u8 + 1;
+        let checksum = crc32(&newer[..header]);
+        newer[header..header + 4].copy_from_slice(&checksum.to_le_bytes());
+        assert!(matches!(
+            Tree::read_from(newer.as_slice()),
+            Err(TreeError::InvalidData(_))
+        ));
+    }
+}
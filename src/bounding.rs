@@ -44,6 +44,7 @@ impl Unsigned for usize {}
 /// Inner typy shuld be any [`Unsigned`](num::Unsigned):
 /// `u8`, `u16`, `u32`, `u64`, `u128`, `usize`.
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TUVec3<U: Unsigned> {
     pub x: U,
     pub y: U,
@@ -222,6 +223,7 @@ impl BVec3 {
 /// Inner type shuld be any [`Unsigned`](num::Unsigned):
 /// `u8`, `u16`, `u32`, `u64`, `u128`, `usize`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Aabb<U: Unsigned> {
     pub min: TUVec3<U>,
     pub max: TUVec3<U>,
@@ -327,7 +329,7 @@ impl<U: Unsigned> Aabb<U> {
         ]
     }
 
-    fn _split(&self, i: usize, center: TUVec3<U>) -> Aabb<U> {
+    pub(crate) fn _split(&self, i: usize, center: TUVec3<U>) -> Aabb<U> {
         let x_mask = (i & 0b1) != 0;
         let y_mask = (i & 0b10) != 0;
         let z_mask = (i & 0b100) != 0;
@@ -354,6 +356,18 @@ impl<U: Unsigned> Aabb<U> {
         lemin.all() && gtmax.all()
     }
 
+    /// Min corner of the `volume` part, inside the aabb.
+    ///
+    /// Volumes, stored in several leaves, are attributed to the leaf containing this corner,
+    /// so the ones, sticking out of the aabb, are attributed to a leaf too.
+    pub(crate) fn clipped_min(&self, volume: &Aabb<U>) -> TUVec3<U> {
+        TUVec3::new(
+            volume.min.x.max(self.min.x),
+            volume.min.y.max(self.min.y),
+            volume.min.z.max(self.min.z),
+        )
+    }
+
     /// Checks if this volume overlaps with another [`Aabb`].
     pub fn overlaps(&self, other: &Aabb<U>) -> bool {
         self.max.x.min(other.max.x) > self.min.x.max(other.min.x)
@@ -433,6 +447,7 @@ mod tests {
 }
 
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TUVec3u8(pub TUVec3<u8>);
 impl TUVec3u8 {
     pub fn new(x: u8, y: u8, z: u8) -> Self {
@@ -447,6 +462,7 @@ impl Position for TUVec3u8 {
 }
 
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TUVec3u16(pub TUVec3<u16>);
 impl TUVec3u16 {
     pub fn new(x: u16, y: u16, z: u16) -> Self {
@@ -461,6 +477,7 @@ impl Position for TUVec3u16 {
 }
 
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TUVec3u32(pub TUVec3<u32>);
 impl TUVec3u32 {
     pub fn new(x: u32, y: u32, z: u32) -> Self {
@@ -475,6 +492,7 @@ impl Position for TUVec3u32 {
 }
 
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TUVec3u64(pub TUVec3<u64>);
 impl TUVec3u64 {
     pub fn new(x: u64, y: u64, z: u64) -> Self {
@@ -489,6 +507,7 @@ impl Position for TUVec3u64 {
 }
 
 #[derive(Default, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TUVec3u128(pub TUVec3<u128>);
 impl TUVec3u128 {
     pub fn new(x: u128, y: u128, z: u128) -> Self {
@@ -0,0 +1,625 @@
+//! [`CompactOctree`] implementation.
+//!
+//! Memory efficient octree layout, where a node is just a [`NodeType`]:
+//! a tag and an index. Nodes store neither their [`Aabb`] nor a parent link.
+//! Bounds are derived during the traversal, by splitting the root [`Aabb`]
+//! on the way down, and the path is kept on the traversal stack.
+//!
+//! For the `u64` and `u128` coordinates [`Aabb`] is the most of the [`Node`](crate::node::Node)
+//! memory, so [`CompactOctree`] is a few times smaller than an [`Octree`].
+//! The price is a slightly slower traversal.
+
+use std::collections::HashMap;
+
+use smallvec::SmallVec;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, TUVec3, Unsigned},
+    intersect_with::Visited,
+    node::{octant, walk_octants_exclusive, walk_octants_inclusive, Branch, NodeType},
+    pool::{Pool, PoolElementIterator, PoolIterator, PoolIteratorMut},
+    tree::{leaf_elements, Octree},
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Octree with implicit node bounds.
+///
+/// Supports the same core operations as [`Octree`]:
+/// insertion, removing, searching and intersection with a custom closure.
+///
+/// ```rust
+/// use oktree::prelude::*;
+///
+/// let mut tree = CompactOctree::from_aabb(Aabb::new(TUVec3::splat(16), 16u64).unwrap());
+/// let c1_id = tree.insert(TUVec3u64::new(1, 1, 1)).unwrap();
+/// let c2_id = tree.insert(TUVec3u64::new(8, 8, 8)).unwrap();
+///
+/// assert_eq!(tree.find(&TUVec3::new(8, 8, 8)), Some(c2_id));
+/// assert!(tree.remove(c1_id).is_ok());
+/// assert_eq!(tree.find(&TUVec3::new(1, 1, 1)), None);
+/// ```
+#[derive(Clone)]
+pub struct CompactOctree<U, T>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+{
+    /// [`Aabb`] of the root node. All the other bounds are derived from it.
+    aabb: Aabb<U>,
+
+    /// [`Pool`] of stored elements. Access it by [`ElementId`]
+    pub(crate) elements: Pool<T>,
+
+    /// [`Pool`] of tree nodes. Access it by [`NodeId`]
+    pub(crate) nodes: Pool<NodeType, NodeId>,
+
+    pub(crate) root: NodeId,
+
+    /// Elements of the [`bucket`](NodeType::Bucket) nodes, converted from an [`Octree`]
+    pub(crate) buckets: HashMap<NodeId, Vec<ElementId>>,
+}
+
+impl<U, T> CompactOctree<U, T>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+{
+    /// Construct a tree from [`Aabb`].
+    ///
+    /// `aabb` should be positive and it's dimensions should be the power of 2.
+    /// The root node will adopt aabb's dimensions.
+    pub fn from_aabb(aabb: Aabb<U>) -> Self {
+        Self::from_aabb_with_capacity(aabb, 0)
+    }
+
+    /// Construct a tree from [`Aabb`] and capacity.
+    ///
+    /// `aabb` should be positive and it's dimensions should be the power of 2.
+    /// Helps to reduce the amount of the memory reallocations.
+    /// The root node will adopt aabb's dimensions.
+    pub fn from_aabb_with_capacity(aabb: Aabb<U>, capacity: usize) -> Self {
+        let mut nodes = Pool::with_capacity(capacity);
+        let root = nodes.insert(NodeType::Empty);
+        CompactOctree {
+            aabb,
+            elements: Pool::with_capacity(capacity),
+            nodes,
+            root,
+            buckets: Default::default(),
+        }
+    }
+
+    /// Returns the [`Aabb`] of the root node.
+    pub fn aabb(&self) -> Aabb<U> {
+        self.aabb
+    }
+
+    /// Insert an element into a tree.
+    ///
+    /// Recursively subdivide the space, creating new nodes.
+    /// Returns inserted element's [`id`](ElementId)
+    pub fn insert(&mut self, elem: T) -> Result<ElementId, TreeError> {
+        let volume = elem.volume();
+        if !self.aabb.overlaps(&volume) {
+            return Err(TreeError::OutOfTreeBounds(format!(
+                "{volume} is outside of aabb: min: {} max: {}",
+                self.aabb.min, self.aabb.max,
+            )));
+        }
+
+        let element = self.elements.try_insert(elem)?;
+
+        let mut insertions: SmallVec<[Insertion<U>; 10]> = SmallVec::new();
+        insertions.push(Insertion {
+            element,
+            node: self.root,
+            aabb: self.aabb,
+            volume,
+        });
+
+        let mut was_inserted = false;
+        while let Some(insertion) = insertions.pop() {
+            match self._insert(insertion, &mut insertions) {
+                Ok(e) => was_inserted |= e == Some(element),
+                Err(err) => {
+                    // Some leaves may already point to the element. Removal clears them
+                    // and collapses the branches left empty, before tombstoning it.
+                    let _ = self.remove(element);
+                    return Err(err);
+                }
+            }
+        }
+
+        if !was_inserted {
+            self.elements.tombstone(element);
+            return Err(TreeError::AlreadyOccupied(format!(
+                "Elements for volume: {} already exists",
+                volume
+            )));
+        }
+
+        Ok(element)
+    }
+
+    #[inline]
+    fn _insert<const C: usize>(
+        &mut self,
+        insertion: Insertion<U>,
+        insertions: &mut SmallVec<[Insertion<U>; C]>,
+    ) -> Result<Option<ElementId>, TreeError> {
+        let Insertion {
+            element,
+            node,
+            aabb,
+            volume,
+        } = insertion;
+
+        match self.nodes[node] {
+            NodeType::Empty => {
+                self.nodes[node] = NodeType::Leaf(element);
+                Ok(Some(element))
+            }
+
+            NodeType::Leaf(e) => {
+                if aabb.unit() {
+                    return Ok(None); // ignore
+                }
+
+                let e1 = self.elements[e].volume();
+                if e1.overlaps(&volume) {
+                    return Ok(None);
+                }
+
+                let first_child = self.nodes.try_insert_block(|_| NodeType::Empty)?;
+                self.nodes[node] = NodeType::Branch(Branch::new(first_child));
+
+                insertions.push(insertion);
+                insertions.push(Insertion {
+                    element: e,
+                    node,
+                    aabb,
+                    volume: e1,
+                });
+                Ok(None)
+            }
+
+            NodeType::Bucket => {
+                let elements = &self.elements;
+                let bucket = self.buckets.get_mut(&node).unwrap();
+                if bucket
+                    .iter()
+                    .any(|&e| elements[e].volume().overlaps(&volume))
+                {
+                    return Ok(None);
+                }
+
+                bucket.push(element);
+                Ok(Some(element))
+            }
+
+            NodeType::Branch(branch) => {
+                let center = aabb.center();
+                walk_octants_exclusive(center, &volume, |i| {
+                    insertions.push(Insertion {
+                        element,
+                        node: branch.child(i),
+                        aabb: aabb._split(i, center),
+                        volume,
+                    });
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove an element from the tree
+    ///
+    /// Empty branches are collapsed bottom up, following the traversal path.
+    /// Element is only marked as removed and could be reused.
+    pub fn remove(&mut self, element: ElementId) -> Result<(), TreeError> {
+        let Some(elem) = self.elements.get(element) else {
+            return Err(TreeError::ElementNotFound(format!(
+                "Element with id: {} not found",
+                element.0
+            )));
+        };
+
+        let volume = elem.volume();
+        if !self.aabb.overlaps(&volume) {
+            return Err(TreeError::OutOfTreeBounds(format!(
+                "{volume} is outside of aabb: min: {} max: {}",
+                self.aabb.min, self.aabb.max,
+            )));
+        }
+
+        // Branches are visited in pre-order, so walking them back
+        // collapses the children before their parents.
+        let mut branches: SmallVec<[NodeId; 32]> = SmallVec::new();
+        let mut stack: SmallVec<[(NodeId, Aabb<U>); 32]> = SmallVec::new();
+        stack.push((self.root, self.aabb));
+        while let Some((node, aabb)) = stack.pop() {
+            match self.nodes[node] {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(e) if e == element => self.nodes[node] = NodeType::Empty,
+
+                NodeType::Leaf(_) => (),
+
+                NodeType::Bucket => {
+                    let bucket = self.buckets.get_mut(&node).unwrap();
+                    bucket.retain(|&e| e != element);
+                    if let [last] = bucket[..] {
+                        self.buckets.remove(&node);
+                        self.nodes[node] = NodeType::Leaf(last);
+                    }
+                }
+
+                NodeType::Branch(branch) => {
+                    branches.push(node);
+                    let center = aabb.center();
+                    walk_octants_inclusive(center, &volume, |i| {
+                        stack.push((branch.child(i), aabb._split(i, center)));
+                    });
+                }
+            }
+        }
+
+        for node in branches.into_iter().rev() {
+            if let NodeType::Branch(branch) = self.nodes[node] {
+                if branch
+                    .children()
+                    .iter()
+                    .all(|&child| self.nodes[child] == NodeType::Empty)
+                {
+                    self.nodes.tombstone_block(branch.first_child);
+                    self.nodes[node] = NodeType::Empty;
+                }
+            }
+        }
+
+        self.elements.tombstone(element);
+        Ok(())
+    }
+
+    /// Clear all the elements in the tree and reset it to the initial state.
+    ///
+    /// The capacity of the tree is preserved.
+    pub fn clear(&mut self) {
+        self.elements.clear();
+        self.buckets.clear();
+        self.nodes.clear();
+        self.root = self.nodes.insert(NodeType::Empty);
+    }
+
+    /// Search for the element at the [`point`](TUVec3)
+    ///
+    /// Returns element's [`id`](ElementId) or [`None`] if elements if not found.
+    pub fn find(&self, point: &TUVec3<U>) -> Option<ElementId> {
+        if !self.aabb.contains(point) {
+            return None;
+        }
+
+        let mut node = self.root;
+        let mut aabb = self.aabb;
+        loop {
+            return match self.nodes[node] {
+                NodeType::Empty => None,
+
+                NodeType::Leaf(e) => {
+                    if self.elements[e].volume().contains(point) {
+                        Some(e)
+                    } else {
+                        None
+                    }
+                }
+
+                NodeType::Bucket => self.buckets[&node]
+                    .iter()
+                    .copied()
+                    .find(|&e| self.elements[e].volume().contains(point)),
+
+                NodeType::Branch(branch) => {
+                    let center = aabb.center();
+                    let i = octant(point, center);
+                    node = branch.child(i);
+                    aabb = aabb._split(i, center);
+                    continue;
+                }
+            };
+        }
+    }
+
+    /// Intersect [`CompactOctree`] with a custom intersection closure.
+    ///
+    /// Returns the [`vector`](Vec) of [`elements`](ElementId),
+    /// intersected by volume.
+    pub fn intersect_with<F>(&self, what: F) -> Vec<ElementId>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+    {
+        let mut elements = Vec::with_capacity(10);
+        self.intersect_with_for_each_with_ids(what, |e, _| elements.push(e));
+        elements
+    }
+
+    /// Intersect [`CompactOctree`] with a custom intersection closure.
+    /// Each element that intersects with the volume is passed to the supplied closure once,
+    /// even if it's stored in several leaves.
+    pub fn intersect_with_for_each_with_ids<F, F2>(&self, what: F, mut actor: F2)
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(ElementId, &T),
+    {
+        let mut visited = Visited::default();
+        let mut stack: SmallVec<[(NodeId, Aabb<U>); 32]> = SmallVec::new();
+        stack.push((self.root, self.aabb));
+        while let Some((node, aabb)) = stack.pop() {
+            let ntype = self.nodes[node];
+            match ntype {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &id in leaf_elements(&self.buckets, node, &ntype) {
+                        let e = &self.elements[id];
+                        let volume = e.volume();
+                        if what(&volume) && visited.first(&aabb, &volume, id) {
+                            actor(id, e);
+                        }
+                    }
+                }
+
+                NodeType::Branch(branch) => {
+                    if what(&aabb) {
+                        let center = aabb.center();
+                        for i in 0..8 {
+                            stack.push((branch.child(i), aabb._split(i, center)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the element if element exists and not garbaged.
+    pub fn get_element(&self, element: ElementId) -> Option<&T> {
+        self.elements.get(element)
+    }
+
+    /// Returns the element if element exists and not garbaged.
+    pub fn get_element_mut(&mut self, element: ElementId) -> Option<&mut T> {
+        self.elements.get_mut(element)
+    }
+
+    /// Returns the number of actual elements in the tree
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    #[inline(always)]
+    /// Is the tree empty
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Returns an iterator over the elements in the tree.
+    pub fn iter(&self) -> PoolIterator<'_, T> {
+        self.elements.iter()
+    }
+
+    /// Returns an mutable iterator over the elements in the tree.
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<'_, T> {
+        self.elements.iter_mut()
+    }
+
+    /// Returns an iterator over the elements in the tree and their ids.
+    pub fn iter_elements(&self) -> PoolElementIterator<'_, T> {
+        self.elements.iter_elements()
+    }
+}
+
+/// Converts an [`Octree`] into a [`CompactOctree`] with the same structure.
+///
+/// [`Element ids`](ElementId) are preserved.
+impl<U, T, A> From<Octree<U, T, A>> for CompactOctree<U, T>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    fn from(mut tree: Octree<U, T, A>) -> Self {
+        let aabb = tree.nodes[tree.root].aabb;
+        let mut compact = CompactOctree::from_aabb_with_capacity(aabb, tree.nodes.len());
+        compact.elements = tree.elements;
+
+        let mut stack: SmallVec<[(NodeId, NodeId); 32]> = SmallVec::new();
+        stack.push((tree.root, compact.root));
+        while let Some((from, to)) = stack.pop() {
+            compact.nodes[to] = match tree.nodes[from].ntype {
+                NodeType::Branch(branch) => {
+                    // The source tree already has at least as many nodes,
+                    // so the ids can't overflow.
+                    let first_child = compact
+                        .nodes
+                        .try_insert_block(|_| NodeType::Empty)
+                        .expect("node id overflow");
+                    let children = Branch::new(first_child);
+                    for i in 0..8 {
+                        stack.push((branch.child(i), children.child(i)));
+                    }
+                    NodeType::Branch(children)
+                }
+                NodeType::Bucket => {
+                    let bucket = tree.buckets.remove(&from).unwrap_or_default();
+                    compact.buckets.insert(to, bucket);
+                    NodeType::Bucket
+                }
+                ntype => ntype,
+            };
+        }
+
+        compact
+    }
+}
+
+impl<U: Unsigned, T: Volume<U = U>> std::fmt::Debug for CompactOctree<U, T>
+where
+    T: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CompactOctree")
+            .field("aabb", &self.aabb)
+            .field("elements", &self.elements)
+            .field("nodes", &self.nodes)
+            .field("root", &self.root)
+            .field("buckets", &self.buckets)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct Insertion<U: Unsigned> {
+    element: ElementId,
+    node: NodeId,
+    aabb: Aabb<U>,
+    volume: Aabb<U>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bounding::TUVec3u16, node::Node, tests::DummyVolume};
+
+    #[test]
+    fn test_memory() {
+        assert!(
+            std::mem::size_of::<NodeType>() * 4 <= std::mem::size_of::<Node<u64>>(),
+            "compact node should be at least 4 times smaller"
+        );
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut tree = CompactOctree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8u16), 8));
+
+        let c1 = tree.insert(TUVec3u16::new(1, 1, 1)).unwrap();
+        let c2 = tree.insert(TUVec3u16::new(2, 2, 2)).unwrap();
+        assert!(tree.insert(TUVec3u16::new(1, 1, 1)).is_err());
+        assert!(tree.insert(TUVec3u16::new(16, 1, 1)).is_err());
+        assert_eq!(tree.nodes.len(), 25);
+
+        assert_eq!(tree.find(&TUVec3::new(1, 1, 1)), Some(c1));
+        assert_eq!(tree.find(&TUVec3::new(2, 2, 2)), Some(c2));
+        assert_eq!(tree.find(&TUVec3::new(3, 3, 3)), None);
+        assert_eq!(tree.find(&TUVec3::new(16, 3, 3)), None);
+
+        assert_eq!(tree.remove(c1), Ok(()));
+        assert_eq!(tree.nodes.len(), 25);
+        assert_eq!(tree.find(&TUVec3::new(1, 1, 1)), None);
+
+        assert_eq!(tree.remove(c2), Ok(()));
+        assert!(tree.remove(c2).is_err());
+        assert_eq!(tree.nodes.len(), 1);
+        assert_eq!(tree.nodes[tree.root], NodeType::Empty);
+        assert!(tree.is_empty());
+
+        let c3 = tree.insert(TUVec3u16::new(15, 15, 15)).unwrap();
+        let c4 = tree.insert(TUVec3u16::new(14, 14, 14)).unwrap();
+        // All three freed blocks are reused, the fourth one is appended
+        assert_eq!(tree.nodes.len(), 33);
+        assert_eq!(tree.nodes.garbage_len(), 0);
+        assert_eq!(tree.find(&TUVec3::new(15, 15, 15)), Some(c3));
+        assert_eq!(tree.find(&TUVec3::new(14, 14, 14)), Some(c4));
+    }
+
+    #[test]
+    fn test_volumes() {
+        let mut tree = CompactOctree::from_aabb(Aabb::new_unchecked(TUVec3::splat(16u16), 16));
+
+        let v1 = DummyVolume::new(Aabb::new_unchecked(TUVec3::new(13, 13, 13), 3));
+        let v2 = DummyVolume::new(Aabb::new_unchecked(TUVec3::new(19, 13, 13), 3));
+        let v1 = tree.insert(v1).unwrap();
+        let v2 = tree.insert(v2).unwrap();
+
+        assert_eq!(tree.find(&TUVec3::new(9, 13, 13)), None);
+        assert_eq!(tree.find(&TUVec3::new(10, 13, 13)), Some(v1));
+        assert_eq!(tree.find(&TUVec3::new(15, 13, 13)), Some(v1));
+        assert_eq!(tree.find(&TUVec3::new(16, 13, 13)), Some(v2));
+        assert_eq!(tree.find(&TUVec3::new(21, 13, 13)), Some(v2));
+        assert_eq!(tree.find(&TUVec3::new(22, 13, 13)), None);
+
+        let area = Aabb::from_min_max(TUVec3::new(10, 13, 13), TUVec3::new(12, 14, 14));
+        let hits = tree.intersect_with(|aabb| area.overlaps(aabb));
+        assert_eq!(hits, vec![v1]);
+
+        assert_eq!(tree.remove(v1), Ok(()));
+        assert_eq!(tree.remove(v2), Ok(()));
+        assert_eq!(tree.nodes.len(), 1);
+    }
+
+    #[test]
+    fn test_from_octree() {
+        let mut octree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8u16), 8));
+        for i in 0..16 {
+            octree.insert(TUVec3u16::new(i, 15 - i, i / 2)).unwrap();
+        }
+        octree.remove(ElementId(3)).unwrap();
+        let nodes = octree.nodes.len();
+
+        let tree = CompactOctree::from(octree);
+        assert_eq!(tree.nodes.len(), nodes);
+        assert_eq!(tree.len(), 15);
+        for i in 0..16 {
+            let found = tree.find(&TUVec3::new(i, 15 - i, i / 2));
+            if i == 3 {
+                assert_eq!(found, None);
+            } else {
+                assert_eq!(found, Some(ElementId::from(usize::from(i))));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "index-u16", not(feature = "index-u64")))]
+    fn test_index_overflow_rollback() {
+        use rand::Rng;
+
+        const HALF: u32 = 32768;
+        let mut tree = CompactOctree::from_aabb(Aabb::new_unchecked(TUVec3::splat(HALF), HALF));
+        let corner = Aabb::from_min_max(TUVec3::splat(HALF - 1), TUVec3::splat(HALF));
+        tree.insert(DummyVolume::new(corner)).unwrap();
+
+        // Fill the nodes far from the corner, until no block is left
+        let mut rnd = rand::thread_rng();
+        loop {
+            let min = TUVec3::new(
+                rnd.gen_range(0..HALF / 2),
+                rnd.gen_range(0..HALF / 2),
+                rnd.gen_range(0..HALF / 2),
+            );
+            let volume = DummyVolume::new(Aabb::new_unchecked(min, 1));
+            if let Err(TreeError::IndexOverflow(_)) = tree.insert(volume) {
+                if tree.nodes.garbage_len() == 0 {
+                    break;
+                }
+            }
+        }
+
+        // Empty octant is filled first, then splitting the corner leaf overflows
+        let volume = Aabb::from_min_max(
+            TUVec3::splat(HALF - 8),
+            TUVec3::new(HALF - 1, HALF - 1, HALF + 8),
+        );
+        assert!(matches!(
+            tree.insert(DummyVolume::new(volume)),
+            Err(TreeError::IndexOverflow(_))
+        ));
+        assert_eq!(tree.find(&TUVec3::new(HALF - 8, HALF - 8, HALF)), None);
+        for node in tree.nodes.iter() {
+            if let NodeType::Leaf(e) = node {
+                assert!(tree.get_element(*e).is_some());
+            }
+        }
+    }
+}
@@ -0,0 +1,417 @@
+//! Dense grid conversion.
+//!
+//! [`Octree::to_dense`] extracts a region of the tree into a flat [`Dense`] array,
+//! [`Octree::write_dense`] and [`Octree::from_dense`] import it back.
+//!
+//! ```rust
+//! use oktree::{dense::Dense, prelude::*};
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! let c1_id = tree.insert(TUVec3u8::new(1, 2, 3)).unwrap();
+//!
+//! let region = Aabb::from_min_max(TUVec3::new(0, 0, 0), TUVec3::new(4, 4, 4));
+//! let dense = tree.to_dense(region);
+//! assert_eq!(dense[[1, 2, 3]], Some(c1_id));
+//! assert_eq!(dense.iter().flatten().count(), 1);
+//!
+//! let mut noise = Dense::new([2, 2, 2], false);
+//! noise[[1, 1, 0]] = true;
+//! tree.write_dense(TUVec3::new(4, 4, 4), &noise, |cell, &v| v.then_some(TUVec3u8(cell)))
+//!     .unwrap();
+//! assert!(tree.find(&TUVec3::new(5, 5, 4)).is_some());
+//! ```
+
+use std::ops::{Index, IndexMut};
+
+use heapless::Vec as HVec;
+use num::cast;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, TUVec3, Unsigned},
+    node::NodeType,
+    tree::Octree,
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Dense 3D array.
+///
+/// Values are stored in `x`-major order:
+/// `x` varies the slowest, `z` the fastest,
+/// so `[x, y, z]` is at `(x * size_y + y) * size_z + z`.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dense<V> {
+    size: [usize; 3],
+    data: Vec<V>,
+}
+
+impl<V: Clone> Dense<V> {
+    /// Creates an array of `size`, filled with the `value`.
+    pub fn new(size: [usize; 3], value: V) -> Self {
+        Dense {
+            size,
+            data: vec![value; size[0] * size[1] * size[2]],
+        }
+    }
+}
+
+impl<V> Dense<V> {
+    /// Wraps the `x`-major `data` of `size`.
+    ///
+    /// Returns [`TreeError::InvalidData`] if the `data` length doesn't match the `size`.
+    pub fn from_vec(size: [usize; 3], data: Vec<V>) -> Result<Self, TreeError> {
+        if data.len() != size[0] * size[1] * size[2] {
+            return Err(TreeError::InvalidData(format!(
+                "{} values don't fit the size {size:?}",
+                data.len()
+            )));
+        }
+        Ok(Dense { size, data })
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the flat index of the `[x, y, z]` cell.
+    ///
+    /// Returns [`None`] if the cell is out of the array.
+    #[inline]
+    pub fn index(&self, [x, y, z]: [usize; 3]) -> Option<usize> {
+        let [sx, sy, sz] = self.size;
+        (x < sx && y < sy && z < sz).then(|| (x * sy + y) * sz + z)
+    }
+
+    /// Returns the `[x, y, z]` cell of the flat `index`.
+    #[inline]
+    pub fn cell(&self, index: usize) -> [usize; 3] {
+        let [_, sy, sz] = self.size;
+        [index / (sy * sz), index / sz % sy, index % sz]
+    }
+
+    pub fn get(&self, cell: [usize; 3]) -> Option<&V> {
+        self.index(cell).map(|i| &self.data[i])
+    }
+
+    pub fn get_mut(&mut self, cell: [usize; 3]) -> Option<&mut V> {
+        self.index(cell).map(|i| &mut self.data[i])
+    }
+
+    pub fn as_slice(&self) -> &[V] {
+        &self.data
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [V] {
+        &mut self.data
+    }
+
+    pub fn into_vec(self) -> Vec<V> {
+        self.data
+    }
+
+    /// Returns an iterator over the values in `x`-major order.
+    pub fn iter(&self) -> std::slice::Iter<'_, V> {
+        self.data.iter()
+    }
+
+    /// Returns an iterator over the cells and their values in `x`-major order.
+    pub fn iter_cells(&self) -> impl Iterator<Item = ([usize; 3], &V)> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (self.cell(i), value))
+    }
+}
+
+impl<V> Index<[usize; 3]> for Dense<V> {
+    type Output = V;
+
+    fn index(&self, cell: [usize; 3]) -> &V {
+        match Dense::index(self, cell) {
+            Some(i) => &self.data[i],
+            None => panic!("Cell {cell:?} is out of the size {:?}", self.size),
+        }
+    }
+}
+
+impl<V> IndexMut<[usize; 3]> for Dense<V> {
+    fn index_mut(&mut self, cell: [usize; 3]) -> &mut V {
+        match Dense::index(self, cell) {
+            Some(i) => &mut self.data[i],
+            None => panic!("Cell {cell:?} is out of the size {:?}", self.size),
+        }
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Extracts the `aabb` region into a [`Dense`] array of [`elements`](ElementId).
+    ///
+    /// Cell `[x, y, z]` of the array corresponds to the `aabb.min + (x, y, z)` tree cell.
+    /// Only the nodes, overlapping the region, are visited,
+    /// empty nodes and the parts of the region outside of the tree are left as [`None`].
+    pub fn to_dense(&self, aabb: Aabb<U>) -> Dense<Option<ElementId>> {
+        let size = [
+            aabb.max.x.saturating_sub(aabb.min.x),
+            aabb.max.y.saturating_sub(aabb.min.y),
+            aabb.max.z.saturating_sub(aabb.min.z),
+        ]
+        .map(|s| cast::<U, usize>(s).unwrap());
+
+        let mut dense = Dense::new(size, None);
+        if !dense.is_empty() {
+            self.rto_dense(self.root, &aabb, &mut dense);
+        }
+        dense
+    }
+
+    fn rto_dense(&self, node: NodeId, region: &Aabb<U>, dense: &mut Dense<Option<ElementId>>) {
+        // Walk the nodes with a heapless stack. Children that don't fit
+        // into a full stack are extracted by a recursive call.
+        let mut stack = HVec::<_, 32>::new();
+        stack.push(node).unwrap();
+        while let Some(node) = stack.pop() {
+            let n = self.nodes[node];
+            if !n.aabb.overlaps(region) {
+                continue;
+            }
+
+            match n.ntype {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &e in self.leaf_elements(node) {
+                        let volume = self.elements[e].volume();
+                        if volume.overlaps(region) {
+                            fill(dense, region, &volume, e);
+                        }
+                    }
+                }
+
+                NodeType::Branch(branch) => {
+                    let children = branch.children();
+                    let mut iter = children.iter();
+                    while let Some(child) = iter.next() {
+                        // If we can't push to the stack (to be processed on the next loop
+                        // iteration) then we fallback to recursive calls.
+                        if stack.push(*child).is_err() {
+                            self.rto_dense(*child, region, dense);
+                            for child in iter.by_ref() {
+                                self.rto_dense(*child, region, dense);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes the `dense` array into the tree region, starting at `origin`.
+    ///
+    /// Returns [`TreeError::OutOfTreeBounds`] if the region doesn't fit into the tree.
+    /// Otherwise elements, overlapping the region, are removed first.
+    /// Then every cell value is converted by `new` and the resulting elements are inserted
+    /// in `x`-major order.
+    ///
+    /// Returns the number of inserted elements.
+    pub fn write_dense<V>(
+        &mut self,
+        origin: TUVec3<U>,
+        dense: &Dense<V>,
+        mut new: impl FnMut(TUVec3<U>, &V) -> Option<T>,
+    ) -> Result<usize, TreeError> {
+        let [sx, sy, sz] = dense.size().map(|s| {
+            cast::<usize, U>(s)
+                .ok_or_else(|| TreeError::OutOfTreeBounds(format!("Dense size {s} overflows")))
+        });
+        let size = TUVec3::new(sx?, sy?, sz?);
+
+        let aabb = self.nodes[self.root].aabb;
+        let fits = |o: U, s: U, min: U, max: U| o >= min && o <= max && s <= max - o;
+        if !(fits(origin.x, size.x, aabb.min.x, aabb.max.x)
+            && fits(origin.y, size.y, aabb.min.y, aabb.max.y)
+            && fits(origin.z, size.z, aabb.min.z, aabb.max.z))
+        {
+            return Err(TreeError::OutOfTreeBounds(format!(
+                "Dense region at {origin} of size {size} is outside of the tree {aabb}"
+            )));
+        }
+        let region = Aabb::from_min_max(origin, origin + size);
+
+        let mut overlapping = self.intersect_with(|aabb| aabb.overlaps(&region));
+        overlapping.sort_unstable();
+        overlapping.dedup();
+        for e in overlapping {
+            self.remove(e)?;
+        }
+
+        let mut inserted = 0;
+        for (cell, value) in dense.iter_cells() {
+            let [x, y, z] = cell.map(|c| cast::<usize, U>(c).unwrap());
+            let position = origin + TUVec3::new(x, y, z);
+            if let Some(element) = new(position, value) {
+                self.insert(element)?;
+                inserted += 1;
+            }
+        }
+
+        Ok(inserted)
+    }
+
+    /// Creates a tree of `aabb` from the `dense` array, placed at `origin`.
+    ///
+    /// See [`write_dense`](Self::write_dense).
+    pub fn from_dense<V>(
+        aabb: Aabb<U>,
+        origin: TUVec3<U>,
+        dense: &Dense<V>,
+        new: impl FnMut(TUVec3<U>, &V) -> Option<T>,
+    ) -> Result<Self, TreeError> {
+        let mut tree = Octree::from_aabb_with_aggregate(aabb);
+        tree.write_dense(origin, dense, new)?;
+        Ok(tree)
+    }
+}
+
+/// Fills the cells of `volume`, clipped by the `region`.
+fn fill<U: Unsigned>(
+    dense: &mut Dense<Option<ElementId>>,
+    region: &Aabb<U>,
+    volume: &Aabb<U>,
+    e: ElementId,
+) {
+    let lo = [
+        volume.min.x.max(region.min.x) - region.min.x,
+        volume.min.y.max(region.min.y) - region.min.y,
+        volume.min.z.max(region.min.z) - region.min.z,
+    ]
+    .map(|c| cast::<U, usize>(c).unwrap());
+    let hi = [
+        volume.max.x.min(region.max.x) - region.min.x,
+        volume.max.y.min(region.max.y) - region.min.y,
+        volume.max.z.min(region.max.z) - region.min.z,
+    ]
+    .map(|c| cast::<U, usize>(c).unwrap());
+
+    for x in lo[0]..hi[0] {
+        for y in lo[1]..hi[1] {
+            // Innermost axis is contiguous
+            let start = dense.index([x, y, lo[2]]).unwrap();
+            dense.data[start..start + hi[2] - lo[2]].fill(Some(e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::TUVec3u8;
+    use crate::tests::{empty_tree, DummyVolume};
+
+    #[test]
+    fn test_dense() {
+        let mut dense = Dense::new([2, 3, 4], 0);
+        assert_eq!(dense.len(), 24);
+        assert_eq!(dense.index([1, 2, 3]), Some(23));
+        assert_eq!(dense.index([0, 1, 0]), Some(4));
+        assert_eq!(dense.index([2, 0, 0]), None);
+        assert_eq!(dense.cell(23), [1, 2, 3]);
+        assert_eq!(dense.cell(13), [1, 0, 1]);
+
+        dense[[1, 0, 1]] = 5;
+        assert_eq!(dense.as_slice()[13], 5);
+        assert_eq!(dense.get([1, 0, 1]), Some(&5));
+        assert_eq!(dense.get([0, 3, 0]), None);
+
+        assert!(Dense::from_vec([2, 2, 2], vec![0; 7]).is_err());
+        let dense = Dense::from_vec([2, 2, 2], (0..8).collect()).unwrap();
+        assert_eq!(dense[[1, 1, 0]], 6);
+    }
+
+    #[test]
+    fn test_to_dense() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        let c1 = tree.insert(TUVec3u8::new(1, 2, 3)).unwrap();
+        let c2 = tree.insert(TUVec3u8::new(4, 4, 4)).unwrap();
+        tree.insert(TUVec3u8::new(12, 12, 12)).unwrap();
+
+        let region = Aabb::from_min_max(TUVec3::new(1, 1, 1), TUVec3::new(5, 5, 5));
+        let dense = tree.to_dense(region);
+        assert_eq!(dense.size(), [4, 4, 4]);
+        assert_eq!(dense[[0, 1, 2]], Some(c1));
+        assert_eq!(dense[[3, 3, 3]], Some(c2));
+        assert_eq!(dense.iter().flatten().count(), 2);
+
+        // Region outside of the tree
+        let region = Aabb::from_min_max(TUVec3::new(14, 14, 14), TUVec3::new(18, 18, 18));
+        let dense = tree.to_dense(region);
+        assert_eq!(dense.iter().flatten().count(), 0);
+
+        // Volumes are clipped by the region
+        let mut tree = empty_tree();
+        let block = tree
+            .insert(DummyVolume::new(Aabb::new_unchecked(TUVec3::splat(4), 2)))
+            .unwrap();
+        let region = Aabb::from_min_max(TUVec3::new(3, 0, 5), TUVec3::new(8, 8, 8));
+        let dense = tree.to_dense(region);
+        assert_eq!(dense.iter().flatten().count(), 3 * 4);
+        assert_eq!(dense[[0, 2, 0]], Some(block));
+        assert_eq!(dense[[2, 5, 0]], Some(block));
+        assert_eq!(dense[[2, 6, 0]], None);
+    }
+
+    #[test]
+    fn test_write_dense() {
+        let mut dense = Dense::new([4, 4, 4], 0u8);
+        dense[[0, 0, 0]] = 1;
+        dense[[3, 2, 1]] = 2;
+
+        let new = |cell, v: &u8| (*v > 0).then_some(TUVec3u8(cell));
+        let mut tree: Octree<u8, TUVec3u8> = Octree::from_dense(
+            Aabb::new_unchecked(TUVec3::splat(8), 8),
+            TUVec3::splat(2),
+            &dense,
+            new,
+        )
+        .unwrap();
+        assert_eq!(tree.len(), 2);
+        assert!(tree.find(&TUVec3::new(2, 2, 2)).is_some());
+        assert!(tree.find(&TUVec3::new(5, 4, 3)).is_some());
+
+        let outside = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+        let region = Aabb::from_min_max(TUVec3::splat(2), TUVec3::splat(6));
+        let extracted = tree
+            .to_dense(region)
+            .iter()
+            .map(Option::is_some)
+            .collect::<Vec<_>>();
+        assert_eq!(extracted, dense.iter().map(|v| *v > 0).collect::<Vec<_>>());
+
+        // Overwrites the region
+        let mut dense = Dense::new([4, 4, 4], 0u8);
+        dense[[1, 1, 1]] = 1;
+        assert_eq!(tree.write_dense(TUVec3::splat(2), &dense, new), Ok(1));
+        assert_eq!(tree.len(), 2);
+        assert!(tree.find(&TUVec3::new(2, 2, 2)).is_none());
+        assert!(tree.find(&TUVec3::new(3, 3, 3)).is_some());
+        assert_eq!(tree.find(&TUVec3::new(1, 1, 1)), Some(outside));
+
+        assert!(matches!(
+            tree.write_dense(TUVec3::splat(15), &dense, new),
+            Err(TreeError::OutOfTreeBounds(_))
+        ));
+        assert_eq!(tree.len(), 2);
+    }
+}
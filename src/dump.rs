@@ -0,0 +1,301 @@
+//! Graphviz `DOT` and `JSON` dumps of the tree structure.
+//!
+//! Both formats describe the node hierarchy only: node ids, depths,
+//! [`Aabb`]s, [`NodeType`]s and the [`ElementId`]s stored in the leaves.
+//! [`DumpOptions`] limit the dump to a depth or to a region.
+//!
+//! ```rust
+//! use oktree::{dump::DumpOptions, prelude::*};
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(14, 14, 14)).unwrap();
+//!
+//! let dot = tree.to_dot(&DumpOptions::default());
+//! assert!(dot.starts_with("digraph octree {"));
+//! assert!(dot.contains("n0 -> n8"));
+//!
+//! let json = tree.to_json(&DumpOptions::default().with_max_depth(0));
+//! assert!(json.contains(r#""truncated":true"#));
+//! ```
+//!
+//! Render the `DOT` output with `dot -Tsvg tree.dot -o tree.svg`.
+
+use std::{fmt::Write as _, io::Write};
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, TUVec3, Unsigned},
+    node::NodeType,
+    tree::Octree,
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Limits of the dumped part of the tree.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct DumpOptions<U: Unsigned> {
+    /// Children of the nodes at this depth are not dumped. Root has depth `0`.
+    pub max_depth: Option<usize>,
+
+    /// Only nodes, overlapping this region, are dumped.
+    pub region: Option<Aabb<U>>,
+}
+
+impl<U: Unsigned> DumpOptions<U> {
+    /// Dumps the nodes up to the `max_depth`.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Dumps only the nodes, overlapping the `region`.
+    pub fn with_region(mut self, region: Aabb<U>) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    fn includes(&self, aabb: &Aabb<U>) -> bool {
+        self.region.is_none_or(|region| region.overlaps(aabb))
+    }
+
+    fn truncates(&self, depth: usize) -> bool {
+        self.max_depth.is_some_and(|max_depth| depth >= max_depth)
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Returns the node hierarchy as a Graphviz `DOT` digraph.
+    ///
+    /// Every node is labelled with it's [`NodeId`], depth, [`Aabb`] and [`NodeType`].
+    /// Edges are labelled with the child's octant.
+    /// Branches, whose children are cut by the [`max_depth`](DumpOptions::max_depth),
+    /// are drawn dashed.
+    pub fn to_dot(&self, options: &DumpOptions<U>) -> String {
+        let mut dot = String::from("digraph octree {\n    node [shape=box, fontname=monospace];\n");
+
+        let mut stack = Vec::new();
+        if options.includes(&self.nodes[self.root].aabb) {
+            stack.push((self.root, 0));
+        }
+        while let Some((node, depth)) = stack.pop() {
+            let n = &self.nodes[node];
+            let truncated = matches!(n.ntype, NodeType::Branch(_)) && options.truncates(depth);
+
+            let _ = write!(
+                dot,
+                "    n{} [label=\"{node}\\ndepth {depth}\\n{}\\n{}\"",
+                usize::from(node),
+                n.aabb,
+                self.ntype_label(node, &n.ntype)
+            );
+            dot.push_str(if truncated {
+                ", style=dashed];\n"
+            } else {
+                "];\n"
+            });
+
+            if let (NodeType::Branch(branch), false) = (n.ntype, truncated) {
+                for (i, child) in branch.children().into_iter().enumerate().rev() {
+                    if options.includes(&self.nodes[child].aabb) {
+                        let _ = writeln!(
+                            dot,
+                            "    n{} -> n{} [label=\"{i}\"];",
+                            usize::from(node),
+                            usize::from(child)
+                        );
+                        stack.push((child, depth + 1));
+                    }
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Writes the [`DOT`](Self::to_dot) dump into the `writer`.
+    pub fn write_dot(
+        &self,
+        mut writer: impl Write,
+        options: &DumpOptions<U>,
+    ) -> Result<(), TreeError> {
+        writer
+            .write_all(self.to_dot(options).as_bytes())
+            .map_err(|err| TreeError::Io(err.to_string()))
+    }
+
+    /// Returns the node hierarchy as a nested `JSON` object.
+    ///
+    /// Every node is an object with `id`, `depth`, `aabb` (`min` and `max` arrays) and `type`
+    /// (`"empty"`, `"leaf"`, `"bucket"` or `"branch"`) fields.
+    /// Leaves have an `element`, buckets have an `elements` array and branches
+    /// have a `children` array of the included children with their `octant`s.
+    /// Branches, whose children are cut by the [`max_depth`](DumpOptions::max_depth),
+    /// have `"truncated":true` instead.
+    ///
+    /// Returns `null` if the root is outside of the [`region`](DumpOptions::region).
+    pub fn to_json(&self, options: &DumpOptions<U>) -> String {
+        let mut json = String::new();
+        if options.includes(&self.nodes[self.root].aabb) {
+            self.rjson(self.root, 0, options, &mut json);
+        } else {
+            json.push_str("null");
+        }
+        json
+    }
+
+    /// Writes the [`JSON`](Self::to_json) dump into the `writer`.
+    pub fn write_json(
+        &self,
+        mut writer: impl Write,
+        options: &DumpOptions<U>,
+    ) -> Result<(), TreeError> {
+        writer
+            .write_all(self.to_json(options).as_bytes())
+            .map_err(|err| TreeError::Io(err.to_string()))
+    }
+
+    // Recursion depth is bounded by the tree depth, which doesn't exceed the bit width of `U`.
+    fn rjson(&self, node: NodeId, depth: usize, options: &DumpOptions<U>, json: &mut String) {
+        let n = &self.nodes[node];
+        let vec = |v: TUVec3<U>| format!("[{},{},{}]", v.x, v.y, v.z);
+        let _ = write!(
+            json,
+            r#"{{"id":{},"depth":{depth},"aabb":{{"min":{},"max":{}}},"#,
+            usize::from(node),
+            vec(n.aabb.min),
+            vec(n.aabb.max)
+        );
+
+        match n.ntype {
+            NodeType::Empty => json.push_str(r#""type":"empty"}"#),
+
+            NodeType::Leaf(e) => {
+                let _ = write!(json, r#""type":"leaf","element":{}}}"#, usize::from(e));
+            }
+
+            NodeType::Bucket => {
+                json.push_str(r#""type":"bucket","elements":["#);
+                json.push_str(&join(self.bucket(node)));
+                json.push_str("]}");
+            }
+
+            NodeType::Branch(_) if options.truncates(depth) => {
+                json.push_str(r#""type":"branch","truncated":true}"#);
+            }
+
+            NodeType::Branch(branch) => {
+                json.push_str(r#""type":"branch","children":["#);
+                let mut first = true;
+                for (i, child) in branch.children().into_iter().enumerate() {
+                    if options.includes(&self.nodes[child].aabb) {
+                        if !first {
+                            json.push(',');
+                        }
+                        first = false;
+                        let _ = write!(json, r#"{{"octant":{i},"node":"#);
+                        self.rjson(child, depth + 1, options, json);
+                        json.push('}');
+                    }
+                }
+                json.push_str("]}");
+            }
+        }
+    }
+
+    fn ntype_label(&self, node: NodeId, ntype: &NodeType) -> String {
+        match ntype {
+            NodeType::Bucket => format!("NodeType: Bucket({})", join(self.bucket(node))),
+            ntype => ntype.to_string(),
+        }
+    }
+}
+
+fn join(elements: &[ElementId]) -> String {
+    elements
+        .iter()
+        .map(|&e| usize::from(e).to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::TUVec3u8;
+    use crate::tests::corners_tree;
+
+    fn tree() -> Octree<u8, TUVec3u8> {
+        let mut tree = corners_tree();
+        tree.insert(TUVec3u8::new(2, 2, 2)).unwrap();
+        tree
+    }
+
+    #[test]
+    fn test_dot() {
+        let tree = tree();
+        let dot = tree.to_dot(&DumpOptions::default());
+        assert_eq!(dot.matches("->").count(), tree.nodes.len() - 1);
+        assert!(dot.contains("n0 -> n1 [label=\"0\"];"));
+        assert!(dot.contains("NodeType: Leaf(ElementId: 2)"));
+        assert!(dot.ends_with("}\n"));
+
+        let dot = tree.to_dot(&DumpOptions::default().with_max_depth(1));
+        assert_eq!(dot.matches("->").count(), 8);
+        assert_eq!(dot.matches("style=dashed").count(), 1);
+
+        let region = Aabb::from_min_max(TUVec3::splat(8), TUVec3::splat(16));
+        let dot = tree.to_dot(&DumpOptions::default().with_region(region));
+        assert_eq!(dot.matches("->").count(), 1);
+        assert!(dot.contains("n0 -> n8"));
+    }
+
+    #[test]
+    fn test_json() {
+        let tree = tree();
+        let region = Aabb::from_min_max(TUVec3::splat(8), TUVec3::splat(16));
+        assert_eq!(
+            tree.to_json(&DumpOptions::default().with_region(region)),
+            concat!(
+                r#"{"id":0,"depth":0,"aabb":{"min":[0,0,0],"max":[16,16,16]},"type":"branch","children":["#,
+                r#"{"octant":7,"node":{"id":8,"depth":1,"aabb":{"min":[8,8,8],"max":[16,16,16]},"type":"leaf","element":1}}"#,
+                "]}"
+            )
+        );
+
+        let json = tree.to_json(&DumpOptions::default());
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["children"][7]["node"]["element"], 1);
+        assert_eq!(value["children"][0]["node"]["type"], "branch");
+
+        let json = tree.to_json(&DumpOptions::default().with_max_depth(1));
+        assert_eq!(json.matches(r#""truncated":true"#).count(), 1);
+        assert_eq!(json.matches(r#""type":"empty""#).count(), 6);
+
+        let outside = Aabb::from_min_max(TUVec3::splat(16), TUVec3::splat(20));
+        assert_eq!(
+            tree.to_json(&DumpOptions::default().with_region(outside)),
+            "null"
+        );
+    }
+
+    #[test]
+    fn test_buckets() {
+        let mut tree =
+            Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8)).with_max_depth(0);
+        tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+        tree.insert(TUVec3u8::new(2, 2, 2)).unwrap();
+
+        assert!(tree
+            .to_dot(&DumpOptions::default())
+            .contains("NodeType: Bucket(0,1)"));
+        assert!(tree
+            .to_json(&DumpOptions::default())
+            .contains(r#""type":"bucket","elements":[0,1]}"#));
+    }
+}
@@ -0,0 +1,672 @@
+//! [`FrozenOctree`] implementation.
+//!
+//! Read-only tree, queried directly from a byte slice,
+//! for example a memory mapped file.
+//! Loading only validates the data, nothing is allocated or copied.
+//!
+//! Layout, all numbers are little endian:
+//! - Header: magic `OKFZ`, version (`u16`), coordinate width in bytes (`u8`), reserved (`u8`),
+//!   node count (`u64`), element slot count (`u64`), element count (`u64`),
+//!   bucket entry count (`u64`) and root [`Aabb`].
+//! - Nodes: `u64` per node in breadth-first order. Lower 2 bits are the type:
+//!   `0` - empty, `1` - leaf, `2` - branch, `3` - bucket. Upper bits are the [`ElementId`] of a leaf,
+//!   the index of the first of 8 contiguous children of a branch
+//!   or the index of the first entry of a bucket.
+//! - Buckets: `u64` entries. Each [`Bucket`](NodeType::Bucket) is it's element count,
+//!   followed by it's [`ElementId`]s. Buckets are in the order of their nodes.
+//! - Element volumes: [`Aabb`] per [`ElementId`]. Removed ids have an empty volume.
+//!
+//! Node bounds are not stored, they are derived from the root [`Aabb`] during the traversal.
+
+use std::{collections::VecDeque, io::Write};
+
+use heapless::Vec as HVec;
+
+use crate::{
+    aggregate::Aggregate,
+    binary::{coordinate_width, Decode, Encode},
+    bounding::{Aabb, TUVec3, Unsigned},
+    intersect_with::Visited,
+    node::{octant, NodeType},
+    tree::Octree,
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Version of the frozen layout.
+pub const FROZEN_VERSION: u16 = 2;
+
+const MAGIC: &[u8; 4] = b"OKFZ";
+const HEADER: usize = 40;
+const NODE: usize = 8;
+const ENTRY: usize = 8;
+
+const EMPTY: u64 = 0;
+const LEAF: u64 = 1;
+const BRANCH: u64 = 2;
+const BUCKET: u64 = 3;
+
+/// Read-only tree, borrowing it's data from a byte slice.
+///
+/// Built by [`Octree::freeze`] or [`Octree::write_frozen`].
+/// Stores only the element volumes, so queries return [`ElementId`]s
+/// of the source tree.
+///
+/// ```rust
+/// use oktree::{frozen::FrozenOctree, prelude::*};
+///
+/// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16u8).unwrap());
+/// let c1_id = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+///
+/// let bytes = tree.freeze();
+/// let frozen = FrozenOctree::<u8>::from_bytes(&bytes).unwrap();
+///
+/// assert_eq!(frozen.find(&TUVec3::new(1, 1, 1)), Some(c1_id));
+/// assert_eq!(frozen.intersect_with(|_| true), vec![c1_id]);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct FrozenOctree<'a, U: Unsigned> {
+    aabb: Aabb<U>,
+    len: usize,
+    nodes: &'a [u8],
+    buckets: &'a [u8],
+    volumes: &'a [u8],
+}
+
+impl<'a, U> FrozenOctree<'a, U>
+where
+    U: Unsigned + Encode + Decode,
+{
+    /// Validates the `bytes` and wraps them into a [`FrozenOctree`].
+    ///
+    /// Fails with [`TreeError::InvalidData`] if the `bytes` are not a valid frozen tree.
+    pub fn from_bytes(bytes: &'a [u8]) -> Result<Self, TreeError> {
+        let invalid = |info: String| Err(TreeError::InvalidData(info));
+
+        let width = width::<U>();
+        let volume = width * 6;
+        if bytes.len() < HEADER + volume || &bytes[..4] != MAGIC {
+            return invalid("Not a frozen octree".into());
+        }
+
+        let mut header = &bytes[4..HEADER + volume];
+        let version = u16::decode(&mut header)?;
+        let file_width = u8::decode(&mut header)?;
+        let _reserved = u8::decode(&mut header)?;
+        let node_count = usize::decode(&mut header)?;
+        let slot_count = usize::decode(&mut header)?;
+        let len = usize::decode(&mut header)?;
+        let entry_count = usize::decode(&mut header)?;
+        let aabb = Aabb::<U>::decode(&mut header)?;
+
+        if version != FROZEN_VERSION {
+            return invalid(format!(
+                "Frozen version {version} is not supported, expected {FROZEN_VERSION}"
+            ));
+        }
+
+        if file_width as usize != width {
+            return invalid(format!(
+                "Coordinates are {file_width} bytes, expected {width}"
+            ));
+        }
+
+        if !aabb.min.lt(&aabb.max).all() {
+            return invalid(format!("Root {aabb} is empty"));
+        }
+
+        let nodes_end = node_count
+            .checked_mul(NODE)
+            .and_then(|n| n.checked_add(HEADER + volume));
+        let buckets_end = entry_count
+            .checked_mul(ENTRY)
+            .zip(nodes_end)
+            .and_then(|(b, n)| b.checked_add(n));
+        let end = slot_count
+            .checked_mul(volume)
+            .zip(buckets_end)
+            .and_then(|(v, b)| v.checked_add(b));
+        let (Some(nodes_end), Some(buckets_end), Some(end)) = (nodes_end, buckets_end, end) else {
+            return invalid("Sizes overflow".into());
+        };
+
+        if end != bytes.len() || node_count == 0 || len > slot_count {
+            return invalid(format!(
+                "Expected {end} bytes with {node_count} nodes, found {}",
+                bytes.len()
+            ));
+        }
+
+        let frozen = FrozenOctree {
+            aabb,
+            len,
+            nodes: &bytes[HEADER + volume..nodes_end],
+            buckets: &bytes[nodes_end..buckets_end],
+            volumes: &bytes[buckets_end..],
+        };
+
+        let element = |e: usize| e < slot_count && ElementId::try_from_index(e).is_ok();
+
+        // Children always follow their parent,
+        // so any traversal is finite and stays inside of the slice.
+        // Buckets follow each other, so each entry is validated once.
+        let mut next_bucket = 0;
+        for i in 0..node_count {
+            let (tag, payload) = frozen.node(i);
+            let valid = match tag {
+                EMPTY => true,
+                LEAF => element(payload),
+                BRANCH => payload > i && payload.checked_add(8).is_some_and(|n| n <= node_count),
+                BUCKET => {
+                    let end = (payload == next_bucket && payload < entry_count)
+                        .then(|| frozen.entry(payload).checked_add(payload + 1))
+                        .flatten()
+                        .filter(|&end| end <= entry_count);
+                    match end {
+                        Some(end) => {
+                            next_bucket = end;
+                            frozen.bucket(payload).all(element)
+                        }
+                        None => false,
+                    }
+                }
+                _ => false,
+            };
+
+            if !valid {
+                return invalid(format!("Node {i} is corrupted"));
+            }
+        }
+
+        if next_bucket != entry_count {
+            return invalid(format!(
+                "Buckets have {entry_count} entries, {next_bucket} are used"
+            ));
+        }
+
+        Ok(frozen)
+    }
+
+    /// Returns the [`Aabb`] of the root node.
+    pub fn aabb(&self) -> Aabb<U> {
+        self.aabb
+    }
+
+    /// Returns the number of elements in the tree.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    /// Is the tree empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the volume of the element.
+    ///
+    /// Returns [`None`] if the element is not in the tree.
+    pub fn volume(&self, element: ElementId) -> Option<Aabb<U>> {
+        let size = width::<U>() * 6;
+        let start = usize::from(element).checked_mul(size)?;
+        let mut bytes = self.volumes.get(start..start + size)?;
+        let aabb = Aabb::decode(&mut bytes).ok()?;
+        aabb.min.lt(&aabb.max).all().then_some(aabb)
+    }
+
+    #[inline(always)]
+    fn node(&self, i: usize) -> (u64, usize) {
+        let bytes = &self.nodes[i * NODE..(i + 1) * NODE];
+        let node = u64::from_le_bytes(bytes.try_into().unwrap());
+        (node & 0b11, (node >> 2) as usize)
+    }
+
+    #[inline(always)]
+    fn entry(&self, i: usize) -> usize {
+        let bytes = &self.buckets[i * ENTRY..(i + 1) * ENTRY];
+        u64::from_le_bytes(bytes.try_into().unwrap()) as usize
+    }
+
+    /// Element ids of the bucket, starting at the entry `i`.
+    #[inline(always)]
+    fn bucket(&self, i: usize) -> impl Iterator<Item = usize> + '_ {
+        (i + 1..i + 1 + self.entry(i)).map(|j| self.entry(j))
+    }
+
+    #[inline(always)]
+    fn element_volume(&self, e: usize) -> Aabb<U> {
+        let size = width::<U>() * 6;
+        let mut bytes = &self.volumes[e * size..(e + 1) * size];
+        Aabb::decode(&mut bytes).unwrap()
+    }
+
+    /// Search for the element at the [`point`](TUVec3)
+    ///
+    /// Returns element's [`id`](ElementId) or [`None`] if elements if not found.
+    pub fn find(&self, point: &TUVec3<U>) -> Option<ElementId> {
+        if !self.aabb.contains(point) {
+            return None;
+        }
+
+        let mut node = 0;
+        let mut aabb = self.aabb;
+        loop {
+            return match self.node(node) {
+                (LEAF, e) if self.element_volume(e).contains(point) => Some(ElementId::from(e)),
+
+                (BUCKET, i) => self
+                    .bucket(i)
+                    .find(|&e| self.element_volume(e).contains(point))
+                    .map(ElementId::from),
+
+                (BRANCH, first_child) => {
+                    let center = aabb.center();
+                    let i = octant(point, center);
+                    node = first_child + i;
+                    aabb = aabb._split(i, center);
+                    continue;
+                }
+
+                _ => None,
+            };
+        }
+    }
+
+    /// Intersect [`FrozenOctree`] with a custom intersection closure.
+    ///
+    /// Returns the [`vector`](Vec) of [`elements`](ElementId),
+    /// intersected by volume.
+    pub fn intersect_with<F>(&self, what: F) -> Vec<ElementId>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+    {
+        let mut elements = Vec::with_capacity(10);
+        self.intersect_with_for_each(what, |e| elements.push(e));
+        elements
+    }
+
+    /// Intersect [`FrozenOctree`] with a custom intersection closure.
+    /// Each element that intersects with the volume is passed to the supplied closure once,
+    /// even if it's stored in several leaves.
+    pub fn intersect_with_for_each<F, F2>(&self, what: F, mut actor: F2)
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(ElementId),
+    {
+        self.rintersect_with(0, self.aabb, &what, &mut actor, &mut Visited::default());
+    }
+
+    fn rintersect_with<F, F2>(
+        &self,
+        node: usize,
+        aabb: Aabb<U>,
+        what: &F,
+        actor: &mut F2,
+        visited: &mut Visited,
+    ) where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(ElementId),
+    {
+        // Walk the nodes with a heapless stack. Children that don't fit
+        // into a full stack are intersected by a recursive call.
+        let mut stack = HVec::<_, 32>::new();
+        stack.push((node, aabb)).unwrap();
+        while let Some((node, aabb)) = stack.pop() {
+            match self.node(node) {
+                (LEAF, e) => {
+                    let volume = self.element_volume(e);
+                    let e = ElementId::from(e);
+                    if what(&volume) && visited.first(&aabb, &volume, e) {
+                        actor(e);
+                    }
+                }
+
+                (BUCKET, i) => {
+                    for e in self.bucket(i) {
+                        let volume = self.element_volume(e);
+                        let e = ElementId::from(e);
+                        if what(&volume) && visited.first(&aabb, &volume, e) {
+                            actor(e);
+                        }
+                    }
+                }
+
+                (BRANCH, first_child) if what(&aabb) => {
+                    let center = aabb.center();
+                    for i in 0..8 {
+                        let child = (first_child + i, aabb._split(i, center));
+                        // If we can't push to the stack (to be processed on the next loop
+                        // iteration) then we fallback to recursive calls.
+                        if stack.push(child).is_err() {
+                            self.rintersect_with(child.0, child.1, what, actor, visited);
+                        }
+                    }
+                }
+
+                _ => (),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "bevy")]
+mod ray_cast {
+    use bevy::math::bounding::{Aabb3d, IntersectsVolume, RayCast3d};
+    use heapless::Vec as HVec;
+
+    use super::{FrozenOctree, BRANCH, BUCKET, LEAF};
+    use crate::{
+        bevy_integration::HitResult,
+        binary::{Decode, Encode},
+        bounding::{Aabb, Unsigned},
+        ElementId,
+    };
+
+    impl<U> FrozenOctree<'_, U>
+    where
+        U: Unsigned + Encode + Decode,
+    {
+        /// Intersects a [`FrozenOctree`] with the [`RayCast3d`].
+        ///
+        /// Returns a [`HitResult`] with [`ElementId`] and the doistance to
+        /// the intersection if any.
+        pub fn ray_cast(&self, ray: &RayCast3d) -> HitResult {
+            let mut hit = HitResult::default();
+            self.recursive_ray_cast(0, self.aabb, ray, &mut hit);
+            hit
+        }
+
+        fn recursive_ray_cast(
+            &self,
+            node: usize,
+            aabb: Aabb<U>,
+            ray: &RayCast3d,
+            hit: &mut HitResult,
+        ) {
+            // Walk the nodes with a heapless stack. Children that don't fit
+            // into a full stack are cast against by a recursive call.
+            let mut stack = HVec::<_, 32>::new();
+            stack.push((node, aabb)).unwrap();
+            while let Some((node, aabb)) = stack.pop() {
+                let bounds: Aabb3d = aabb.into();
+                if !ray.intersects(&bounds) {
+                    continue;
+                }
+
+                match self.node(node) {
+                    (LEAF, e) => {
+                        let volume = self.element_volume(e).into();
+                        if let Some(dist) = ray.aabb_intersection_at(&volume) {
+                            if hit.element.is_none() || hit.distance > dist {
+                                hit.element = Some(ElementId::from(e));
+                                hit.distance = dist;
+                            }
+                        }
+                    }
+
+                    (BUCKET, i) => {
+                        for e in self.bucket(i) {
+                            let volume = self.element_volume(e).into();
+                            if let Some(dist) = ray.aabb_intersection_at(&volume) {
+                                if hit.element.is_none() || hit.distance > dist {
+                                    hit.element = Some(ElementId::from(e));
+                                    hit.distance = dist;
+                                }
+                            }
+                        }
+                    }
+
+                    (BRANCH, first_child) => {
+                        let center = aabb.center();
+                        for i in 0..8 {
+                            let child = (first_child + i, aabb._split(i, center));
+                            // If we can't push to the stack (to be processed on the next loop
+                            // iteration) then we fallback to recursive calls.
+                            if stack.push(child).is_err() {
+                                self.recursive_ray_cast(child.0, child.1, ray, hit);
+                            }
+                        }
+                    }
+
+                    _ => (),
+                }
+            }
+        }
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned + Encode + Decode,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Builds the [`FrozenOctree`] data.
+    ///
+    /// Use [`FrozenOctree::from_bytes`] to query it.
+    pub fn freeze(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_frozen(&mut bytes)
+            .expect("writing into a Vec doesn't fail");
+        bytes
+    }
+
+    /// Writes the [`FrozenOctree`] data.
+    pub fn write_frozen(&self, mut writer: impl Write) -> Result<(), TreeError> {
+        let io_error = |err: std::io::Error| TreeError::Io(err.to_string());
+
+        let mut nodes = Vec::with_capacity(self.nodes.len() * NODE);
+        let mut buckets = Vec::new();
+        let mut next_child = 1;
+        let mut queue: VecDeque<NodeId> = VecDeque::from([self.root]);
+        while let Some(node) = queue.pop_front() {
+            let packed = match self.nodes[node].ntype {
+                NodeType::Empty => EMPTY,
+                NodeType::Leaf(e) => pack(LEAF, e.into())?,
+                NodeType::Branch(branch) => {
+                    queue.extend(branch.children());
+                    next_child += 8;
+                    pack(BRANCH, next_child - 8)?
+                }
+                NodeType::Bucket => {
+                    let bucket = self.bucket(node);
+                    let packed = pack(BUCKET, buckets.len() / ENTRY)?;
+                    bucket.len().encode(&mut buckets);
+                    for &e in bucket {
+                        usize::from(e).encode(&mut buckets);
+                    }
+                    packed
+                }
+            };
+            nodes.extend_from_slice(&packed.to_le_bytes());
+        }
+
+        let mut header = Vec::with_capacity(HEADER + width::<U>() * 6);
+        header.extend_from_slice(MAGIC);
+        FROZEN_VERSION.encode(&mut header);
+        (width::<U>() as u8).encode(&mut header);
+        0u8.encode(&mut header);
+        (nodes.len() / NODE).encode(&mut header);
+        self.elements.vec.len().encode(&mut header);
+        self.elements.len().encode(&mut header);
+        (buckets.len() / ENTRY).encode(&mut header);
+        self.nodes[self.root].aabb.encode(&mut header);
+
+        writer.write_all(&header).map_err(io_error)?;
+        writer.write_all(&nodes).map_err(io_error)?;
+        writer.write_all(&buckets).map_err(io_error)?;
+
+        let mut volumes = Vec::with_capacity(width::<U>() * 6);
+        let empty = Aabb::<U>::from_min_max(TUVec3::zero(), TUVec3::zero());
+        for i in 0..self.elements.vec.len() {
+            volumes.clear();
+            match self.elements.get(ElementId::from(i)) {
+                Some(element) => element.volume().encode(&mut volumes),
+                None => empty.encode(&mut volumes),
+            }
+            writer.write_all(&volumes).map_err(io_error)?;
+        }
+
+        writer.flush().map_err(io_error)
+    }
+}
+
+/// Packs the node tag into the lower 2 bits and the `payload` into the rest.
+///
+/// Fails with [`TreeError::IndexOverflow`] if the `payload` doesn't fit into 62 bits.
+#[inline(always)]
+fn pack(tag: u64, payload: usize) -> Result<u64, TreeError> {
+    let payload = payload as u64;
+    if payload > u64::MAX >> 2 {
+        return Err(TreeError::IndexOverflow(format!(
+            "Frozen node payload {payload} doesn't fit into 62 bits"
+        )));
+    }
+    Ok(tag | payload << 2)
+}
+
+#[inline(always)]
+fn width<U: Unsigned + Decode>() -> usize {
+    coordinate_width::<U>() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::{TUVec3u16, TUVec3u64};
+    use crate::tests::DummyVolume;
+
+    fn tree() -> Octree<u16, TUVec3u16> {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(16), 16));
+        for i in 0..32 {
+            tree.insert(TUVec3u16::new(i, 31 - i, i / 2)).unwrap();
+        }
+        for i in (0..32).step_by(3) {
+            tree.remove(ElementId(i)).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn test_find() {
+        let tree = tree();
+        let bytes = tree.freeze();
+        let frozen = FrozenOctree::<u16>::from_bytes(&bytes).unwrap();
+
+        assert_eq!(frozen.len(), tree.len());
+        assert_eq!(frozen.aabb(), tree.nodes[tree.root].aabb);
+        for x in 0..33 {
+            for y in 0..33 {
+                for z in 0..17 {
+                    let point = TUVec3::new(x, y, z);
+                    assert_eq!(frozen.find(&point), tree.find(&point), "{point}");
+                }
+            }
+        }
+
+        assert_eq!(
+            frozen.volume(ElementId(1)),
+            Some(TUVec3u16::new(1, 30, 0).volume())
+        );
+        assert_eq!(frozen.volume(ElementId(3)), None);
+        assert_eq!(frozen.volume(ElementId(100)), None);
+    }
+
+    #[test]
+    fn test_intersect_with() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(16), 16));
+        for i in 0..4 {
+            let aabb = Aabb::new_unchecked(TUVec3::new(4 + i * 8, 4, 4), 3);
+            tree.insert(DummyVolume::new(aabb)).unwrap();
+        }
+        let bytes = tree.freeze();
+        let frozen = FrozenOctree::<u16>::from_bytes(&bytes).unwrap();
+
+        let area = Aabb::from_min_max(TUVec3::new(6, 0, 0), TUVec3::new(14, 8, 8));
+        let mut expected = tree.intersect_with(|aabb| area.overlaps(aabb));
+        let mut elements = frozen.intersect_with(|aabb| area.overlaps(aabb));
+        expected.sort();
+        elements.sort();
+        assert!(elements.windows(2).all(|w| w[0] != w[1]));
+        assert_eq!(elements, expected);
+        assert_eq!(elements, vec![ElementId(0), ElementId(1)]);
+    }
+
+    #[test]
+    fn test_buckets() {
+        let half = 1 << 40;
+        let mut tree =
+            Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(half), half)).with_max_depth(4);
+        for i in 0..32 {
+            tree.insert(TUVec3u64::new(i, 31 - i, i / 2)).unwrap();
+        }
+        tree.insert(TUVec3u64::new(half, half, half)).unwrap();
+        assert!(!tree.buckets.is_empty());
+
+        // Buckets stay shallow, instead of the chains down to the unit size
+        let bytes = tree.freeze();
+        let frozen = FrozenOctree::<u64>::from_bytes(&bytes).unwrap();
+        assert_eq!(frozen.nodes.len() / NODE, tree.nodes.len());
+        assert_eq!(frozen.nodes.len() / NODE, 1 + 4 * 8);
+        for (e, element) in tree.iter_elements() {
+            assert_eq!(frozen.find(&element.0), Some(e));
+        }
+        assert_eq!(frozen.find(&TUVec3::new(1, 1, 1)), None);
+
+        let area = Aabb::from_min_max(TUVec3::new(0, 20, 0), TUVec3::new(8, 32, 8));
+        let mut expected = tree.intersect_with(|aabb| area.overlaps(aabb));
+        let mut elements = frozen.intersect_with(|aabb| area.overlaps(aabb));
+        expected.sort();
+        elements.sort();
+        assert_eq!(elements, expected);
+        assert_eq!(elements.len(), 8);
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let bytes = tree().freeze();
+        assert!(FrozenOctree::<u16>::from_bytes(&bytes).is_ok());
+
+        assert!(FrozenOctree::<u8>::from_bytes(&bytes).is_err());
+        assert!(FrozenOctree::<u16>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(FrozenOctree::<u16>::from_bytes(&bytes[..10]).is_err());
+
+        // Branch pointing back to the root
+        let mut corrupted = bytes.clone();
+        let root = HEADER + 12;
+        corrupted[root..root + NODE].copy_from_slice(&BRANCH.to_le_bytes());
+        assert!(FrozenOctree::<u16>::from_bytes(&corrupted).is_err());
+
+        // Bucket without entries
+        corrupted[root..root + NODE].copy_from_slice(&BUCKET.to_le_bytes());
+        assert!(FrozenOctree::<u16>::from_bytes(&corrupted).is_err());
+    }
+
+    #[test]
+    fn test_pack() {
+        let max = (u64::MAX >> 2) as usize;
+        assert_eq!(pack(LEAF, max), Ok(u64::MAX - 2));
+        if usize::BITS == 64 {
+            assert!(matches!(
+                pack(LEAF, max + 1),
+                Err(TreeError::IndexOverflow(_))
+            ));
+        }
+    }
+
+    #[cfg(feature = "bevy")]
+    #[test]
+    fn test_ray_cast() {
+        use bevy::math::{bounding::RayCast3d, Dir3A, Vec3A};
+
+        let tree = tree();
+        let bytes = tree.freeze();
+        let frozen = FrozenOctree::<u16>::from_bytes(&bytes).unwrap();
+
+        for i in 0..32 {
+            let origin = Vec3A::new(i as f32 + 0.5, 40.0, i as f32 / 2.0 + 0.5);
+            let ray = RayCast3d::new(origin, Dir3A::NEG_Y, 100.0);
+            assert_eq!(frozen.ray_cast(&ray), tree.ray_cast(&ray));
+        }
+    }
+}
@@ -1,18 +1,28 @@
 //! Helper functions with a custom intersection closure.
+//!
+//! Volumes, crossing the node boundaries, are stored in every leaf they overlap.
+//! Every query still passes each matching element to the caller exactly once:
+//! elements, not contained in the current leaf, are checked against a visited bitset,
+//! allocated only when such an element is met.
+
+use std::ops::ControlFlow;
 
 use heapless::Vec as HVec;
+use smallvec::SmallVec;
 
 use crate::{
+    aggregate::Aggregate,
     bounding::{Aabb, Unsigned},
     node::NodeType,
-    tree::Octree,
+    tree::{leaf_elements, Octree},
     ElementId, NodeId, Volume,
 };
 
-impl<U, T> Octree<U, T>
+impl<U, T, A> Octree<U, T, A>
 where
     U: Unsigned,
     T: Volume<U = U>,
+    A: Aggregate<T>,
 {
     /// Intersect [`Octree`] with a custom intersection closure.
     ///
@@ -21,7 +31,6 @@ where
     ///
     /// ```rust
     /// use oktree::prelude::*;
-    /// use bevy::prelude::*;
     ///
     /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
     ///
@@ -36,7 +45,7 @@ where
         F: Fn(&Aabb<U>) -> bool,
     {
         let mut elements = Vec::with_capacity(10);
-        self.rintersect_with(self.root, &what, &mut elements);
+        self.rintersect_with(self.root, &what, &mut elements, &mut Visited::default());
         elements
     }
 
@@ -48,7 +57,6 @@ where
     ///
     /// ```rust
     /// use oktree::prelude::*;
-    /// use bevy::prelude::*;
     ///
     /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
     ///
@@ -64,11 +72,16 @@ where
     where
         F: Fn(&Aabb<U>) -> bool,
     {
-        self.rintersect_with(self.root, &what, elements);
+        self.rintersect_with(self.root, &what, elements, &mut Visited::default());
     }
 
-    fn rintersect_with<F>(&self, node: NodeId, what: &F, elements: &mut Vec<ElementId>)
-    where
+    fn rintersect_with<F>(
+        &self,
+        node: NodeId,
+        what: &F,
+        elements: &mut Vec<ElementId>,
+        visited: &mut Visited,
+    ) where
         F: Fn(&Aabb<U>) -> bool,
     {
         // We use a heapless stack to loop through the nodes until we complete the intersect however
@@ -80,23 +93,26 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(e) => {
-                    let aabb = self.elements[e].volume();
-                    if what(&aabb) {
-                        elements.push(e);
-                    };
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &e in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let aabb = self.elements[e].volume();
+                        if what(&aabb) && visited.first(&n.aabb, &aabb, e) {
+                            elements.push(e);
+                        };
+                    }
                 }
 
                 NodeType::Branch(branch) => {
                     if what(&n.aabb) {
-                        let mut iter = branch.children.iter();
+                        let children = branch.children();
+                        let mut iter = children.iter();
                         while let Some(child) = iter.next() {
                             // If we can't push to the stack (to be processed on the next loop
                             // iteration) then we fallback to recursive calls.
                             if stack.push(*child).is_err() {
-                                self.rintersect_with(*child, what, elements);
+                                self.rintersect_with(*child, what, elements, visited);
                                 for child in iter.by_ref() {
-                                    self.rintersect_with(*child, what, elements);
+                                    self.rintersect_with(*child, what, elements, visited);
                                 }
                             }
                         }
@@ -112,7 +128,6 @@ where
     ///
     /// ```rust
     /// use oktree::prelude::*;
-    /// use bevy::prelude::*;
     ///
     /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
     ///
@@ -128,13 +143,54 @@ where
         F: Fn(&Aabb<U>) -> bool,
         F2: FnMut(&T),
     {
-        self.rintersect_with_for_each(self.root, &what, &mut actor);
+        let _ = self.try_intersect_with_for_each(what, |e| {
+            actor(e);
+            ControlFlow::<()>::Continue(())
+        });
     }
 
-    fn rintersect_with_for_each<F, F2>(&self, node: NodeId, what: &F, actor: &mut F2)
+    /// Same as [`intersect_with_for_each`](Self::intersect_with_for_each),
+    /// but the traversal stops as soon as the closure returns [`ControlFlow::Break`].
+    ///
+    /// Returns the [`ControlFlow::Break`] value or [`ControlFlow::Continue`]
+    /// if every intersected element was visited.
+    ///
+    /// ```rust
+    /// use std::ops::ControlFlow;
+    /// use oktree::prelude::*;
+    ///
+    /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
+    ///
+    /// let c1 = TUVec3u8::new(1u8, 1, 1);
+    /// tree.insert(c1).unwrap();
+    /// tree.insert(TUVec3u8::new(2u8, 2, 2)).unwrap();
+    ///
+    /// let mut visited = 0;
+    /// let found = tree.try_intersect_with_for_each(|_| true, |e| {
+    ///     visited += 1;
+    ///     ControlFlow::Break(e.clone())
+    /// });
+    /// assert!(matches!(found, ControlFlow::Break(_)));
+    /// assert_eq!(visited, 1);
+    /// ```
+    pub fn try_intersect_with_for_each<F, F2, B>(&self, what: F, mut actor: F2) -> ControlFlow<B>
     where
         F: Fn(&Aabb<U>) -> bool,
-        F2: FnMut(&T),
+        F2: FnMut(&T) -> ControlFlow<B>,
+    {
+        self.rintersect_with_for_each(self.root, &what, &mut actor, &mut Visited::default())
+    }
+
+    fn rintersect_with_for_each<F, F2, B>(
+        &self,
+        node: NodeId,
+        what: &F,
+        actor: &mut F2,
+        visited: &mut Visited,
+    ) -> ControlFlow<B>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(&T) -> ControlFlow<B>,
     {
         // We use a heapless stack to loop through the nodes until we complete the intersect however
         // if the stack becomes full then then we fallbackon recursive calls.
@@ -145,28 +201,30 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(e) => {
-                    let e = &self.elements[e];
-                    let aabb = e.volume();
-                    if what(&aabb) {
-                        actor(e);
-                    };
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &id in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let e = &self.elements[id];
+                        let aabb = e.volume();
+                        if what(&aabb) && visited.first(&n.aabb, &aabb, id) {
+                            actor(e)?;
+                        };
+                    }
                 }
 
                 NodeType::Branch(branch) => {
                     if what(&n.aabb) {
-                        let mut iter = branch.children.iter();
-                        while let Some(child) = iter.next() {
+                        for child in branch.children().iter() {
                             // If we can't push to the stack (to be processed on the next loop
                             // iteration) then we fallback to recursive calls.
                             if stack.push(*child).is_err() {
-                                self.rintersect_with_for_each(*child, what, actor);
+                                self.rintersect_with_for_each(*child, what, actor, visited)?;
                             }
                         }
                     }
                 }
             }
         }
+        ControlFlow::Continue(())
     }
 
     /// Intersect [`Octree`] with a custom intersection closure reusing a
@@ -175,7 +233,6 @@ where
     ///
     /// ```rust
     /// use oktree::prelude::*;
-    /// use bevy::prelude::*;
     ///
     /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
     ///
@@ -191,13 +248,36 @@ where
         F: Fn(&Aabb<U>) -> bool,
         F2: FnMut(&mut T),
     {
-        self.rintersect_with_for_each_mut(self.root, &what, &mut actor);
+        let _ = self.try_intersect_with_for_each_mut(what, |e| {
+            actor(e);
+            ControlFlow::<()>::Continue(())
+        });
     }
 
-    fn rintersect_with_for_each_mut<F, F2>(&mut self, node: NodeId, what: &F, actor: &mut F2)
+    /// Same as [`intersect_with_for_each_mut`](Self::intersect_with_for_each_mut),
+    /// but the traversal stops as soon as the closure returns [`ControlFlow::Break`].
+    pub fn try_intersect_with_for_each_mut<F, F2, B>(
+        &mut self,
+        what: F,
+        mut actor: F2,
+    ) -> ControlFlow<B>
     where
         F: Fn(&Aabb<U>) -> bool,
-        F2: FnMut(&mut T),
+        F2: FnMut(&mut T) -> ControlFlow<B>,
+    {
+        self.rintersect_with_for_each_mut(self.root, &what, &mut actor, &mut Visited::default())
+    }
+
+    fn rintersect_with_for_each_mut<F, F2, B>(
+        &mut self,
+        node: NodeId,
+        what: &F,
+        actor: &mut F2,
+        visited: &mut Visited,
+    ) -> ControlFlow<B>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(&mut T) -> ControlFlow<B>,
     {
         // We use a heapless stack to loop through the nodes until we complete the intersect however
         // if the stack becomes full then then we fallbackon recursive calls.
@@ -208,28 +288,30 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(id) => {
-                    let e = &mut self.elements[id];
-                    let aabb = e.volume();
-                    if what(&aabb) {
-                        actor(e);
-                    };
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &id in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let e = &mut self.elements[id];
+                        let aabb = e.volume();
+                        if what(&aabb) && visited.first(&n.aabb, &aabb, id) {
+                            actor(e)?;
+                        };
+                    }
                 }
 
                 NodeType::Branch(branch) => {
                     if what(&n.aabb) {
-                        let mut iter = branch.children.iter();
-                        while let Some(child) = iter.next() {
+                        for child in branch.children().iter() {
                             // If we can't push to the stack (to be processed on the next loop
                             // iteration) then we fallback to recursive calls.
                             if stack.push(*child).is_err() {
-                                self.rintersect_with_for_each_mut(*child, what, actor);
+                                self.rintersect_with_for_each_mut(*child, what, actor, visited)?;
                             }
                         }
                     }
                 }
             }
         }
+        ControlFlow::Continue(())
     }
 
     /// Intersect [`Octree`] with a custom intersection closure reusing a
@@ -238,7 +320,6 @@ where
     ///
     /// ```rust
     /// use oktree::prelude::*;
-    /// use bevy::prelude::*;
     ///
     /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
     ///
@@ -254,13 +335,41 @@ where
         F: Fn(&Aabb<U>) -> bool,
         F2: FnMut(ElementId, &T),
     {
-        self.rintersect_with_for_each_with_ids(self.root, &what, &mut actor);
+        let _ = self.try_intersect_with_for_each_with_ids(what, |id, e| {
+            actor(id, e);
+            ControlFlow::<()>::Continue(())
+        });
     }
 
-    fn rintersect_with_for_each_with_ids<F, F2>(&self, node: NodeId, what: &F, actor: &mut F2)
+    /// Same as [`intersect_with_for_each_with_ids`](Self::intersect_with_for_each_with_ids),
+    /// but the traversal stops as soon as the closure returns [`ControlFlow::Break`].
+    pub fn try_intersect_with_for_each_with_ids<F, F2, B>(
+        &self,
+        what: F,
+        mut actor: F2,
+    ) -> ControlFlow<B>
     where
         F: Fn(&Aabb<U>) -> bool,
-        F2: FnMut(ElementId, &T),
+        F2: FnMut(ElementId, &T) -> ControlFlow<B>,
+    {
+        self.rintersect_with_for_each_with_ids(
+            self.root,
+            &what,
+            &mut actor,
+            &mut Visited::default(),
+        )
+    }
+
+    fn rintersect_with_for_each_with_ids<F, F2, B>(
+        &self,
+        node: NodeId,
+        what: &F,
+        actor: &mut F2,
+        visited: &mut Visited,
+    ) -> ControlFlow<B>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(ElementId, &T) -> ControlFlow<B>,
     {
         // We use a heapless stack to loop through the nodes until we complete the intersect however
         // if the stack becomes full then then we fallbackon recursive calls.
@@ -271,28 +380,32 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(id) => {
-                    let e = &self.elements[id];
-                    let aabb = e.volume();
-                    if what(&aabb) {
-                        actor(id, e);
-                    };
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &id in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let e = &self.elements[id];
+                        let aabb = e.volume();
+                        if what(&aabb) && visited.first(&n.aabb, &aabb, id) {
+                            actor(id, e)?;
+                        };
+                    }
                 }
 
                 NodeType::Branch(branch) => {
                     if what(&n.aabb) {
-                        let mut iter = branch.children.iter();
-                        while let Some(child) = iter.next() {
+                        for child in branch.children().iter() {
                             // If we can't push to the stack (to be processed on the next loop
                             // iteration) then we fallback to recursive calls.
                             if stack.push(*child).is_err() {
-                                self.rintersect_with_for_each_with_ids(*child, what, actor);
+                                self.rintersect_with_for_each_with_ids(
+                                    *child, what, actor, visited,
+                                )?;
                             }
                         }
                     }
                 }
             }
         }
+        ControlFlow::Continue(())
     }
 
     /// Intersect [`Octree`] with a custom intersection closure reusing a
@@ -301,7 +414,6 @@ where
     ///
     /// ```rust
     /// use oktree::prelude::*;
-    /// use bevy::prelude::*;
     ///
     /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
     ///
@@ -317,17 +429,41 @@ where
         F: Fn(&Aabb<U>) -> bool,
         F2: FnMut(ElementId, &mut T),
     {
-        self.rintersect_with_for_each_with_ids_mut(self.root, &what, &mut actor);
+        let _ = self.try_intersect_with_for_each_with_ids_mut(what, |id, e| {
+            actor(id, e);
+            ControlFlow::<()>::Continue(())
+        });
+    }
+
+    /// Same as [`intersect_with_for_each_with_ids_mut`](Self::intersect_with_for_each_with_ids_mut),
+    /// but the traversal stops as soon as the closure returns [`ControlFlow::Break`].
+    pub fn try_intersect_with_for_each_with_ids_mut<F, F2, B>(
+        &mut self,
+        what: F,
+        mut actor: F2,
+    ) -> ControlFlow<B>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(ElementId, &mut T) -> ControlFlow<B>,
+    {
+        self.rintersect_with_for_each_with_ids_mut(
+            self.root,
+            &what,
+            &mut actor,
+            &mut Visited::default(),
+        )
     }
 
-    fn rintersect_with_for_each_with_ids_mut<F, F2>(
+    fn rintersect_with_for_each_with_ids_mut<F, F2, B>(
         &mut self,
         node: NodeId,
         what: &F,
         actor: &mut F2,
-    ) where
+        visited: &mut Visited,
+    ) -> ControlFlow<B>
+    where
         F: Fn(&Aabb<U>) -> bool,
-        F2: FnMut(ElementId, &mut T),
+        F2: FnMut(ElementId, &mut T) -> ControlFlow<B>,
     {
         // We use a heapless stack to loop through the nodes until we complete the intersect however
         // if the stack becomes full then then we fallbackon recursive calls.
@@ -338,28 +474,32 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(id) => {
-                    let e = &mut self.elements[id];
-                    let aabb = e.volume();
-                    if what(&aabb) {
-                        actor(id, e);
-                    };
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &id in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let e = &mut self.elements[id];
+                        let aabb = e.volume();
+                        if what(&aabb) && visited.first(&n.aabb, &aabb, id) {
+                            actor(id, e)?;
+                        };
+                    }
                 }
 
                 NodeType::Branch(branch) => {
                     if what(&n.aabb) {
-                        let mut iter = branch.children.iter();
-                        while let Some(child) = iter.next() {
+                        for child in branch.children().iter() {
                             // If we can't push to the stack (to be processed on the next loop
                             // iteration) then we fallback to recursive calls.
                             if stack.push(*child).is_err() {
-                                self.rintersect_with_for_each_with_ids_mut(*child, what, actor);
+                                self.rintersect_with_for_each_with_ids_mut(
+                                    *child, what, actor, visited,
+                                )?;
                             }
                         }
                     }
                 }
             }
         }
+        ControlFlow::Continue(())
     }
 
     /// Anti intersect [`Octree`] with a custom intersection closure reusing a
@@ -368,7 +508,6 @@ where
     ///
     /// ```rust
     /// use oktree::prelude::*;
-    /// use bevy::prelude::*;
     ///
     /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
     ///
@@ -376,7 +515,7 @@ where
     /// let c1_id = tree.insert(c1).unwrap();
     ///
     /// let mut elements = Vec::new();
-    /// tree.anti_intersect_with_for_each(|_| true, |e| elements.push(e.clone()) );
+    /// tree.anti_intersect_with_for_each(|_| false, |e| elements.push(e.clone()) );
     /// assert_eq!(elements, vec![c1]);
     /// ```
     pub fn anti_intersect_with_for_each<F, F2>(&self, what: F, mut actor: F2)
@@ -384,13 +523,36 @@ where
         F: Fn(&Aabb<U>) -> bool,
         F2: FnMut(&T),
     {
-        self.anti_rintersect_with_for_each(self.root, &what, &mut actor);
+        let _ = self.try_anti_intersect_with_for_each(what, |e| {
+            actor(e);
+            ControlFlow::<()>::Continue(())
+        });
     }
 
-    fn anti_rintersect_with_for_each<F, F2>(&self, node: NodeId, what: &F, actor: &mut F2)
+    /// Same as [`anti_intersect_with_for_each`](Self::anti_intersect_with_for_each),
+    /// but the traversal stops as soon as the closure returns [`ControlFlow::Break`].
+    pub fn try_anti_intersect_with_for_each<F, F2, B>(
+        &self,
+        what: F,
+        mut actor: F2,
+    ) -> ControlFlow<B>
     where
         F: Fn(&Aabb<U>) -> bool,
-        F2: FnMut(&T),
+        F2: FnMut(&T) -> ControlFlow<B>,
+    {
+        self.anti_rintersect_with_for_each(self.root, &what, &mut actor, &mut Visited::default())
+    }
+
+    fn anti_rintersect_with_for_each<F, F2, B>(
+        &self,
+        node: NodeId,
+        what: &F,
+        actor: &mut F2,
+        visited: &mut Visited,
+    ) -> ControlFlow<B>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(&T) -> ControlFlow<B>,
     {
         // We use a heapless stack to loop through the nodes until we complete the intersect however
         // if the stack becomes full then then we fallbackon recursive calls.
@@ -401,35 +563,42 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(e) => {
-                    let e = &self.elements[e];
-                    let aabb = e.volume();
-                    if !what(&aabb) {
-                        actor(e);
-                    };
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &id in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let e = &self.elements[id];
+                        let aabb = e.volume();
+                        if !what(&aabb) && visited.first(&n.aabb, &aabb, id) {
+                            actor(e)?;
+                        };
+                    }
                 }
 
                 NodeType::Branch(branch) => {
                     if what(&n.aabb) {
-                        let mut iter = branch.children.iter();
-                        while let Some(child) = iter.next() {
+                        for child in branch.children().iter() {
                             if stack.push(*child).is_err() {
-                                self.anti_rintersect_with_for_each(*child, what, actor);
+                                self.anti_rintersect_with_for_each(*child, what, actor, visited)?;
                             }
                         }
                     } else {
-                        for child in branch.children.iter() {
-                            self.anti_rintersect_with_for_each_trigger_all(*child, actor);
+                        for child in branch.children().iter() {
+                            self.anti_rintersect_with_for_each_trigger_all(*child, actor, visited)?;
                         }
                     }
                 }
             }
         }
+        ControlFlow::Continue(())
     }
 
-    fn anti_rintersect_with_for_each_trigger_all<F2>(&self, node: NodeId, actor: &mut F2)
+    fn anti_rintersect_with_for_each_trigger_all<F2, B>(
+        &self,
+        node: NodeId,
+        actor: &mut F2,
+        visited: &mut Visited,
+    ) -> ControlFlow<B>
     where
-        F2: FnMut(&T),
+        F2: FnMut(&T) -> ControlFlow<B>,
     {
         let mut stack = HVec::<_, 32>::new();
         stack.push(node).unwrap();
@@ -438,19 +607,342 @@ where
             match n.ntype {
                 NodeType::Empty => (),
 
-                NodeType::Leaf(e) => {
-                    actor(&self.elements[e]);
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    for &id in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let e = &self.elements[id];
+                        if visited.first(&n.aabb, &e.volume(), id) {
+                            actor(e)?;
+                        }
+                    }
                 }
 
                 NodeType::Branch(branch) => {
-                    let mut iter = branch.children.iter();
-                    while let Some(child) = iter.next() {
+                    for child in branch.children().iter() {
                         if stack.push(*child).is_err() {
-                            self.anti_rintersect_with_for_each_trigger_all(*child, actor);
+                            self.anti_rintersect_with_for_each_trigger_all(*child, actor, visited)?;
                         }
                     }
                 }
             }
         }
+        ControlFlow::Continue(())
+    }
+
+    /// Lazily intersect [`Octree`] with a custom intersection closure.
+    ///
+    /// Returns an [`iterator`](QueryIter) over the intersected [`elements`](ElementId)
+    /// and their values. Nodes are traversed only as far as the iterator is advanced,
+    /// so it composes with the iterator adapters and could be left early with `break`.
+    ///
+    /// Elements, spanning several leaves, are yielded once.
+    ///
+    /// ```rust
+    /// use oktree::prelude::*;
+    ///
+    /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
+    ///
+    /// let c1_id = tree.insert(TUVec3u8::new(1u8, 1, 1)).unwrap();
+    /// tree.insert(TUVec3u8::new(20u8, 20, 20)).unwrap();
+    ///
+    /// let area = Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(8));
+    /// let found: Vec<_> = tree
+    ///     .query_iter(|aabb| aabb.overlaps(&area))
+    ///     .map(|(id, _)| id)
+    ///     .collect();
+    /// assert_eq!(found, vec![c1_id]);
+    ///
+    /// for (_, cell) in tree.query_iter(|_| true) {
+    ///     if cell.0.x > 10 {
+    ///         break;
+    ///     }
+    /// }
+    /// ```
+    pub fn query_iter<F>(&self, what: F) -> QueryIter<'_, U, T, A, F>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+    {
+        let mut stack = SmallVec::new();
+        stack.push(self.root);
+        QueryIter {
+            tree: self,
+            what,
+            stack,
+            pending: [].iter(),
+            leaf: Aabb::default(),
+            visited: Visited::default(),
+        }
+    }
+
+    /// Checks if any element overlaps the `aabb`.
+    ///
+    /// Stops at the first found element.
+    ///
+    /// ```rust
+    /// use oktree::prelude::*;
+    ///
+    /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
+    /// tree.insert(TUVec3u8::new(1u8, 1, 1)).unwrap();
+    ///
+    /// assert!(tree.any_in(&Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(4))));
+    /// assert!(!tree.any_in(&Aabb::from_min_max(TUVec3::splat(4), TUVec3::splat(8))));
+    /// ```
+    pub fn any_in(&self, aabb: &Aabb<U>) -> bool {
+        self.first_in(aabb).is_some()
+    }
+
+    /// Returns an element, overlapping the `aabb`.
+    ///
+    /// Stops at the first found element.
+    /// If several elements overlap the `aabb`, which one is returned is unspecified.
+    ///
+    /// ```rust
+    /// use oktree::prelude::*;
+    ///
+    /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
+    /// let c1_id = tree.insert(TUVec3u8::new(1u8, 1, 1)).unwrap();
+    ///
+    /// assert_eq!(tree.first_in(&Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(4))), Some(c1_id));
+    /// ```
+    pub fn first_in(&self, aabb: &Aabb<U>) -> Option<ElementId> {
+        match self.try_intersect_with_for_each_with_ids(
+            |volume| volume.overlaps(aabb),
+            |id, _| ControlFlow::Break(id),
+        ) {
+            ControlFlow::Break(id) => Some(id),
+            ControlFlow::Continue(()) => None,
+        }
+    }
+}
+
+/// Lazy iterator over the intersected elements.
+///
+/// Returned by [`Octree::query_iter`].
+pub struct QueryIter<'tree, U, T, A, F>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+    F: Fn(&Aabb<U>) -> bool,
+{
+    tree: &'tree Octree<U, T, A>,
+    what: F,
+    stack: SmallVec<[NodeId; 32]>,
+
+    /// Not yet checked elements of the current leaf or bucket
+    pending: std::slice::Iter<'tree, ElementId>,
+
+    /// Bounds of the current leaf or bucket
+    leaf: Aabb<U>,
+
+    visited: Visited,
+}
+
+impl<'tree, U, T, A, F> Iterator for QueryIter<'tree, U, T, A, F>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+    F: Fn(&Aabb<U>) -> bool,
+{
+    type Item = (ElementId, &'tree T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            for &e in self.pending.by_ref() {
+                let element = &self.tree.elements[e];
+                let volume = element.volume();
+                if (self.what)(&volume) && self.visited.first(&self.leaf, &volume, e) {
+                    return Some((e, element));
+                }
+            }
+
+            let node = self.stack.pop()?;
+            let n = &self.tree.nodes[node];
+            match n.ntype {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    self.pending = leaf_elements(&self.tree.buckets, node, &n.ntype).iter();
+                    self.leaf = n.aabb;
+                }
+
+                NodeType::Branch(branch) => {
+                    if (self.what)(&n.aabb) {
+                        self.stack.extend(branch.children().into_iter().rev());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Elements, spanning several leaves, already passed to the caller.
+///
+/// An element, contained in the current leaf, can't be stored in any other one,
+/// so the bitset is consulted and grown only for the spanning elements.
+#[derive(Default, Debug, Clone)]
+pub(crate) struct Visited {
+    bits: Vec<u64>,
+}
+
+impl Visited {
+    /// Returns `true` when the element with the `volume`, stored in the `leaf`,
+    /// is visited for the first time.
+    pub(crate) fn first<U: Unsigned>(
+        &mut self,
+        leaf: &Aabb<U>,
+        volume: &Aabb<U>,
+        element: ElementId,
+    ) -> bool {
+        if leaf.min.le(&volume.min).all() && volume.max.le(&leaf.max).all() {
+            return true;
+        }
+
+        let index = usize::from(element);
+        let (word, bit) = (index / 64, 1 << (index % 64));
+        if self.bits.len() <= word {
+            self.bits.resize(word + 1, 0);
+        }
+        let first = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        first
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bounding::TUVec3,
+        compact::CompactOctree,
+        frozen::FrozenOctree,
+        tests::{DummyCell, DummyVolume},
+    };
+
+    #[test]
+    fn test_early_exit() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8u8), 8));
+        for i in 0..16 {
+            tree.insert(DummyCell::new(TUVec3::new(i, i, 0))).unwrap();
+        }
+
+        let mut visited = 0;
+        let flow = tree.try_intersect_with_for_each_with_ids(
+            |_| true,
+            |id, _| {
+                visited += 1;
+                if visited == 3 {
+                    ControlFlow::Break(id)
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        );
+        assert!(matches!(flow, ControlFlow::Break(_)));
+        assert_eq!(visited, 3);
+
+        let flow = tree.try_anti_intersect_with_for_each(|_| false, |_| ControlFlow::Break(()));
+        assert_eq!(flow, ControlFlow::Break(()));
+
+        let flow = tree.try_intersect_with_for_each_mut(
+            |_| true,
+            |cell| {
+                cell.node = NodeId(1);
+                ControlFlow::<()>::Continue(())
+            },
+        );
+        assert_eq!(flow, ControlFlow::Continue(()));
+        assert!(tree.iter().all(|cell| cell.node == NodeId(1)));
+
+        let region = Aabb::from_min_max(TUVec3::new(4, 4, 0), TUVec3::new(6, 6, 1));
+        assert!(tree.any_in(&region));
+        assert!(matches!(tree.first_in(&region), Some(ElementId(4 | 5))));
+
+        let empty = Aabb::from_min_max(TUVec3::new(4, 0, 0), TUVec3::new(6, 2, 1));
+        assert!(!tree.any_in(&empty));
+        assert_eq!(tree.first_in(&empty), None);
+    }
+
+    #[test]
+    fn test_query_iter() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(16u16), 16));
+        for i in 0..16 {
+            let aabb = Aabb::from_min_max(TUVec3::new(i * 2, 0, 0), TUVec3::new(i * 2 + 2, 4, 4));
+            tree.insert(DummyVolume::new(aabb)).unwrap();
+        }
+
+        let area = Aabb::from_min_max(TUVec3::new(5, 1, 1), TUVec3::new(13, 2, 2));
+        let what = |aabb: &Aabb<u16>| aabb.overlaps(&area);
+        let mut expected = tree.intersect_with(what);
+        let mut found: Vec<_> = tree.query_iter(what).map(|(id, _)| id).collect();
+        found.sort();
+        expected.sort();
+        assert_eq!(found, expected);
+        assert_eq!(found, (2..7).map(ElementId).collect::<Vec<_>>());
+
+        let checked = std::cell::Cell::new(0);
+        let first = tree
+            .query_iter(|aabb| {
+                checked.set(checked.get() + 1);
+                aabb.overlaps(&area)
+            })
+            .next();
+        assert!(first.is_some());
+        assert!(checked.get() < tree.nodes.len() + tree.len());
+
+        assert_eq!(tree.query_iter(|_| false).count(), 0);
+    }
+
+    #[test]
+    fn test_single_visit() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8u8), 8));
+        // Spans all 8 octants of the root and 2 deeper leaves
+        let big = tree
+            .insert(DummyVolume::new(Aabb::new_unchecked(TUVec3::splat(8), 2)))
+            .unwrap();
+        let small = tree
+            .insert(DummyVolume::new(Aabb::new_unchecked(TUVec3::splat(1), 1)))
+            .unwrap();
+        let once = vec![big, small];
+
+        let mut ids = Vec::new();
+        tree.intersect_with_for_each_with_ids(|_| true, |id, _| ids.push(id));
+        ids.sort();
+        assert_eq!(ids, once);
+
+        let mut ids = tree.intersect_with(|_| true);
+        ids.sort();
+        assert_eq!(ids, once);
+
+        let mut count = 0;
+        tree.intersect_with_for_each(|_| true, |_| count += 1);
+        assert_eq!(count, 2);
+
+        let mut count = 0;
+        tree.intersect_with_for_each_mut(|_| true, |_| count += 1);
+        assert_eq!(count, 2);
+
+        let mut count = 0;
+        tree.intersect_with_for_each_with_ids_mut(|_| true, |_, _| count += 1);
+        assert_eq!(count, 2);
+
+        let mut count = 0;
+        tree.anti_intersect_with_for_each(|_| false, |_| count += 1);
+        assert_eq!(count, 2);
+
+        let mut ids: Vec<_> = tree.query_iter(|_| true).map(|(id, _)| id).collect();
+        ids.sort();
+        assert_eq!(ids, once);
+
+        let frozen = tree.freeze();
+        let frozen = FrozenOctree::<u8>::from_bytes(&frozen).unwrap();
+        let mut ids = frozen.intersect_with(|_| true);
+        ids.sort();
+        assert_eq!(ids, once);
+
+        let compact = CompactOctree::from(tree);
+        let mut ids = compact.intersect_with(|_| true);
+        ids.sort();
+        assert_eq!(ids, once);
     }
 }
@@ -31,6 +31,30 @@
 //!
 //! Intersection methods are not available without this feature.
 //!
+//! [`NodeId`] and [`ElementId`] are `u32` by default.
+//! Enable `index-u16` feature to save memory in small trees
+//! or `index-u64` feature for trees with more than `u32::MAX` nodes.
+//!
+//! Enable `serde` feature to [`serialize`](serialization) the tree and it's bounding types.
+//! Large trees could be stored in the compact [`binary`] format.
+//! Enable `vox` feature to import and export MagicaVoxel [`models`](vox).
+//! PLY and XYZ [`point clouds`](point_cloud) could be quantized into a tree.
+//! Triangle meshes could be [`voxelized`](voxelize), enable `obj` feature to load them.
+//! Regions could be converted to and from [`dense`] arrays.
+//! Elements could be iterated in [`Morton or Hilbert`](morton) order.
+//! Face, edge and corner [`neighbors`] are found through the parent links.
+//! Nodes could keep [`aggregate`] values of their subtrees, updated on insertion and removal.
+//! Coarse [`level of detail`](lod) summaries are reduced from the children of each branch.
+//! Subdivision could be limited by the [`depth`](tree::Octree::with_max_depth) and the
+//! [`node size`](tree::Octree::with_min_size), leaves at the limit keep several elements.
+//! Node counts, depth and memory usage are reported by the tree [`stats`].
+//! Structural invariants are checked by the [`validator`](validate).
+//! Custom queries could walk the nodes with a [`Visitor`](visit::Visitor).
+//! Node hierarchy could be [`dumped`](dump) as Graphviz `DOT` or `JSON`,
+//! node bounds could be exported as a [`wireframe`] `OBJ` or `PLY` mesh.
+//! Static trees could be [`frozen`](frozen::FrozenOctree) and queried directly from a byte slice.
+//! Very sparse and deep trees could use the hashed [`linear`](linear::LinearOctree) backend.
+//!
 //! ## Optimizations:
 //!
 //! - `Unsigned` arithmetics, bitwise operations.
@@ -186,15 +210,35 @@
 
 #![allow(dead_code)]
 
+pub mod aggregate;
 #[cfg(feature = "bevy")]
 pub mod bevy_integration;
+pub mod binary;
 pub mod bounding;
+pub mod compact;
+pub mod dense;
+pub mod dump;
 mod entry;
+pub mod frozen;
 pub mod intersect_with;
+pub mod linear;
+pub mod lod;
+pub mod morton;
+pub mod neighbors;
 pub mod node;
+pub mod point_cloud;
 pub mod pool;
 pub mod prelude;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod stats;
 pub mod tree;
+pub mod validate;
+pub mod visit;
+#[cfg(feature = "vox")]
+pub mod vox;
+pub mod voxelize;
+pub mod wireframe;
 
 use bounding::{TUVec3, Unsigned};
 use prelude::Aabb;
@@ -272,10 +316,53 @@ where
     }
 }
 
+#[cfg(feature = "index-u64")]
+type RawId = u64;
+
+#[cfg(all(feature = "index-u16", not(feature = "index-u64")))]
+type RawId = u16;
+
+#[cfg(not(any(feature = "index-u16", feature = "index-u64")))]
+type RawId = u32;
+
+/// Inner type of [`NodeId`] and [`ElementId`].
+///
+/// `u32` by default. Enable the `index-u16` feature to shrink
+/// the ids of small trees or the `index-u64` feature to grow huge ones.
+/// `index-u64` takes precedence if both features are enabled.
+pub type IdType = RawId;
+
+/// Converts a pool index into an [`IdType`],
+/// failing with [`TreeError::IndexOverflow`] if it doesn't fit.
+#[inline(always)]
+pub(crate) fn try_id(index: usize) -> Result<IdType, TreeError> {
+    IdType::try_from(index).map_err(|_| {
+        TreeError::IndexOverflow(format!(
+            "Index {index} exceeds the maximum id {}",
+            IdType::MAX
+        ))
+    })
+}
+
 /// Index [`tree.nodes`](pool::Pool) with it.
 ///
 #[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-pub struct NodeId(pub u32);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct NodeId(pub IdType);
+
+impl NodeId {
+    /// Converts a pool index into a [`NodeId`].
+    ///
+    /// Fails with [`TreeError::IndexOverflow`] if the index doesn't fit into [`IdType`].
+    #[inline(always)]
+    pub fn try_from_index(index: usize) -> Result<Self, TreeError> {
+        try_id(index).map(NodeId)
+    }
+}
 
 impl From<NodeId> for ElementId {
     fn from(value: NodeId) -> Self {
@@ -289,9 +376,14 @@ impl From<NodeId> for usize {
     }
 }
 
+/// Panics if the index doesn't fit into [`IdType`].
+/// Use [`NodeId::try_from_index`] to handle the overflow.
 impl From<usize> for NodeId {
     fn from(value: usize) -> Self {
-        NodeId(value as u32)
+        match Self::try_from_index(value) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
+        }
     }
 }
 
@@ -312,7 +404,22 @@ impl fmt::Display for NodeId {
 /// let element: &TUVec3u16 = tree.get_element(ElementId(0)).unwrap();
 /// ```
 #[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
-pub struct ElementId(pub u32);
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct ElementId(pub IdType);
+
+impl ElementId {
+    /// Converts a pool index into an [`ElementId`].
+    ///
+    /// Fails with [`TreeError::IndexOverflow`] if the index doesn't fit into [`IdType`].
+    #[inline(always)]
+    pub fn try_from_index(index: usize) -> Result<Self, TreeError> {
+        try_id(index).map(ElementId)
+    }
+}
 
 impl From<ElementId> for usize {
     fn from(value: ElementId) -> Self {
@@ -320,9 +427,14 @@ impl From<ElementId> for usize {
     }
 }
 
+/// Panics if the index doesn't fit into [`IdType`].
+/// Use [`ElementId::try_from_index`] to handle the overflow.
 impl From<usize> for ElementId {
     fn from(value: usize) -> Self {
-        ElementId(value as u32)
+        match Self::try_from_index(value) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
+        }
     }
 }
 
@@ -363,6 +475,27 @@ pub enum TreeError {
 
     /// [`tree`](tree::Octree)'s garbage is corrupted.
     CorruptGarbage(String),
+
+    /// [`Pool`](pool::Pool) index doesn't fit into [`IdType`].
+    IndexOverflow(String),
+
+    /// [`tree`](tree::Octree) violates it's structural invariants.
+    InvalidStructure(String),
+
+    /// Reading or writing the [`binary`] format failed.
+    Io(String),
+
+    /// Data is not a valid [`binary`] tree.
+    InvalidData(String),
+
+    /// MagicaVoxel [`file`](vox) is not valid.
+    MalformedVox(String),
+
+    /// [`Point cloud`](point_cloud) is not valid.
+    MalformedPointCloud(String),
+
+    /// Wavefront [`mesh`](voxelize) is not valid.
+    MalformedObj(String),
 }
 
 impl Error for TreeError {}
@@ -383,26 +516,46 @@ impl fmt::Display for TreeError {
             TreeError::AlreadyOccupied(info) => write!(f, "Volume is already occupied. {info}"),
             TreeError::ElementNotFound(info) => write!(f, "Element not found. {info}"),
             TreeError::CorruptGarbage(info) => write!(f, "Tree's garbage is corrupted. {info}"),
+            TreeError::IndexOverflow(info) => write!(f, "Index overflow. {info}"),
+            TreeError::InvalidStructure(info) => write!(f, "Invalid tree structure. {info}"),
+            TreeError::Io(info) => write!(f, "IO error. {info}"),
+            TreeError::InvalidData(info) => write!(f, "Invalid data. {info}"),
+            TreeError::MalformedVox(info) => write!(f, "Malformed vox file. {info}"),
+            TreeError::MalformedPointCloud(info) => write!(f, "Malformed point cloud. {info}"),
+            TreeError::MalformedObj(info) => write!(f, "Malformed obj file. {info}"),
         }
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
 
     use super::*;
-    use bounding::Aabb;
+    use bounding::{Aabb, TUVec3u8};
     use node::NodeType;
     use rand::Rng;
     use std::collections::HashSet;
     use tree::Octree;
 
-    const RANGE: usize = 65536;
+    pub(crate) const RANGE: usize = 65536;
+
+    /// Empty tree, spanning `0..16` on every axis.
+    pub(crate) fn empty_tree<T: Volume<U = u8>>() -> Octree<u8, T> {
+        Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8))
+    }
+
+    /// Tree with points near the opposite corners.
+    pub(crate) fn corners_tree() -> Octree<u8, TUVec3u8> {
+        let mut tree = empty_tree();
+        tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+        tree.insert(TUVec3u8::new(14, 14, 14)).unwrap();
+        tree
+    }
 
     #[derive(Debug, Clone, Copy, PartialEq)]
-    struct DummyCell<U: Unsigned> {
+    pub(crate) struct DummyCell<U: Unsigned> {
         position: TUVec3<U>,
-        node: NodeId,
+        pub(crate) node: NodeId,
     }
 
     impl<U: Unsigned> Position for DummyCell<U> {
@@ -413,7 +566,7 @@ mod tests {
     }
 
     impl<U: Unsigned> DummyCell<U> {
-        fn new(position: TUVec3<U>) -> Self {
+        pub(crate) fn new(position: TUVec3<U>) -> Self {
             DummyCell {
                 position,
                 node: Default::default(),
@@ -422,8 +575,9 @@ mod tests {
     }
 
     #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-    struct DummyVolume<U: Unsigned> {
-        aabb: Aabb<U>,
+    pub(crate) struct DummyVolume<U: Unsigned> {
+        pub(crate) aabb: Aabb<U>,
+        pub(crate) weight: u32,
         node: NodeId,
     }
 
@@ -435,14 +589,27 @@ mod tests {
     }
 
     impl<U: Unsigned> DummyVolume<U> {
-        fn new(aabb: Aabb<U>) -> Self {
+        pub(crate) fn new(aabb: Aabb<U>) -> Self {
+            Self::weighted(aabb, 0)
+        }
+
+        pub(crate) fn weighted(aabb: Aabb<U>, weight: u32) -> Self {
             Self {
                 aabb,
+                weight,
                 node: Default::default(),
             }
         }
     }
 
+    /// Cube of the `size` at the `min` corner.
+    pub(crate) fn block(min: u8, size: u8, weight: u32) -> DummyVolume<u8> {
+        DummyVolume::weighted(
+            Aabb::from_min_max(TUVec3::splat(min), TUVec3::splat(min + size)),
+            weight,
+        )
+    }
+
     #[test]
     fn test_insert() {
         let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::new(4, 4, 4), 4));
@@ -557,7 +724,7 @@ mod tests {
         assert_eq!(tree.elements.len(), 0);
     }
 
-    fn random_point() -> DummyCell<usize> {
+    pub(crate) fn random_point() -> DummyCell<usize> {
         let mut rnd = rand::thread_rng();
 
         let x = rnd.gen_range(0..=RANGE);
@@ -568,6 +735,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(any(not(feature = "index-u16"), feature = "index-u64"))]
     fn test_65536() {
         let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(RANGE / 2), RANGE / 2));
 
@@ -582,7 +750,7 @@ mod tests {
         assert!(tree.elements.len() > (RANGE as f32 * 0.98) as usize);
 
         for element in 0..tree.len() {
-            let e = ElementId(element as u32);
+            let e = ElementId::from(element);
             let pos = tree.elements[e].position;
             assert_eq!(tree.find(&pos), Some(e));
             assert_eq!(tree.remove(element.into()), Ok(()));
@@ -657,7 +825,7 @@ mod tests {
     fn test_iterator() {
         let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(16), 16));
 
-        for i in 0..16u32 {
+        for i in 0..16 {
             assert_eq!(
                 tree.insert(DummyCell::new(TUVec3::splat(i))),
                 Ok(ElementId(i))
@@ -667,7 +835,7 @@ mod tests {
             assert_eq!(tree.elements.garbage_len(), 0);
         }
 
-        for i in 0..16u32 {
+        for i in 0..16 {
             assert_eq!(
                 tree.elements.iter().next().unwrap().position,
                 TUVec3::splat(i)
@@ -679,7 +847,7 @@ mod tests {
             assert_eq!(tree.elements.garbage_len(), (i + 1) as usize);
         }
 
-        for i in 0..16u32 {
+        for i in 0..16 {
             assert_eq!(
                 tree.insert(DummyCell::new(TUVec3::splat(i))),
                 Ok(ElementId(15 - i))
@@ -689,7 +857,7 @@ mod tests {
             assert_eq!(tree.elements.garbage_len(), (15 - i) as usize);
         }
 
-        for i in 0..16u32 {
+        for i in 0..16 {
             assert_eq!(
                 tree.elements.iter().next().unwrap().position,
                 TUVec3::splat(15 - i)
@@ -778,4 +946,19 @@ mod tests {
         assert!(!v1_volume.overlaps(&v2_volume));
         assert!(v1_volume.overlaps(&v3_volume));
     }
+
+    #[test]
+    #[cfg(not(feature = "index-u64"))]
+    fn test_index_overflow() {
+        let max = IdType::MAX as usize;
+        assert_eq!(ElementId::try_from_index(max), Ok(ElementId(IdType::MAX)));
+        assert!(matches!(
+            ElementId::try_from_index(max + 1),
+            Err(TreeError::IndexOverflow(_))
+        ));
+        assert!(matches!(
+            NodeId::try_from_index(max + 1),
+            Err(TreeError::IndexOverflow(_))
+        ));
+    }
 }
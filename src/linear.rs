@@ -0,0 +1,575 @@
+//! [`LinearOctree`] implementation.
+//!
+//! Pointerless octree, where every node is identified by it's [`locational code`](LocCode):
+//! a Morton key of the node's cell, prefixed with a sentinel bit marking the depth.
+//! Elements and branches are stored in hash maps by their codes,
+//! so a lookup doesn't chase the child links from the root
+//! and the neighbours are located by the code arithmetic.
+//!
+//! Suits very sparse and very deep trees, e.g. with `u64` coordinates,
+//! where the [`Octree::find`](crate::tree::Octree::find) descends up to 64 levels.
+
+use std::collections::HashMap;
+
+use num::cast;
+use smallvec::SmallVec;
+
+use crate::{
+    bounding::{Aabb, TUVec3, Unsigned},
+    morton::{compact, spread, KEY_BITS},
+    pool::{Pool, PoolElementIterator, PoolIterator},
+    ElementId, TreeError, Volume,
+};
+
+/// Locational code of a node.
+///
+/// The Morton key of the node's cell at it's depth,
+/// prefixed with a sentinel `1` bit: `1 << (3 * depth) | morton`.
+/// [`Root`](LocCode::ROOT) is `1`, it's children are `0b1000..=0b1111`, etc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LocCode(pub u128);
+
+impl LocCode {
+    /// Code of the root node.
+    pub const ROOT: LocCode = LocCode(1);
+
+    /// Creates the code of the `cell` at `depth`.
+    ///
+    /// Returns [`None`] if the `depth` exceeds [`KEY_BITS`]
+    /// or the cell coordinates don't fit into `depth` bits.
+    pub fn new(cell: [u64; 3], depth: u32) -> Option<Self> {
+        if depth > KEY_BITS || cell.iter().any(|&c| c >> depth != 0) {
+            return None;
+        }
+        let [x, y, z] = cell.map(spread);
+        Some(LocCode(1 << (3 * depth) | x | y << 1 | z << 2))
+    }
+
+    /// Returns the node's depth. Root's depth is `0`.
+    pub fn depth(self) -> u32 {
+        (127 - self.0.leading_zeros()) / 3
+    }
+
+    /// Returns the node's cell coordinates at it's depth.
+    pub fn cell(self) -> [u64; 3] {
+        let morton = self.0 ^ 1 << (3 * self.depth());
+        [compact(morton), compact(morton >> 1), compact(morton >> 2)]
+    }
+
+    /// Returns the octant index of the node in it's parent.
+    pub fn octant(self) -> usize {
+        (self.0 & 0b111) as usize
+    }
+
+    /// Returns the parent's code, or [`None`] for the root.
+    pub fn parent(self) -> Option<Self> {
+        (self.0 > 1).then_some(LocCode(self.0 >> 3))
+    }
+
+    /// Returns the code of the child in `octant`,
+    /// or [`None`] if the node is at the [`KEY_BITS`] depth.
+    pub fn child(self, octant: usize) -> Option<Self> {
+        debug_assert!(octant < 8, "Octant index out of range: {octant}");
+        (self.depth() < KEY_BITS).then_some(LocCode(self.0 << 3 | octant as u128))
+    }
+
+    /// Returns the code of the same depth node, shifted by `direction` cells.
+    ///
+    /// Returns [`None`] if the neighbour is outside of the root.
+    pub fn neighbor(self, direction: [i64; 3]) -> Option<Self> {
+        let depth = self.depth();
+        let cell = self.cell();
+        let mut neighbor = [0; 3];
+        for i in 0..3 {
+            neighbor[i] = cell[i].checked_add_signed(direction[i])?;
+        }
+        LocCode::new(neighbor, depth)
+    }
+}
+
+/// Octree, stored as hash maps of [`locational codes`](LocCode).
+///
+/// Supports the same core operations as [`Octree`](crate::tree::Octree):
+/// insertion, removing, searching and intersection with a custom closure.
+/// Element volumes should be aligned cubes, i.e. the tree nodes.
+///
+/// ```rust
+/// use oktree::{linear::LinearOctree, prelude::*};
+///
+/// let aabb = Aabb::new(TUVec3::splat(1 << 40), 1u64 << 40).unwrap();
+/// let mut tree = LinearOctree::from_aabb(aabb).unwrap();
+/// let c1_id = tree.insert(TUVec3u64::new(1, 2, 3)).unwrap();
+/// let c2_id = tree.insert(TUVec3u64::new(2, 2, 3)).unwrap();
+///
+/// assert_eq!(tree.find(&TUVec3::new(1, 2, 3)), Some(c1_id));
+///
+/// // Neighbour by the code arithmetic
+/// let code = tree.element_code(c1_id).unwrap();
+/// assert_eq!(tree.get_code(code.neighbor([1, 0, 0]).unwrap()), Some(c2_id));
+/// ```
+#[derive(Clone, Debug)]
+pub struct LinearOctree<U, T>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+{
+    /// [`Aabb`] of the root node.
+    aabb: Aabb<U>,
+
+    /// Depth of the unit cells.
+    depth: u32,
+
+    /// [`Pool`] of stored elements. Access it by [`ElementId`]
+    pub(crate) elements: Pool<T>,
+
+    /// Elements by their node's code.
+    cells: HashMap<LocCode, ElementId>,
+
+    /// Children masks of the nodes, containing elements below them.
+    branches: HashMap<LocCode, u8>,
+
+    /// Number of elements at each depth.
+    depths: [usize; KEY_BITS as usize + 1],
+}
+
+impl<U, T> LinearOctree<U, T>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+{
+    /// Construct a tree from [`Aabb`].
+    ///
+    /// `aabb` should be a cube with the power of 2 size, not larger than `2^KEY_BITS`.
+    pub fn from_aabb(aabb: Aabb<U>) -> Result<Self, TreeError> {
+        Self::from_aabb_with_capacity(aabb, 0)
+    }
+
+    /// Construct a tree from [`Aabb`] and capacity.
+    ///
+    /// `aabb` should be a cube with the power of 2 size, not larger than `2^KEY_BITS`.
+    /// Helps to reduce the amount of the memory reallocations.
+    pub fn from_aabb_with_capacity(aabb: Aabb<U>, capacity: usize) -> Result<Self, TreeError> {
+        let size = cube_size(&aabb)
+            .ok_or_else(|| TreeError::NotPower2(format!("{aabb} is not a power of 2 cube")))?;
+        let depth = size.trailing_zeros();
+        if depth > KEY_BITS {
+            return Err(TreeError::IndexOverflow(format!(
+                "{aabb} is larger than 2^{KEY_BITS}"
+            )));
+        }
+
+        Ok(LinearOctree {
+            aabb,
+            depth,
+            elements: Pool::with_capacity(capacity),
+            cells: HashMap::with_capacity(capacity),
+            branches: HashMap::with_capacity(capacity),
+            depths: [0; KEY_BITS as usize + 1],
+        })
+    }
+
+    /// Returns the [`Aabb`] of the root node.
+    pub fn aabb(&self) -> Aabb<U> {
+        self.aabb
+    }
+
+    /// Returns the depth of the unit cells.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Returns the code of the node at `depth`, containing the `point`.
+    pub fn code(&self, point: &TUVec3<U>, depth: u32) -> Option<LocCode> {
+        if depth > self.depth || !self.aabb.contains(point) {
+            return None;
+        }
+        let shift = self.depth - depth;
+        let cell = [
+            point.x - self.aabb.min.x,
+            point.y - self.aabb.min.y,
+            point.z - self.aabb.min.z,
+        ]
+        .map(|c| cast::<U, u64>(c).unwrap() >> shift);
+        LocCode::new(cell, depth)
+    }
+
+    /// Returns the [`Aabb`] of the node with `code`.
+    ///
+    /// Returns [`None`] if the `code` is deeper than the tree.
+    pub fn code_aabb(&self, code: LocCode) -> Option<Aabb<U>> {
+        let shift = self.depth.checked_sub(code.depth())?;
+        let [x, y, z] = code.cell().map(|c| cast::<u64, U>(c << shift).unwrap());
+        let min = self.aabb.min + TUVec3::new(x, y, z);
+        let size = cast::<u64, U>(1 << shift).unwrap();
+        Some(Aabb::from_min_max(min, min + TUVec3::splat(size)))
+    }
+
+    /// Returns the code of the node, exactly matching the `volume`.
+    fn volume_code(&self, volume: &Aabb<U>) -> Result<LocCode, TreeError> {
+        let inside = self.aabb.min.le(&volume.min).all() && volume.max.le(&self.aabb.max).all();
+        if !inside {
+            return Err(TreeError::OutOfTreeBounds(format!(
+                "{volume} is outside of aabb: min: {} max: {}",
+                self.aabb.min, self.aabb.max,
+            )));
+        }
+
+        let not_aligned = || TreeError::NotPower2(format!("{volume} is not an aligned cube"));
+        let size = cube_size(volume).ok_or_else(not_aligned)?;
+        let shift = size.trailing_zeros();
+        if shift > self.depth {
+            return Err(not_aligned());
+        }
+
+        let relative = [
+            volume.min.x - self.aabb.min.x,
+            volume.min.y - self.aabb.min.y,
+            volume.min.z - self.aabb.min.z,
+        ]
+        .map(|c| cast::<U, u64>(c).unwrap());
+        if relative.iter().any(|c| c & (size - 1) != 0) {
+            return Err(not_aligned());
+        }
+
+        LocCode::new(relative.map(|c| c >> shift), self.depth - shift).ok_or_else(not_aligned)
+    }
+
+    /// Insert an element into a tree.
+    ///
+    /// Element's volume should be an aligned cube, not overlapping other elements.
+    /// Returns inserted element's [`id`](ElementId)
+    pub fn insert(&mut self, elem: T) -> Result<ElementId, TreeError> {
+        let volume = elem.volume();
+        let code = self.volume_code(&volume)?;
+
+        let occupied = self.cells.contains_key(&code)
+            || self.branches.contains_key(&code)
+            || std::iter::successors(code.parent(), |c| c.parent())
+                .any(|ancestor| self.cells.contains_key(&ancestor));
+        if occupied {
+            return Err(TreeError::AlreadyOccupied(format!(
+                "Volume {volume} is already occupied"
+            )));
+        }
+
+        let element = self.elements.try_insert(elem)?;
+        self.cells.insert(code, element);
+        self.depths[code.depth() as usize] += 1;
+
+        // Mark the path, until an already existing branch
+        let mut child = code;
+        while let Some(parent) = child.parent() {
+            let mask = self.branches.entry(parent).or_insert(0);
+            let existed = *mask != 0;
+            *mask |= 1 << child.octant();
+            if existed {
+                break;
+            }
+            child = parent;
+        }
+
+        Ok(element)
+    }
+
+    /// Remove an element from the tree.
+    ///
+    /// Unmarks the branches, left without elements.
+    pub fn remove(&mut self, element: ElementId) -> Result<(), TreeError> {
+        let Some(elem) = self.elements.get(element) else {
+            return Err(TreeError::ElementNotFound(format!(
+                "Element {element} is not found"
+            )));
+        };
+
+        let code = self.volume_code(&elem.volume())?;
+        self.cells.remove(&code);
+        self.depths[code.depth() as usize] -= 1;
+
+        let mut child = code;
+        while let Some(parent) = child.parent() {
+            let mask = self.branches.get_mut(&parent).unwrap();
+            *mask &= !(1 << child.octant());
+            if *mask != 0 {
+                break;
+            }
+            self.branches.remove(&parent);
+            child = parent;
+        }
+
+        self.elements.remove(element);
+        Ok(())
+    }
+
+    /// Remove all elements from the tree.
+    pub fn clear(&mut self) {
+        self.elements.clear();
+        self.cells.clear();
+        self.branches.clear();
+        self.depths = [0; KEY_BITS as usize + 1];
+    }
+
+    /// Search for the element at the [`point`](TUVec3)
+    ///
+    /// Probes a single code per depth, occupied by any element,
+    /// so for the trees of unit elements it is a single hash lookup.
+    /// Returns element's [`id`](ElementId) or [`None`] if elements if not found.
+    pub fn find(&self, point: &TUVec3<U>) -> Option<ElementId> {
+        (0..=self.depth)
+            .rev()
+            .filter(|&depth| self.depths[depth as usize] != 0)
+            .find_map(|depth| self.get_code(self.code(point, depth)?))
+    }
+
+    /// Returns the element, occupying the node with `code`.
+    pub fn get_code(&self, code: LocCode) -> Option<ElementId> {
+        self.cells.get(&code).copied()
+    }
+
+    /// Returns the code of the node, occupied by the `element`.
+    pub fn element_code(&self, element: ElementId) -> Option<LocCode> {
+        self.volume_code(&self.elements.get(element)?.volume()).ok()
+    }
+
+    /// Intersect [`LinearOctree`] with a custom intersection closure.
+    ///
+    /// Returns the [`vector`](Vec) of [`elements`](ElementId),
+    /// intersected by volume.
+    pub fn intersect_with<F>(&self, what: F) -> Vec<ElementId>
+    where
+        F: Fn(&Aabb<U>) -> bool,
+    {
+        let mut elements = Vec::with_capacity(10);
+        self.intersect_with_for_each_with_ids(what, |e, _| elements.push(e));
+        elements
+    }
+
+    /// Intersect [`LinearOctree`] with a custom intersection closure.
+    ///
+    /// Each element that intersects with the volume is passed to the supplied closure.
+    pub fn intersect_with_for_each_with_ids<F, F2>(&self, what: F, mut actor: F2)
+    where
+        F: Fn(&Aabb<U>) -> bool,
+        F2: FnMut(ElementId, &T),
+    {
+        let mut stack: SmallVec<[LocCode; 32]> = SmallVec::new();
+        stack.push(LocCode::ROOT);
+        while let Some(code) = stack.pop() {
+            if let Some(&e) = self.cells.get(&code) {
+                let element = &self.elements[e];
+                if what(&element.volume()) {
+                    actor(e, element);
+                }
+            } else if let Some(&mask) = self.branches.get(&code) {
+                if self.code_aabb(code).is_some_and(|aabb| what(&aabb)) {
+                    for i in (0..8).filter(|i| mask & 1 << i != 0) {
+                        stack.push(LocCode(code.0 << 3 | i as u128));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the element if element exists and not garbaged.
+    pub fn get_element(&self, element: ElementId) -> Option<&T> {
+        self.elements.get(element)
+    }
+
+    /// Returns the element if element exists and not garbaged.
+    ///
+    /// Element's volume should not be changed.
+    pub fn get_element_mut(&mut self, element: ElementId) -> Option<&mut T> {
+        self.elements.get_mut(element)
+    }
+
+    /// Returns the element at the `point`.
+    pub fn get(&self, point: &TUVec3<U>) -> Option<&T> {
+        self.elements.get(self.find(point)?)
+    }
+
+    /// Returns the number of actual elements in the tree
+    pub fn len(&self) -> usize {
+        self.elements.len()
+    }
+
+    /// Is the tree empty
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Returns an iterator over the elements in the tree.
+    pub fn iter(&self) -> PoolIterator<'_, T> {
+        self.elements.iter()
+    }
+
+    /// Returns an iterator over the elements in the tree.
+    pub fn iter_elements(&self) -> PoolElementIterator<'_, T> {
+        self.elements.iter_elements()
+    }
+}
+
+/// Returns the edge length of a power of 2 cube.
+fn cube_size<U: Unsigned>(aabb: &Aabb<U>) -> Option<u64> {
+    if !aabb.min.le(&aabb.max).all() {
+        return None;
+    }
+    let [x, y, z] = [
+        aabb.max.x - aabb.min.x,
+        aabb.max.y - aabb.min.y,
+        aabb.max.z - aabb.min.z,
+    ];
+    let size = cast::<U, u64>(x)?;
+    (x == y && y == z && size.is_power_of_two()).then_some(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::{TUVec3u64, TUVec3u8};
+    use crate::tests::DummyVolume;
+
+    #[test]
+    fn test_loc_code() {
+        assert_eq!(LocCode::ROOT.depth(), 0);
+        assert_eq!(LocCode::ROOT.parent(), None);
+
+        let code = LocCode::new([5, 2, 7], 3).unwrap();
+        assert_eq!(code.depth(), 3);
+        assert_eq!(code.cell(), [5, 2, 7]);
+        assert_eq!(code.octant(), 0b101);
+        assert_eq!(code.parent().unwrap().cell(), [2, 1, 3]);
+        assert_eq!(code.parent().unwrap().child(code.octant()), Some(code));
+
+        assert_eq!(code.neighbor([1, 0, 0]).unwrap().cell(), [6, 2, 7]);
+        assert_eq!(code.neighbor([-1, 1, -7]).unwrap().cell(), [4, 3, 0]);
+        assert_eq!(code.neighbor([0, 0, 1]), None);
+        assert_eq!(code.neighbor([0, -3, 0]), None);
+
+        let deepest = LocCode::new([(1 << KEY_BITS) - 1, 0, 1], KEY_BITS).unwrap();
+        assert_eq!(deepest.depth(), KEY_BITS);
+        assert_eq!(deepest.cell(), [(1 << KEY_BITS) - 1, 0, 1]);
+        assert_eq!(deepest.child(0), None);
+        assert_eq!(LocCode::new([8, 0, 0], 3), None);
+        assert_eq!(LocCode::new([0, 0, 0], KEY_BITS + 1), None);
+    }
+
+    #[test]
+    fn test_from_aabb() {
+        let aabb = Aabb::new_unchecked(TUVec3::splat(1u64 << 41), 1 << 41);
+        assert_eq!(
+            LinearOctree::<u64, TUVec3u64>::from_aabb(aabb)
+                .unwrap()
+                .depth(),
+            42
+        );
+
+        let aabb = Aabb::new_unchecked(TUVec3::splat(1u64 << 42), 1 << 42);
+        assert!(matches!(
+            LinearOctree::<u64, TUVec3u64>::from_aabb(aabb),
+            Err(TreeError::IndexOverflow(_))
+        ));
+
+        let aabb = Aabb::from_min_max(TUVec3::new(0u8, 0, 0), TUVec3::new(8, 8, 4));
+        assert!(matches!(
+            LinearOctree::<u8, TUVec3u8>::from_aabb(aabb),
+            Err(TreeError::NotPower2(_))
+        ));
+    }
+
+    #[test]
+    fn test_code_aabb() {
+        let tree =
+            LinearOctree::<u8, TUVec3u8>::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8))
+                .unwrap();
+        assert_eq!(
+            tree.code_aabb(LocCode::ROOT),
+            Some(Aabb::new_unchecked(TUVec3::splat(8), 8))
+        );
+        assert_eq!(
+            tree.code_aabb(LocCode::new([1, 0, 1], 1).unwrap()),
+            Some(Aabb::from_min_max(
+                TUVec3::new(8, 0, 8),
+                TUVec3::new(16, 8, 16)
+            ))
+        );
+        assert_eq!(tree.code_aabb(LocCode::new([0, 0, 0], 5).unwrap()), None);
+    }
+
+    #[test]
+    fn test_insert_find_remove() {
+        let mut tree = LinearOctree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8)).unwrap();
+
+        let c1 = tree.insert(TUVec3u8::new(1, 2, 3)).unwrap();
+        let c2 = tree.insert(TUVec3u8::new(15, 15, 15)).unwrap();
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree.find(&TUVec3::new(1, 2, 3)), Some(c1));
+        assert_eq!(tree.find(&TUVec3::new(15, 15, 15)), Some(c2));
+        assert_eq!(tree.find(&TUVec3::new(1, 2, 4)), None);
+        assert_eq!(tree.find(&TUVec3::new(16, 0, 0)), None);
+
+        assert!(matches!(
+            tree.insert(TUVec3u8::new(1, 2, 3)),
+            Err(TreeError::AlreadyOccupied(_))
+        ));
+        assert!(matches!(
+            tree.insert(TUVec3u8::new(16, 2, 3)),
+            Err(TreeError::OutOfTreeBounds(_))
+        ));
+
+        // Volumes are nodes
+        let block = DummyVolume::new(Aabb::from_min_max(TUVec3::splat(4u8), TUVec3::splat(8)));
+        let mut blocks = LinearOctree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8)).unwrap();
+        let b1 = blocks.insert(block).unwrap();
+        assert_eq!(blocks.element_code(b1).unwrap().depth(), 2);
+        assert_eq!(blocks.find(&TUVec3::new(5, 6, 7)), Some(b1));
+
+        let inner = DummyVolume::new(Aabb::from_min_max(TUVec3::splat(6), TUVec3::splat(7)));
+        assert!(matches!(
+            blocks.insert(inner),
+            Err(TreeError::AlreadyOccupied(_))
+        ));
+        let outer = DummyVolume::new(Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(8)));
+        assert!(matches!(
+            blocks.insert(outer.clone()),
+            Err(TreeError::AlreadyOccupied(_))
+        ));
+        let unaligned = DummyVolume::new(Aabb::from_min_max(TUVec3::splat(2), TUVec3::splat(6)));
+        assert!(matches!(
+            blocks.insert(unaligned),
+            Err(TreeError::NotPower2(_))
+        ));
+
+        // Removing unmarks the empty branches
+        assert_eq!(tree.remove(c1), Ok(()));
+        assert_eq!(tree.find(&TUVec3::new(1, 2, 3)), None);
+        assert!(matches!(
+            tree.remove(c1),
+            Err(TreeError::ElementNotFound(_))
+        ));
+        assert_eq!(tree.branches.len(), 4);
+        assert_eq!(tree.remove(c2), Ok(()));
+        assert!(tree.branches.is_empty());
+        assert!(tree.is_empty());
+
+        assert_eq!(blocks.remove(b1), Ok(()));
+        assert!(blocks.insert(outer).is_ok());
+    }
+
+    #[test]
+    fn test_intersect_with() {
+        let aabb = Aabb::new_unchecked(TUVec3::splat(8), 8);
+        let mut linear = LinearOctree::from_aabb(aabb).unwrap();
+        let mut tree = crate::tree::Octree::from_aabb(aabb);
+        for i in 0..16 {
+            let c = TUVec3u8::new(i, (i * 3) % 16, (i * 7) % 16);
+            assert_eq!(linear.insert(c), tree.insert(c));
+        }
+
+        let region = Aabb::from_min_max(TUVec3::new(2, 0, 3), TUVec3::new(9, 12, 16));
+        let mut expected = tree.intersect_with(|aabb| aabb.overlaps(&region));
+        let mut found = linear.intersect_with(|aabb| aabb.overlaps(&region));
+        expected.sort();
+        found.sort();
+        assert_eq!(found, expected);
+        assert!(!found.is_empty());
+    }
+}
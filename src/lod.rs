@@ -0,0 +1,390 @@
+//! Level of detail.
+//!
+//! [`Octree::build_lod`] summarises every branch from it's eight children
+//! with a user reduce function, bottom up.
+//! [`Lod::iter_at_depth`] then yields the real elements above the depth
+//! and the summarised values of the branches at the depth.
+//!
+//! ```rust
+//! use oktree::{lod::LodValue, prelude::*};
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(4), 4).unwrap());
+//! tree.insert(TUVec3u8::new(0, 0, 0)).unwrap();
+//! tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(6, 6, 6)).unwrap();
+//!
+//! // Summarise the children by their first element
+//! let lod = tree.build_lod(|children| children.into_iter().flatten().next().copied());
+//!
+//! let coarse: Vec<_> = lod
+//!     .iter_at_depth(1, &Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(8)))
+//!     .map(|value| match value {
+//!         LodValue::Element(_, e) => (false, e.0),
+//!         LodValue::Summary(_, e) => (true, e.0),
+//!     })
+//!     .collect();
+//!
+//! assert_eq!(coarse, [(true, TUVec3::splat(0)), (false, TUVec3::splat(6))]);
+//! ```
+
+use std::array::from_fn;
+
+use smallvec::SmallVec;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, TUVec3, Unsigned},
+    node::NodeType,
+    tree::Octree,
+    ElementId, NodeId, Volume,
+};
+
+/// Summarised values of the tree's branches.
+///
+/// Built by [`Octree::build_lod`].
+/// Borrows the tree, so it couldn't be changed while the summaries are in use.
+pub struct Lod<'tree, U, T, A = ()>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    tree: &'tree Octree<U, T, A>,
+
+    /// Summaries, indexed by [`NodeId`]. Only branches have them.
+    summaries: Vec<Option<T>>,
+}
+
+/// Value, yielded by [`Lod::iter_at_depth`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LodValue<'tree, U: Unsigned, T> {
+    /// Element, stored in a leaf at or above the requested depth.
+    Element(ElementId, &'tree T),
+
+    /// Summary of the branch at the requested depth with it's [`Aabb`].
+    Summary(Aabb<U>, &'tree T),
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Summarises every branch of the tree with the `reduce` function.
+    ///
+    /// `reduce` receives the children in the octant order.
+    /// A child is represented by it's element if it's a leaf,
+    /// by it's summary if it's a branch or a [`bucket`](NodeType::Bucket),
+    /// or [`None`] if it's empty.
+    /// Branches are reduced bottom up, so each branch is reduced once.
+    /// Buckets are reduced from their elements, up to 8 at a time.
+    pub fn build_lod(
+        &self,
+        mut reduce: impl FnMut([Option<&T>; 8]) -> Option<T>,
+    ) -> Lod<'_, U, T, A> {
+        let mut summaries: Vec<Option<T>> = Vec::new();
+        summaries.resize_with(self.nodes.vec.len(), || None);
+
+        // Post-order traversal. Branch is reduced when it's popped the second time.
+        let mut stack: SmallVec<[(NodeId, bool); 32]> = SmallVec::new();
+        stack.push((self.root, false));
+        while let Some((node, visited)) = stack.pop() {
+            let branch = match self.nodes[node].ntype {
+                NodeType::Branch(branch) => branch,
+                NodeType::Bucket => {
+                    let elements = self.bucket(node).iter().map(|&e| &self.elements[e]);
+                    summaries[usize::from(node)] = reduce_all(elements, &mut reduce);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if visited {
+                let children = branch
+                    .children()
+                    .map(|child| match self.nodes[child].ntype {
+                        NodeType::Empty => None,
+                        NodeType::Leaf(e) => Some(&self.elements[e]),
+                        NodeType::Bucket | NodeType::Branch(_) => {
+                            summaries[usize::from(child)].as_ref()
+                        }
+                    });
+                summaries[usize::from(node)] = reduce(children);
+            } else {
+                stack.push((node, true));
+                for child in branch.children() {
+                    stack.push((child, false));
+                }
+            }
+        }
+
+        Lod {
+            tree: self,
+            summaries,
+        }
+    }
+}
+
+impl<U, T, A> Lod<'_, U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Returns the summary of the branch or the bucket `node`.
+    pub fn get(&self, node: NodeId) -> Option<&T> {
+        self.summaries.get(usize::from(node))?.as_ref()
+    }
+
+    /// Returns an iterator over the tree, cut at the `depth`, inside the `aabb`.
+    ///
+    /// Root has depth `0`. Branches at the `depth` yield their summaries,
+    /// branches without a summary are skipped.
+    /// Leaves above or at the `depth` yield their elements.
+    /// Volumes, stored in several leaves, are yielded once.
+    pub fn iter_at_depth(&self, depth: usize, aabb: &Aabb<U>) -> LodIter<'_, U, T, A> {
+        let mut stack = SmallVec::new();
+        stack.push((self.tree.root, 0));
+
+        // Part of the aabb inside the tree. Empty if they don't overlap.
+        let root = self.tree.nodes[self.tree.root].aabb;
+        let min = root.clipped_min(aabb);
+        let max = TUVec3::new(
+            aabb.max.x.min(root.max.x).max(min.x),
+            aabb.max.y.min(root.max.y).max(min.y),
+            aabb.max.z.min(root.max.z).max(min.z),
+        );
+        LodIter {
+            lod: self,
+            depth,
+            aabb: Aabb::from_min_max(min, max),
+            stack,
+            pending: SmallVec::new(),
+        }
+    }
+}
+
+/// Iterator over the tree, cut at the depth.
+///
+/// Returned by [`Lod::iter_at_depth`].
+pub struct LodIter<'lod, U, T, A = ()>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    lod: &'lod Lod<'lod, U, T, A>,
+    depth: usize,
+
+    /// Requested aabb, clipped to the tree
+    aabb: Aabb<U>,
+    stack: SmallVec<[(NodeId, usize); 32]>,
+
+    /// Elements of the current bucket
+    pending: SmallVec<[ElementId; 8]>,
+}
+
+impl<'lod, U, T, A> Iterator for LodIter<'lod, U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    type Item = LodValue<'lod, U, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.lod.tree;
+        if let Some(e) = self.pending.pop() {
+            return Some(LodValue::Element(e, &tree.elements[e]));
+        }
+
+        while let Some((node, depth)) = self.stack.pop() {
+            let n = &tree.nodes[node];
+            if !n.aabb.overlaps(&self.aabb) {
+                continue;
+            }
+
+            match n.ntype {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(e) => {
+                    let element = &tree.elements[e];
+                    if yields(&n.aabb, &element.volume(), &self.aabb) {
+                        return Some(LodValue::Element(e, element));
+                    }
+                }
+
+                NodeType::Bucket => {
+                    self.pending.extend(
+                        tree.bucket(node)
+                            .iter()
+                            .rev()
+                            .copied()
+                            .filter(|&e| yields(&n.aabb, &tree.elements[e].volume(), &self.aabb)),
+                    );
+                    if let Some(e) = self.pending.pop() {
+                        return Some(LodValue::Element(e, &tree.elements[e]));
+                    }
+                }
+
+                NodeType::Branch(branch) if depth < self.depth => {
+                    self.stack.extend(
+                        branch
+                            .children()
+                            .into_iter()
+                            .rev()
+                            .map(|child| (child, depth + 1)),
+                    );
+                }
+
+                NodeType::Branch(_) => {
+                    if let Some(summary) = self.lod.get(node) {
+                        return Some(LodValue::Summary(n.aabb, summary));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Reduces the `items`, up to 8 at a time, until a single value is left.
+fn reduce_all<'a, T: 'a>(
+    items: impl Iterator<Item = &'a T>,
+    reduce: &mut impl FnMut([Option<&T>; 8]) -> Option<T>,
+) -> Option<T> {
+    let items: Vec<&T> = items.collect();
+    let mut level: Vec<T> = items
+        .chunks(8)
+        .filter_map(|chunk| reduce(from_fn(|i| chunk.get(i).copied())))
+        .collect();
+
+    while level.len() > 1 {
+        level = level
+            .chunks(8)
+            .filter_map(|chunk| reduce(from_fn(|i| chunk.get(i))))
+            .collect();
+    }
+    level.pop()
+}
+
+/// Does the leaf `node` yield the `volume`, overlapping the `aabb`.
+///
+/// Volumes are yielded by the leaf, containing the min corner of their part inside the aabb.
+/// The `aabb` should be clipped to the tree, so this corner is inside of some leaf.
+fn yields<U: Unsigned>(node: &Aabb<U>, volume: &Aabb<U>, aabb: &Aabb<U>) -> bool {
+    volume.overlaps(aabb) && node.contains(&aabb.clipped_min(volume))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{block, empty_tree, DummyVolume};
+
+    /// Sums the weights into a block covering the children.
+    fn sum(children: [Option<&DummyVolume<u8>>; 8]) -> Option<DummyVolume<u8>> {
+        let mut children = children.into_iter().flatten();
+        let first = children.next()?.clone();
+        Some(children.fold(first, |acc, b| {
+            let min = TUVec3::new(
+                acc.aabb.min.x.min(b.aabb.min.x),
+                acc.aabb.min.y.min(b.aabb.min.y),
+                acc.aabb.min.z.min(b.aabb.min.z),
+            );
+            let max = TUVec3::new(
+                acc.aabb.max.x.max(b.aabb.max.x),
+                acc.aabb.max.y.max(b.aabb.max.y),
+                acc.aabb.max.z.max(b.aabb.max.z),
+            );
+            DummyVolume::weighted(Aabb::from_min_max(min, max), acc.weight + b.weight)
+        }))
+    }
+
+    #[test]
+    fn test_build_lod() {
+        let mut tree = empty_tree();
+        tree.insert(block(0, 1, 1)).unwrap();
+        tree.insert(block(1, 1, 2)).unwrap();
+        tree.insert(block(3, 1, 4)).unwrap();
+        tree.insert(block(12, 4, 8)).unwrap();
+
+        let mut calls = 0;
+        let lod = tree.build_lod(|children| {
+            calls += 1;
+            sum(children)
+        });
+
+        let branches = tree
+            .iter_nodes()
+            .filter(|n| matches!(n.ntype, NodeType::Branch(_)))
+            .count();
+        assert_eq!(calls, branches);
+        assert_eq!(lod.get(tree.root).map(|b| b.weight), Some(15));
+
+        let all = Aabb::new_unchecked(TUVec3::splat(8), 8);
+        let weights = |depth| {
+            lod.iter_at_depth(depth, &all)
+                .map(|value| match value {
+                    LodValue::Element(_, b) => (false, b.weight),
+                    LodValue::Summary(_, b) => (true, b.weight),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(weights(0), [(true, 15)]);
+        assert_eq!(weights(1), [(true, 7), (false, 8)]);
+        assert_eq!(weights(2), [(true, 7), (false, 8)]);
+        assert_eq!(weights(3), [(true, 3), (false, 4), (false, 8)]);
+        assert_eq!(weights(8), [(false, 1), (false, 2), (false, 4), (false, 8)]);
+    }
+
+    #[test]
+    fn test_iter_at_depth_aabb() {
+        let mut tree = empty_tree();
+        for i in 0..8 {
+            tree.insert(block(i, 1, i as u32)).unwrap();
+        }
+        tree.insert(block(15, 1, 0)).unwrap();
+        // Spans 8 leaves
+        tree.insert(block(10, 4, 100)).unwrap();
+
+        let lod = tree.build_lod(sum);
+        let region = Aabb::from_min_max(TUVec3::splat(4), TUVec3::splat(12));
+        let weights = |depth| {
+            lod.iter_at_depth(depth, &region)
+                .map(|value| match value {
+                    LodValue::Element(_, b) => (false, b.weight),
+                    LodValue::Summary(_, b) => (true, b.weight),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(weights(2), [(true, 22), (false, 100)]);
+        assert_eq!(
+            weights(16),
+            [(false, 4), (false, 5), (false, 6), (false, 7), (false, 100)]
+        );
+    }
+
+    #[test]
+    fn test_iter_at_depth_outside() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(24), 8));
+        tree.insert(block(10, 10, 3)).unwrap();
+        tree.insert(block(24, 2, 1)).unwrap();
+
+        let lod = tree.build_lod(sum);
+        let region = Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(64));
+        let weights: Vec<_> = lod
+            .iter_at_depth(8, &region)
+            .map(|value| match value {
+                LodValue::Element(_, b) => b.weight,
+                LodValue::Summary(_, b) => b.weight,
+            })
+            .collect();
+        assert_eq!(weights, [3, 1]);
+
+        let outside = Aabb::from_min_max(TUVec3::splat(40), TUVec3::splat(64));
+        assert_eq!(lod.iter_at_depth(8, &outside).count(), 0);
+    }
+}
@@ -0,0 +1,483 @@
+//! Morton (Z-order) and Hilbert spatial keys.
+//!
+//! Keys are [`u128`], interleaving 3 coordinates of up to [`KEY_BITS`] bits each.
+//! Coordinates of any [`Unsigned`] type are accepted,
+//! as long as they fit into [`KEY_BITS`], otherwise [`TreeError::IndexOverflow`] is returned.
+//!
+//! Morton bit order matches the octant index `x | y << 1 | z << 2`,
+//! so visiting the children `0..7` depth first yields the elements in Z-order.
+//!
+//! ```rust
+//! use oktree::{morton::{morton_decode, morton_encode}, prelude::*};
+//!
+//! let code = morton_encode(TUVec3::new(1u8, 2, 3)).unwrap();
+//! assert_eq!(code, 0b110_101);
+//! assert_eq!(morton_decode::<u8>(code).unwrap(), TUVec3::new(1, 2, 3));
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(0, 0, 0)).unwrap();
+//! tree.insert(TUVec3u8::new(1, 0, 0)).unwrap();
+//!
+//! let ordered: Vec<_> = tree.iter_morton().map(|(_, e)| e.0).collect();
+//! assert_eq!(ordered, [TUVec3::new(0, 0, 0), TUVec3::new(1, 0, 0), TUVec3::new(1, 1, 1)]);
+//! ```
+
+use std::cmp::Ordering;
+
+use num::cast;
+use smallvec::SmallVec;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{TUVec3, Unsigned},
+    node::NodeType,
+    tree::Octree,
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Maximum number of bits per coordinate in a key.
+pub const KEY_BITS: u32 = 42;
+
+/// `SPREAD[b]` places the bits of `b` into every third bit.
+const SPREAD: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut b = 0;
+    while b < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            table[b] |= ((b as u32 >> bit) & 1) << (3 * bit);
+            bit += 1;
+        }
+        b += 1;
+    }
+    table
+};
+
+pub(crate) fn spread(mut v: u64) -> u128 {
+    let mut result = 0;
+    let mut shift = 0;
+    while v != 0 {
+        result |= (SPREAD[(v & 0xff) as usize] as u128) << shift;
+        v >>= 8;
+        shift += 24;
+    }
+    result
+}
+
+pub(crate) fn compact(code: u128) -> u64 {
+    let mut result = 0;
+    for bit in 0..KEY_BITS {
+        result |= (((code >> (3 * bit)) & 1) as u64) << bit;
+    }
+    result
+}
+
+fn to_bits<U: Unsigned>(position: TUVec3<U>, bits: u32) -> Result<[u64; 3], TreeError> {
+    let limit = 1u64 << bits;
+    let mut result = [0; 3];
+    for (r, c) in result.iter_mut().zip([position.x, position.y, position.z]) {
+        *r = cast::<U, u64>(c).filter(|&c| c < limit).ok_or_else(|| {
+            TreeError::IndexOverflow(format!(
+                "Coordinate {c} of {position} doesn't fit into {bits} bits"
+            ))
+        })?;
+    }
+    Ok(result)
+}
+
+fn from_bits<U: Unsigned>(coordinates: [u64; 3]) -> Result<TUVec3<U>, TreeError> {
+    let [x, y, z] = coordinates.map(cast::<u64, U>);
+    match (x, y, z) {
+        (Some(x), Some(y), Some(z)) => Ok(TUVec3::new(x, y, z)),
+        _ => Err(TreeError::IndexOverflow(format!(
+            "Coordinates {coordinates:?} don't fit into {}",
+            std::any::type_name::<U>()
+        ))),
+    }
+}
+
+fn check_bits(bits: u32) -> Result<(), TreeError> {
+    if bits == 0 || bits > KEY_BITS {
+        return Err(TreeError::IndexOverflow(format!(
+            "Key bits {bits} are out of 1..={KEY_BITS}"
+        )));
+    }
+    Ok(())
+}
+
+/// Encodes the `position` into the Morton key.
+///
+/// `x` occupies the lowest bit of every triple, `z` the highest.
+/// Returns [`TreeError::IndexOverflow`] if a coordinate doesn't fit into [`KEY_BITS`].
+pub fn morton_encode<U: Unsigned>(position: TUVec3<U>) -> Result<u128, TreeError> {
+    let [x, y, z] = to_bits(position, KEY_BITS)?;
+    Ok(spread(x) | spread(y) << 1 | spread(z) << 2)
+}
+
+/// Decodes the Morton key into a position.
+///
+/// Returns [`TreeError::IndexOverflow`] if the `code` exceeds `3 * KEY_BITS` bits
+/// or a coordinate doesn't fit into `U`.
+pub fn morton_decode<U: Unsigned>(code: u128) -> Result<TUVec3<U>, TreeError> {
+    if code >> (3 * KEY_BITS) != 0 {
+        return Err(TreeError::IndexOverflow(format!(
+            "Morton code {code} exceeds {} bits",
+            3 * KEY_BITS
+        )));
+    }
+    from_bits([compact(code), compact(code >> 1), compact(code >> 2)])
+}
+
+/// Encodes the `position` into the Hilbert key of a `2^bits` sized cube.
+///
+/// Uses the John Skilling's transpose algorithm.
+/// Returns [`TreeError::IndexOverflow`] if `bits` are out of `1..=KEY_BITS`
+/// or a coordinate doesn't fit into `bits`.
+pub fn hilbert_encode<U: Unsigned>(position: TUVec3<U>, bits: u32) -> Result<u128, TreeError> {
+    check_bits(bits)?;
+    let mut x = to_bits(position, bits)?;
+    let m = 1u64 << (bits - 1);
+
+    // Inverse undo
+    let mut q = m;
+    while q > 1 {
+        let p = q - 1;
+        for i in 0..3 {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q >>= 1;
+    }
+
+    // Gray encode
+    x[1] ^= x[0];
+    x[2] ^= x[1];
+    let mut t = 0;
+    let mut q = m;
+    while q > 1 {
+        if x[2] & q != 0 {
+            t ^= q - 1;
+        }
+        q >>= 1;
+    }
+    x.iter_mut().for_each(|x| *x ^= t);
+
+    // First axis is the most significant in every triple
+    Ok(spread(x[2]) | spread(x[1]) << 1 | spread(x[0]) << 2)
+}
+
+/// Decodes the Hilbert key of a `2^bits` sized cube into a position.
+///
+/// Returns [`TreeError::IndexOverflow`] if `bits` are out of `1..=KEY_BITS`,
+/// the `code` exceeds `3 * bits` bits or a coordinate doesn't fit into `U`.
+pub fn hilbert_decode<U: Unsigned>(code: u128, bits: u32) -> Result<TUVec3<U>, TreeError> {
+    check_bits(bits)?;
+    if code >> (3 * bits) != 0 {
+        return Err(TreeError::IndexOverflow(format!(
+            "Hilbert code {code} exceeds {} bits",
+            3 * bits
+        )));
+    }
+
+    let mut x = [compact(code >> 2), compact(code >> 1), compact(code)];
+    let n = 2u64 << (bits - 1);
+
+    // Gray decode
+    let t = x[2] >> 1;
+    x[2] ^= x[1];
+    x[1] ^= x[0];
+    x[0] ^= t;
+
+    // Undo excess work
+    let mut q = 2;
+    while q != n {
+        let p = q - 1;
+        for i in (0..3).rev() {
+            if x[i] & q != 0 {
+                x[0] ^= p;
+            } else {
+                let t = (x[0] ^ x[i]) & p;
+                x[0] ^= t;
+                x[i] ^= t;
+            }
+        }
+        q <<= 1;
+    }
+
+    from_bits(x)
+}
+
+/// Compares the positions in Z-order without encoding them,
+/// so the coordinates of any size are accepted.
+pub(crate) fn morton_cmp<U: Unsigned>(a: &TUVec3<U>, b: &TUVec3<U>) -> Ordering {
+    let a = [a.z, a.y, a.x].map(|c| cast::<U, u128>(c).unwrap());
+    let b = [b.z, b.y, b.x].map(|c| cast::<U, u128>(c).unwrap());
+
+    // The axis with the most significant differing bit decides, `z` wins the ties
+    let mut axis = 0;
+    let mut most = 0;
+    for i in 0..3 {
+        let diff = a[i] ^ b[i];
+        if most < diff && most < (most ^ diff) {
+            axis = i;
+            most = diff;
+        }
+    }
+    a[axis].cmp(&b[axis])
+}
+
+/// Iterator over the tree elements in Z-order.
+///
+/// Returned by [`Octree::iter_morton`].
+pub struct MortonIter<'tree, U, T, A = ()>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    tree: &'tree Octree<U, T, A>,
+    stack: SmallVec<[NodeId; 32]>,
+
+    /// Elements of the current bucket in reversed order
+    pending: SmallVec<[ElementId; 8]>,
+}
+
+impl<'tree, U, T, A> Iterator for MortonIter<'tree, U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    type Item = (ElementId, &'tree T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.pending.pop() {
+            return Some((e, &self.tree.elements[e]));
+        }
+
+        let root = self.tree.nodes[self.tree.root].aabb;
+        let min = |element: &T| root.clipped_min(&element.volume());
+        while let Some(node) = self.stack.pop() {
+            let n = self.tree.nodes[node];
+            match n.ntype {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(e) => {
+                    // Volumes, stored in several leaves, are yielded by the one containing
+                    // their min corner, clamped to the tree
+                    let element = &self.tree.elements[e];
+                    if n.aabb.contains(&min(element)) {
+                        return Some((e, element));
+                    }
+                }
+
+                NodeType::Bucket => {
+                    let elements = &self.tree.elements;
+                    self.pending.extend(
+                        self.tree
+                            .bucket(node)
+                            .iter()
+                            .copied()
+                            .filter(|&e| n.aabb.contains(&min(&elements[e]))),
+                    );
+                    self.pending
+                        .sort_by(|&a, &b| morton_cmp(&min(&elements[b]), &min(&elements[a])));
+                    if let Some(e) = self.pending.pop() {
+                        return Some((e, &elements[e]));
+                    }
+                }
+
+                NodeType::Branch(branch) => {
+                    self.stack.extend(branch.children().into_iter().rev());
+                }
+            }
+        }
+        None
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Returns an iterator over the elements in Z-order.
+    ///
+    /// Children are traversed depth first in the octant order `0..7`,
+    /// so point elements are ordered by their [`morton_encode`] keys.
+    /// Volumes are ordered by their min corners' leaves and yielded once.
+    /// Min corners of the volumes, sticking out of the tree, are clamped to it.
+    pub fn iter_morton(&self) -> MortonIter<'_, U, T, A> {
+        let mut stack = SmallVec::new();
+        stack.push(self.root);
+        MortonIter {
+            tree: self,
+            stack,
+            pending: SmallVec::new(),
+        }
+    }
+
+    /// Returns an iterator over the elements in Hilbert curve order.
+    ///
+    /// Elements are ordered by the [`hilbert_encode`] keys of their min corners,
+    /// clamped to the tree and relative to the tree's min corner.
+    /// Trees larger than `2^KEY_BITS` are ordered by the `KEY_BITS` most significant bits,
+    /// elements of the same key keep the Z-order.
+    pub fn iter_hilbert(&self) -> impl Iterator<Item = (ElementId, &T)> {
+        let aabb = self.nodes[self.root].aabb;
+        let size = cast::<U, u128>(aabb.size()).unwrap();
+        let bits = (u128::BITS - size.saturating_sub(1).leading_zeros()).max(1);
+        let shift = bits.saturating_sub(KEY_BITS);
+        let bits = bits.min(KEY_BITS);
+
+        let key = |element: &T| {
+            let min = aabb.clipped_min(&element.volume());
+            let [x, y, z] = [min.x - aabb.min.x, min.y - aabb.min.y, min.z - aabb.min.z]
+                .map(|c| c >> cast(shift).unwrap());
+            hilbert_encode(TUVec3::new(x, y, z), bits).unwrap()
+        };
+
+        let mut elements: Vec<_> = self.iter_morton().map(|(e, t)| (key(t), e, t)).collect();
+        elements.sort_by_key(|(key, _, _)| *key);
+        elements.into_iter().map(|(_, e, t)| (e, t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::{Aabb, TUVec3u8};
+    use crate::tests::{empty_tree, DummyVolume};
+
+    #[test]
+    fn test_morton() {
+        assert_eq!(morton_encode(TUVec3::new(0u8, 0, 0)), Ok(0));
+        assert_eq!(morton_encode(TUVec3::new(1u8, 0, 0)), Ok(0b001));
+        assert_eq!(morton_encode(TUVec3::new(0u8, 1, 0)), Ok(0b010));
+        assert_eq!(morton_encode(TUVec3::new(0u8, 0, 1)), Ok(0b100));
+        assert_eq!(morton_encode(TUVec3::new(255u8, 0, 0)), Ok(0o11111111));
+
+        let max = (1u128 << KEY_BITS) - 1;
+        let position = TUVec3::new(max, 0, max);
+        let code = morton_encode(position).unwrap();
+        assert_eq!(code.count_ones(), 2 * KEY_BITS);
+        assert_eq!(morton_decode::<u128>(code), Ok(position));
+
+        for position in [
+            TUVec3::new(12345u64, 0, 678),
+            TUVec3::new(1 << 40, (1 << 41) + 7, 3),
+        ] {
+            let code = morton_encode(position).unwrap();
+            assert_eq!(morton_decode::<u64>(code), Ok(position));
+        }
+
+        assert!(matches!(
+            morton_encode(TUVec3::new(1u128 << KEY_BITS, 0, 0)),
+            Err(TreeError::IndexOverflow(_))
+        ));
+        assert!(matches!(
+            morton_decode::<u128>(1 << (3 * KEY_BITS)),
+            Err(TreeError::IndexOverflow(_))
+        ));
+        assert!(matches!(
+            morton_decode::<u8>(1 << 24),
+            Err(TreeError::IndexOverflow(_))
+        ));
+    }
+
+    #[test]
+    fn test_hilbert() {
+        for bits in 1..=3 {
+            let side = 1u8 << bits;
+            let mut positions = vec![None; 1 << (3 * bits)];
+            for x in 0..side {
+                for y in 0..side {
+                    for z in 0..side {
+                        let position = TUVec3::new(x, y, z);
+                        let code = hilbert_encode(position, bits).unwrap();
+                        assert_eq!(hilbert_decode::<u8>(code, bits), Ok(position));
+                        positions[code as usize] = Some(position);
+                    }
+                }
+            }
+
+            // Every step of the curve is a unit step
+            let positions: Vec<_> = positions.into_iter().map(Option::unwrap).collect();
+            for pair in positions.windows(2) {
+                let [a, b] = [pair[0], pair[1]];
+                let distance = a.x.abs_diff(b.x) + a.y.abs_diff(b.y) + a.z.abs_diff(b.z);
+                assert_eq!(distance, 1, "{a} -> {b}");
+            }
+        }
+
+        let position = TUVec3::new(3u64 << 40, 1, 1 << 41);
+        let code = hilbert_encode(position, KEY_BITS).unwrap();
+        assert_eq!(hilbert_decode::<u64>(code, KEY_BITS), Ok(position));
+
+        assert!(hilbert_encode(TUVec3::new(4u8, 0, 0), 2).is_err());
+        assert!(hilbert_encode(TUVec3::new(0u8, 0, 0), 0).is_err());
+        assert!(hilbert_decode::<u8>(1 << 6, 2).is_err());
+    }
+
+    #[test]
+    fn test_iter_morton() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        let mut positions = Vec::new();
+        for i in 0..16 {
+            let position = TUVec3::new(i, (i * 7) % 16, (i * 5) % 16);
+            tree.insert(TUVec3u8(position)).unwrap();
+            positions.push(position);
+        }
+
+        positions.sort_by_key(|p| morton_encode(*p).unwrap());
+        let ordered: Vec<_> = tree.iter_morton().map(|(_, e)| e.0).collect();
+        assert_eq!(ordered, positions);
+
+        positions.sort_by_key(|p| hilbert_encode(*p, 4).unwrap());
+        let ordered: Vec<_> = tree.iter_hilbert().map(|(_, e)| e.0).collect();
+        assert_eq!(ordered, positions);
+
+        for (e, element) in tree.iter_morton() {
+            assert_eq!(tree.find(&element.0), Some(e));
+        }
+    }
+
+    #[test]
+    fn test_iter_morton_volumes() {
+        let mut tree = empty_tree();
+        let small = tree
+            .insert(DummyVolume::new(Aabb::new_unchecked(TUVec3::splat(1), 1)))
+            .unwrap();
+        let large = tree
+            .insert(DummyVolume::new(Aabb::new_unchecked(TUVec3::splat(8), 4)))
+            .unwrap();
+
+        let ordered: Vec<_> = tree.iter_morton().map(|(e, _)| e).collect();
+        assert_eq!(ordered, [small, large]);
+        assert_eq!(tree.iter_hilbert().count(), 2);
+
+        // Min corner outside of the tree
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(24u8), 8));
+        let outside = tree
+            .insert(DummyVolume::new(Aabb::from_min_max(
+                TUVec3::splat(10),
+                TUVec3::splat(20),
+            )))
+            .unwrap();
+        let inside = tree
+            .insert(DummyVolume::new(Aabb::new_unchecked(TUVec3::splat(30), 1)))
+            .unwrap();
+
+        let ordered: Vec<_> = tree.iter_morton().map(|(e, _)| e).collect();
+        assert_eq!(ordered, [outside, inside]);
+        let ordered: Vec<_> = tree.iter_hilbert().map(|(e, _)| e).collect();
+        assert_eq!(ordered, [outside, inside]);
+    }
+}
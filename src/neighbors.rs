@@ -0,0 +1,351 @@
+//! Node and element neighbour finding.
+//!
+//! Neighbours are located by ascending the [`parent`](crate::node::Node::parent) links
+//! from the starting node to the closest common ancestor and descending back,
+//! rather than searching from the root.
+//!
+//! ```rust
+//! use oktree::{neighbors::Connectivity, prelude::*};
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! let c1_id = tree.insert(TUVec3u8::new(4, 4, 4)).unwrap();
+//! let c2_id = tree.insert(TUVec3u8::new(5, 4, 4)).unwrap();
+//! let c3_id = tree.insert(TUVec3u8::new(5, 5, 4)).unwrap();
+//!
+//! assert_eq!(tree.neighbors(c1_id, Connectivity::Face6), Ok(vec![c2_id]));
+//! assert_eq!(tree.neighbors(c1_id, Connectivity::Edge18), Ok(vec![c2_id, c3_id]));
+//! ```
+
+use smallvec::SmallVec;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, TUVec3, Unsigned},
+    node::NodeType,
+    tree::Octree,
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Which cells are considered adjacent.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Connectivity {
+    /// Sharing a face.
+    #[default]
+    Face6,
+
+    /// Sharing a face or an edge.
+    Edge18,
+
+    /// Sharing a face, an edge or a corner.
+    Vertex26,
+}
+
+impl Connectivity {
+    /// Returns the unit directions to the adjacent cells.
+    ///
+    /// Ordered by `z`, then `y`, then `x` from `-1` to `1`.
+    pub fn directions(self) -> impl Iterator<Item = [i8; 3]> {
+        let max = match self {
+            Connectivity::Face6 => 1,
+            Connectivity::Edge18 => 2,
+            Connectivity::Vertex26 => 3,
+        };
+
+        (0..27).filter_map(move |i| {
+            let direction = [i % 3 - 1, i / 3 % 3 - 1, i / 9 - 1];
+            let nonzero = direction.iter().filter(|&&d| d != 0).count();
+            (nonzero != 0 && nonzero <= max).then_some(direction)
+        })
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Returns the adjacent node of equal or larger size in the `direction`.
+    ///
+    /// `direction` components should be `-1`, `0` or `1`.
+    /// Ascends the parent links, until the ancestor contains the adjacent cell,
+    /// then descends towards it, stopping at the node of the same size or at a larger leaf.
+    ///
+    /// Returns [`None`] if the `node` doesn't exist or the adjacent cell is outside of the tree.
+    pub fn neighbor_node(&self, node: NodeId, direction: [i8; 3]) -> Option<NodeId> {
+        debug_assert!(
+            direction.iter().all(|d| (-1..=1).contains(d)),
+            "Direction out of range: {direction:?}"
+        );
+
+        let n = self.nodes.get(node)?;
+        let target = self.adjacent(&n.aabb, n.aabb.size(), direction)?;
+
+        let mut current = self.ascend(node, &target);
+        loop {
+            let c = self.nodes[current];
+            match c.ntype {
+                NodeType::Branch(branch) if c.aabb.size() > n.aabb.size() => {
+                    current = branch.find_child(&target.min, c.aabb.center());
+                }
+                _ => return Some(current),
+            }
+        }
+    }
+
+    /// Returns the elements, adjacent to the `element` with the given `connectivity`.
+    ///
+    /// Volume elements are adjacent to all the elements along their faces, edges or corners.
+    /// Every neighbour is returned once, in the order of [`Connectivity::directions`].
+    ///
+    /// Returns [`TreeError::ElementNotFound`] if the `element` doesn't exist.
+    pub fn neighbors(
+        &self,
+        element: ElementId,
+        connectivity: Connectivity,
+    ) -> Result<Vec<ElementId>, TreeError> {
+        let Some(elem) = self.elements.get(element) else {
+            return Err(TreeError::ElementNotFound(format!(
+                "Element {element} is not found"
+            )));
+        };
+
+        let volume = elem.volume();
+        let leaf = self.leaf_containing(&volume.min);
+
+        let mut neighbors = Vec::new();
+        for direction in connectivity.directions() {
+            let Some(region) = self.adjacent(&volume, U::one(), direction) else {
+                continue;
+            };
+
+            let mut stack: SmallVec<[NodeId; 32]> = SmallVec::new();
+            stack.push(self.ascend(leaf, &region));
+            while let Some(node) = stack.pop() {
+                let n = self.nodes[node];
+                match n.ntype {
+                    NodeType::Empty => (),
+
+                    NodeType::Leaf(_) | NodeType::Bucket => {
+                        for &e in self.leaf_elements(node) {
+                            if e != element
+                                && !neighbors.contains(&e)
+                                && self.elements[e].volume().overlaps(&region)
+                            {
+                                neighbors.push(e);
+                            }
+                        }
+                    }
+
+                    NodeType::Branch(branch) => {
+                        for child in branch.children().into_iter().rev() {
+                            if self.nodes[child].aabb.overlaps(&region) {
+                                stack.push(child);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(neighbors)
+    }
+
+    /// Returns the region, adjacent to the `volume` in `direction`.
+    /// It's extent along the moving axes is `step`.
+    ///
+    /// Returns [`None`] if the region is outside of the tree.
+    fn adjacent(&self, volume: &Aabb<U>, step: U, direction: [i8; 3]) -> Option<Aabb<U>> {
+        let root = self.nodes[self.root].aabb;
+        let components = |v: TUVec3<U>| [v.x, v.y, v.z];
+        let [min, max] = [volume.min, volume.max].map(components);
+        let [root_min, root_max] = [root.min, root.max].map(components);
+
+        let [x, y, z] = [0, 1, 2].map(|i| match direction[i] {
+            -1 if min[i] > root_min[i] => Some((min[i] - step, min[i])),
+            0 => Some((min[i], max[i])),
+            1 if max[i] < root_max[i] => Some((max[i], max[i] + step)),
+            _ => None,
+        });
+        let ((x0, x1), (y0, y1), (z0, z1)) = (x?, y?, z?);
+
+        Some(Aabb::from_min_max(
+            TUVec3::new(x0, y0, z0),
+            TUVec3::new(x1, y1, z1),
+        ))
+    }
+
+    /// Ascends from the `node` to the first ancestor, containing the `region`.
+    fn ascend(&self, mut node: NodeId, region: &Aabb<U>) -> NodeId {
+        loop {
+            let n = self.nodes[node];
+            let contains = n.aabb.min.le(&region.min).all() && region.max.le(&n.aabb.max).all();
+            match n.parent {
+                Some(parent) if !contains => node = parent,
+                _ => return node,
+            }
+        }
+    }
+
+    /// Descends from the root to the leaf, containing the `point`.
+    fn leaf_containing(&self, point: &TUVec3<U>) -> NodeId {
+        let mut node = self.root;
+        while let NodeType::Branch(branch) = self.nodes[node].ntype {
+            node = branch.find_child(point, self.nodes[node].aabb.center());
+        }
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::TUVec3u8;
+    use crate::tests::{empty_tree, DummyVolume};
+
+    fn cube() -> (Octree<u8, TUVec3u8>, Vec<ElementId>) {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        let mut ids = Vec::new();
+        for z in 0..3 {
+            for y in 0..3 {
+                for x in 0..3 {
+                    ids.push(tree.insert(TUVec3u8::new(x + 2, y + 2, z + 2)).unwrap());
+                }
+            }
+        }
+        (tree, ids)
+    }
+
+    #[test]
+    fn test_directions() {
+        assert_eq!(Connectivity::Face6.directions().count(), 6);
+        assert_eq!(Connectivity::Edge18.directions().count(), 18);
+        assert_eq!(Connectivity::Vertex26.directions().count(), 26);
+        assert_eq!(Connectivity::Face6.directions().next(), Some([0, 0, -1]));
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let (tree, ids) = cube();
+        let center = ids[13];
+
+        let faces = tree.neighbors(center, Connectivity::Face6).unwrap();
+        assert_eq!(
+            faces,
+            vec![ids[4], ids[10], ids[12], ids[14], ids[16], ids[22]]
+        );
+
+        assert_eq!(
+            tree.neighbors(center, Connectivity::Edge18).unwrap().len(),
+            18
+        );
+
+        let mut all = tree.neighbors(center, Connectivity::Vertex26).unwrap();
+        all.sort();
+        let mut expected = ids.clone();
+        expected.remove(13);
+        assert_eq!(all, expected);
+
+        // Corner
+        assert_eq!(
+            tree.neighbors(ids[0], Connectivity::Face6).unwrap().len(),
+            3
+        );
+        assert_eq!(
+            tree.neighbors(ids[0], Connectivity::Edge18).unwrap().len(),
+            6
+        );
+        assert_eq!(
+            tree.neighbors(ids[0], Connectivity::Vertex26)
+                .unwrap()
+                .len(),
+            7
+        );
+
+        let mut tree = tree;
+        tree.remove(center).unwrap();
+        assert!(matches!(
+            tree.neighbors(center, Connectivity::Face6),
+            Err(TreeError::ElementNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_neighbors_at_bounds() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        let c1 = tree.insert(TUVec3u8::new(0, 0, 0)).unwrap();
+        let c2 = tree.insert(TUVec3u8::new(0, 1, 0)).unwrap();
+        let c3 = tree.insert(TUVec3u8::new(15, 15, 15)).unwrap();
+        assert_eq!(tree.neighbors(c1, Connectivity::Vertex26), Ok(vec![c2]));
+        assert_eq!(tree.neighbors(c3, Connectivity::Vertex26), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_neighbors_volumes() {
+        let mut tree = empty_tree();
+        let block = tree
+            .insert(DummyVolume::new(Aabb::from_min_max(
+                TUVec3::splat(4),
+                TUVec3::splat(8),
+            )))
+            .unwrap();
+        let face = tree
+            .insert(DummyVolume::new(Aabb::from_min_max(
+                TUVec3::new(8, 6, 5),
+                TUVec3::new(9, 7, 6),
+            )))
+            .unwrap();
+        let corner = tree
+            .insert(DummyVolume::new(Aabb::from_min_max(
+                TUVec3::splat(3),
+                TUVec3::splat(4),
+            )))
+            .unwrap();
+        tree.insert(DummyVolume::new(Aabb::from_min_max(
+            TUVec3::splat(10),
+            TUVec3::splat(11),
+        )))
+        .unwrap();
+
+        assert_eq!(tree.neighbors(block, Connectivity::Face6), Ok(vec![face]));
+        assert_eq!(
+            tree.neighbors(block, Connectivity::Vertex26),
+            Ok(vec![corner, face])
+        );
+        assert_eq!(tree.neighbors(face, Connectivity::Face6), Ok(vec![block]));
+    }
+
+    #[test]
+    fn test_neighbor_node() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        tree.insert(TUVec3u8::new(0, 0, 0)).unwrap();
+        tree.insert(TUVec3u8::new(1, 0, 0)).unwrap();
+
+        let leaf = tree.leaf_containing(&TUVec3::new(0, 0, 0));
+        assert_eq!(tree.nodes[leaf].aabb.size(), 1);
+
+        // Sibling of the same size
+        let right = tree.neighbor_node(leaf, [1, 0, 0]).unwrap();
+        assert_eq!(tree.nodes[right].aabb.min, TUVec3::new(1, 0, 0));
+
+        // Larger empty node
+        let up = tree.neighbor_node(leaf, [0, 1, 1]).unwrap();
+        assert_eq!(tree.nodes[up].aabb.min, TUVec3::new(0, 1, 1));
+        let far = tree.neighbor_node(right, [1, 1, 1]).unwrap();
+        assert_eq!(
+            tree.nodes[far].aabb,
+            Aabb::from_min_max(TUVec3::new(2, 0, 0), TUVec3::new(4, 2, 2))
+        );
+
+        // Outside of the tree
+        assert_eq!(tree.neighbor_node(leaf, [-1, 0, 0]), None);
+        assert_eq!(tree.neighbor_node(tree.root, [0, 1, 0]), None);
+
+        // From a larger node towards a subdivided one
+        let large = tree.neighbor_node(far, [-1, 0, 0]).unwrap();
+        assert_eq!(
+            tree.nodes[large].aabb,
+            Aabb::from_min_max(TUVec3::splat(0), TUVec3::splat(2))
+        );
+    }
+}
@@ -1,11 +1,12 @@
 //! [`Node`] implementation.
 
 use core::fmt;
+use std::array::from_fn;
 
 use crate::{
     bounding::{Aabb, TUVec3, Unsigned},
     pool::Pool,
-    ElementId, NodeId,
+    ElementId, IdType, NodeId,
 };
 
 /// [`Octree's`](crate::tree::Octree) node.
@@ -14,30 +15,42 @@ use crate::{
 /// and can be one of the following types:
 /// - [`NodeType::Empty`]. Empty node.
 /// - [`NodeType::Leaf`]. Node, containig a single [`ElementId`].
+/// - [`NodeType::Bucket`]. Node, containig several elements.
 /// - [`NodeType::Branch`]. Node, containig a 8 child nodes.
+///
+/// Trees with an [`Aggregate`](crate::aggregate::Aggregate) also keep
+/// the aggregated value of the node's subtree.
 #[derive(Clone, Copy, Debug)]
-pub struct Node<U: Unsigned> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Node<U: Unsigned, A = ()> {
     pub aabb: Aabb<U>,
     pub ntype: NodeType,
     pub parent: Option<NodeId>,
+
+    /// Aggregated value of the elements in the node's subtree.
+    /// It is recomputed after deserialization.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub aggregate: A,
 }
 
-impl<U: Unsigned> Default for Node<U> {
+impl<U: Unsigned, A: Default> Default for Node<U, A> {
     fn default() -> Self {
         Node {
             aabb: Aabb::<U>::default(),
             ntype: Default::default(),
             parent: Default::default(),
+            aggregate: Default::default(),
         }
     }
 }
 
-impl<U: Unsigned> Node<U> {
-    pub(crate) fn from_aabb(aabb: Aabb<U>, parent: Option<NodeId>) -> Self {
+impl<U: Unsigned, A> Node<U, A> {
+    pub(crate) fn from_aabb(aabb: Aabb<U>, parent: Option<NodeId>, aggregate: A) -> Self {
         Node {
             aabb,
+            ntype: NodeType::Empty,
             parent,
-            ..Default::default()
+            aggregate,
         }
     }
 }
@@ -45,12 +58,19 @@ impl<U: Unsigned> Node<U> {
 /// [`Node`] types.
 /// - [`NodeType::Empty`]. Empty node.
 /// - [`NodeType::Leaf`]. Node, containig a single [`ElementId`].
+/// - [`NodeType::Bucket`]. Node, containig several elements.
 /// - [`NodeType::Branch`]. Node, containig a 8 child nodes.
 #[derive(Default, Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum NodeType {
     #[default]
     Empty,
     Leaf(ElementId),
+
+    /// Leaf at the tree's [`max depth`](crate::tree::Octree::with_max_depth)
+    /// or [`min size`](crate::tree::Octree::with_min_size), holding several elements.
+    /// Elements are listed by [`Octree::bucket`](crate::tree::Octree::bucket).
+    Bucket,
     Branch(Branch),
 }
 
@@ -59,6 +79,7 @@ impl fmt::Display for NodeType {
         match self {
             NodeType::Empty => write!(f, "NodeType: Empty"),
             NodeType::Leaf(e) => write!(f, "NodeType: Leaf({e})"),
+            NodeType::Bucket => write!(f, "NodeType: Bucket"),
             NodeType::Branch(branch) => write!(f, "NodeType: Branch({:?})", branch),
         }
     }
@@ -66,153 +87,98 @@ impl fmt::Display for NodeType {
 
 /// Branch, containig a link to a 8 child [`nodes`](Node).
 ///
+/// Children are allocated as a single contiguous block in the [`Pool`],
+/// so only the id of the first child is stored.
+/// Child `i` lives at `first_child + i`, where `i = x | y << 1 | z << 2`.
+///
 /// Contained by [`branch`](NodeType::Branch) nodes.
 #[derive(Default, Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Branch {
-    pub children: [NodeId; 8],
+    pub first_child: NodeId,
 }
 
 impl Branch {
-    pub(crate) fn new(children: [NodeId; 8]) -> Self {
-        Branch { children }
+    pub(crate) fn new(first_child: NodeId) -> Self {
+        Branch { first_child }
+    }
+
+    /// Returns the id of the child in octant `i`.
+    #[inline(always)]
+    pub fn child(&self, i: usize) -> NodeId {
+        debug_assert!(i < 8, "Octant index out of range: {i}");
+        NodeId(self.first_child.0 + i as IdType)
+    }
+
+    /// Returns the ids of all 8 children.
+    #[inline(always)]
+    pub fn children(&self) -> [NodeId; 8] {
+        from_fn(|i| self.child(i))
     }
 
     #[inline(always)]
     pub fn x0_y0_z0(&self) -> NodeId {
-        self.children[0]
+        self.child(0)
     }
 
     #[inline(always)]
     pub fn x1_y0_z0(&self) -> NodeId {
-        self.children[1]
+        self.child(1)
     }
 
     #[inline(always)]
     pub fn x0_y1_z0(&self) -> NodeId {
-        self.children[2]
+        self.child(2)
     }
 
     #[inline(always)]
     pub fn x1_y1_z0(&self) -> NodeId {
-        self.children[3]
+        self.child(3)
     }
 
     #[inline(always)]
     pub fn x0_y0_z1(&self) -> NodeId {
-        self.children[4]
+        self.child(4)
     }
 
     #[inline(always)]
     pub fn x1_y0_z1(&self) -> NodeId {
-        self.children[5]
+        self.child(5)
     }
 
     #[inline(always)]
     pub fn x0_y1_z1(&self) -> NodeId {
-        self.children[6]
+        self.child(6)
     }
 
     #[inline(always)]
     pub fn x1_y1_z1(&self) -> NodeId {
-        self.children[7]
+        self.child(7)
     }
 
     #[inline]
-    pub fn center<U: Unsigned>(&self, nodes: &Pool<Node<U>>) -> TUVec3<U> {
-        let node = nodes[self.x0_y0_z0()];
-        node.aabb.max
+    pub fn center<U: Unsigned, A>(&self, nodes: &Pool<Node<U, A>, NodeId>) -> TUVec3<U> {
+        nodes[self.x0_y0_z0()].aabb.max
     }
 
     #[inline]
-    pub(crate) fn walk_children_inclusive<U: Unsigned>(
+    pub(crate) fn walk_children_inclusive<U: Unsigned, A>(
         &self,
-        nodes: &Pool<Node<U>>,
+        nodes: &Pool<Node<U, A>, NodeId>,
         aabb: &Aabb<U>,
         mut f: impl FnMut(NodeId),
     ) {
-        let branch_center = self.center(nodes);
-        if aabb.min.x <= branch_center.x {
-            if aabb.min.y <= branch_center.y {
-                if aabb.min.z <= branch_center.z {
-                    f(self.x0_y0_z0());
-                }
-                if aabb.max.z >= branch_center.z {
-                    f(self.x0_y0_z1());
-                }
-            }
-            if aabb.max.y >= branch_center.y {
-                if aabb.min.z <= branch_center.z {
-                    f(self.x0_y1_z0());
-                }
-                if aabb.max.z >= branch_center.z {
-                    f(self.x0_y1_z1());
-                }
-            }
-        }
-        if aabb.max.x >= branch_center.x {
-            if aabb.min.y <= branch_center.y {
-                if aabb.min.z <= branch_center.z {
-                    f(self.x1_y0_z0());
-                }
-                if aabb.max.z >= branch_center.z {
-                    f(self.x1_y0_z1());
-                }
-            }
-            if aabb.max.y >= branch_center.y {
-                if aabb.min.z <= branch_center.z {
-                    f(self.x1_y1_z0());
-                }
-                if aabb.max.z >= branch_center.z {
-                    f(self.x1_y1_z1());
-                }
-            }
-        }
+        walk_octants_inclusive(self.center(nodes), aabb, |i| f(self.child(i)));
     }
 
     #[inline]
-    pub(crate) fn walk_children_exclusive<U: Unsigned>(
+    pub(crate) fn walk_children_exclusive<U: Unsigned, A>(
         &self,
-        nodes: &Pool<Node<U>>,
+        nodes: &Pool<Node<U, A>, NodeId>,
         aabb: &Aabb<U>,
         mut f: impl FnMut(NodeId),
     ) {
-        let branch_center = self.center(nodes);
-        if aabb.min.x < branch_center.x {
-            if aabb.min.y < branch_center.y {
-                if aabb.min.z < branch_center.z {
-                    f(self.x0_y0_z0());
-                }
-                if aabb.max.z > branch_center.z {
-                    f(self.x0_y0_z1());
-                }
-            }
-            if aabb.max.y > branch_center.y {
-                if aabb.min.z < branch_center.z {
-                    f(self.x0_y1_z0());
-                }
-                if aabb.max.z > branch_center.z {
-                    f(self.x0_y1_z1());
-                }
-            }
-        }
-        if aabb.max.x > branch_center.x {
-            if aabb.min.y < branch_center.y {
-                if aabb.min.z < branch_center.z {
-                    f(self.x1_y0_z0());
-                }
-                if aabb.max.z > branch_center.z {
-                    f(self.x1_y0_z1());
-                }
-            }
-            if aabb.max.y > branch_center.y {
-                if aabb.min.z < branch_center.z {
-                    f(self.x1_y1_z0());
-                }
-                if aabb.max.z > branch_center.z {
-                    f(self.x1_y1_z1());
-                }
-            }
-        }
+        walk_octants_exclusive(self.center(nodes), aabb, |i| f(self.child(i)));
     }
 
     /// Search which octant is suitable for the position.
@@ -221,12 +187,111 @@ impl Branch {
     /// * `center`: center of the current node's [`Aabb`]
     #[inline(always)]
     pub fn find_child<U: Unsigned>(&self, position: &TUVec3<U>, center: TUVec3<U>) -> NodeId {
-        let x = if position.x < center.x { 0 } else { 1 };
-        let y = if position.y < center.y { 0 } else { 1 };
-        let z = if position.z < center.z { 0 } else { 1 };
+        self.child(octant(position, center))
+    }
+}
 
-        let idx = x | y << 1 | z << 2;
+/// Search which octant index is suitable for the position.
+///
+/// * `position`: Element's position
+/// * `center`: center of the current node's [`Aabb`]
+#[inline(always)]
+pub(crate) fn octant<U: Unsigned>(position: &TUVec3<U>, center: TUVec3<U>) -> usize {
+    let x = if position.x < center.x { 0 } else { 1 };
+    let y = if position.y < center.y { 0 } else { 1 };
+    let z = if position.z < center.z { 0 } else { 1 };
+
+    x | y << 1 | z << 2
+}
+
+/// Calls `f` with the index of every octant around `center`,
+/// touched or overlapped by the `aabb`.
+#[inline]
+pub(crate) fn walk_octants_inclusive<U: Unsigned>(
+    center: TUVec3<U>,
+    aabb: &Aabb<U>,
+    mut f: impl FnMut(usize),
+) {
+    if aabb.min.x <= center.x {
+        if aabb.min.y <= center.y {
+            if aabb.min.z <= center.z {
+                f(0);
+            }
+            if aabb.max.z >= center.z {
+                f(4);
+            }
+        }
+        if aabb.max.y >= center.y {
+            if aabb.min.z <= center.z {
+                f(2);
+            }
+            if aabb.max.z >= center.z {
+                f(6);
+            }
+        }
+    }
+    if aabb.max.x >= center.x {
+        if aabb.min.y <= center.y {
+            if aabb.min.z <= center.z {
+                f(1);
+            }
+            if aabb.max.z >= center.z {
+                f(5);
+            }
+        }
+        if aabb.max.y >= center.y {
+            if aabb.min.z <= center.z {
+                f(3);
+            }
+            if aabb.max.z >= center.z {
+                f(7);
+            }
+        }
+    }
+}
 
-        self.children[idx]
+/// Calls `f` with the index of every octant around `center`,
+/// overlapped by the `aabb`.
+#[inline]
+pub(crate) fn walk_octants_exclusive<U: Unsigned>(
+    center: TUVec3<U>,
+    aabb: &Aabb<U>,
+    mut f: impl FnMut(usize),
+) {
+    if aabb.min.x < center.x {
+        if aabb.min.y < center.y {
+            if aabb.min.z < center.z {
+                f(0);
+            }
+            if aabb.max.z > center.z {
+                f(4);
+            }
+        }
+        if aabb.max.y > center.y {
+            if aabb.min.z < center.z {
+                f(2);
+            }
+            if aabb.max.z > center.z {
+                f(6);
+            }
+        }
+    }
+    if aabb.max.x > center.x {
+        if aabb.min.y < center.y {
+            if aabb.min.z < center.z {
+                f(1);
+            }
+            if aabb.max.z > center.z {
+                f(5);
+            }
+        }
+        if aabb.max.y > center.y {
+            if aabb.min.z < center.z {
+                f(3);
+            }
+            if aabb.max.z > center.z {
+                f(7);
+            }
+        }
     }
 }
@@ -0,0 +1,797 @@
+//! Point cloud import and export.
+//!
+//! [`read_ply`] and [`read_xyz`] load floating point [`points`](Point).
+//! [`quantize`] places them into a tree, using a voxel [`Grid`],
+//! merging the points that fall into the same cell.
+//! [`write_ply`] exports the occupied cells for the standard viewers.
+//!
+//! ```rust
+//! use oktree::{point_cloud::{quantize, read_xyz, Grid}, prelude::*};
+//!
+//! #[derive(Debug)]
+//! struct Cell {
+//!     position: TUVec3<u16>,
+//!     count: usize,
+//! }
+//!
+//! impl Position for Cell {
+//!     type U = u16;
+//!     fn position(&self) -> TUVec3<u16> {
+//!         self.position
+//!     }
+//! }
+//!
+//! let points = read_xyz("0.1 0.1 0.1\n0.4 0.2 0.3\n2.5 0.5 0.5\n".as_bytes()).unwrap();
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap());
+//! let grid = Grid::new([0.0; 3], 0.5);
+//! let stats = quantize(
+//!     &mut tree,
+//!     &grid,
+//!     points,
+//!     |position, _| Cell { position, count: 1 },
+//!     |cell, _| cell.count += 1,
+//! )
+//! .unwrap();
+//!
+//! assert_eq!(stats.cells, 2);
+//! assert_eq!(tree.get(&TUVec3::new(0, 0, 0)).unwrap().count, 2);
+//! ```
+
+use std::io::{Read, Write};
+
+use num::cast;
+
+use crate::{
+    bounding::{TUVec3, Unsigned},
+    entry::Entry,
+    tree::Octree,
+    Position, TreeError, Volume,
+};
+
+/// Point of a point cloud.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    pub position: [f64; 3],
+    pub color: Option<[u8; 3]>,
+}
+
+/// Voxel grid, mapping the world space points to the tree cells.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    /// World position of the `(0, 0, 0)` cell's corner.
+    pub origin: [f64; 3],
+
+    /// Edge length of a cell.
+    pub voxel_size: f64,
+}
+
+impl Grid {
+    pub fn new(origin: [f64; 3], voxel_size: f64) -> Self {
+        Grid { origin, voxel_size }
+    }
+
+    /// Returns the cell, containing the `point`.
+    ///
+    /// Returns [`None`] if the cell's coordinates are negative or don't fit into `U`.
+    pub fn cell<U: Unsigned>(&self, point: [f64; 3]) -> Option<TUVec3<U>> {
+        let [x, y, z] = [0, 1, 2].map(|i| {
+            let c = ((point[i] - self.origin[i]) / self.voxel_size).floor();
+            cast::<f64, U>(c)
+        });
+        Some(TUVec3::new(x?, y?, z?))
+    }
+
+    /// Returns the world position of the cell's center.
+    pub fn center<U: Unsigned>(&self, cell: TUVec3<U>) -> [f64; 3] {
+        let c: [f64; 3] = [cell.x, cell.y, cell.z].map(|c| cast(c).unwrap());
+        [0, 1, 2].map(|i| self.origin[i] + (c[i] + 0.5) * self.voxel_size)
+    }
+}
+
+/// Result of the [`quantize`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quantized {
+    /// Number of the inserted cells.
+    pub cells: usize,
+
+    /// Number of the points, merged into the existing cells.
+    pub merged: usize,
+
+    /// Number of the points outside of the grid or the tree.
+    pub skipped: usize,
+}
+
+/// Inserts the `points` into the `tree`.
+///
+/// Each point is mapped to a cell of the `grid`.
+/// The first point of a cell is converted into an element by `new`,
+/// following points are merged into it by `reduce`.
+/// Points outside of the grid or the tree are skipped.
+pub fn quantize<U, T>(
+    tree: &mut Octree<U, T>,
+    grid: &Grid,
+    points: impl IntoIterator<Item = Point>,
+    mut new: impl FnMut(TUVec3<U>, &Point) -> T,
+    mut reduce: impl FnMut(&mut T, &Point),
+) -> Result<Quantized, TreeError>
+where
+    U: Unsigned,
+    T: Position<U = U>,
+{
+    let aabb = tree.nodes[tree.root].aabb;
+    let mut stats = Quantized::default();
+    for point in points {
+        let cell = match grid.cell(point.position) {
+            Some(cell) if aabb.contains(&cell) => cell,
+            _ => {
+                stats.skipped += 1;
+                continue;
+            }
+        };
+
+        match tree.entry(cell) {
+            Entry::Occupied(mut entry) => {
+                reduce(&mut entry, &point);
+                stats.merged += 1;
+            }
+            Entry::Vacant(entry) => {
+                entry.try_insert(new(cell, &point))?;
+                stats.cells += 1;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+fn malformed(info: impl Into<String>) -> TreeError {
+    TreeError::MalformedPointCloud(info.into())
+}
+
+fn read_all(mut reader: impl Read) -> Result<Vec<u8>, TreeError> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|err| TreeError::Io(err.to_string()))?;
+    Ok(bytes)
+}
+
+fn parse<F: std::str::FromStr>(token: Option<&str>, line: usize) -> Result<F, TreeError> {
+    let token = token.ok_or_else(|| malformed(format!("Missing value at line {line}")))?;
+    token
+        .parse()
+        .map_err(|_| malformed(format!("Invalid value {token:?} at line {line}")))
+}
+
+/// Reads an `XYZ` text point cloud.
+///
+/// Each line is `x y z` or `x y z r g b`, separated by spaces, tabs or commas.
+/// Empty lines and lines starting with `#` are skipped.
+pub fn read_xyz(reader: impl Read) -> Result<Vec<Point>, TreeError> {
+    let bytes = read_all(reader)?;
+    let text = std::str::from_utf8(&bytes).map_err(|err| malformed(err.to_string()))?;
+
+    let mut points = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut tokens = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|t| !t.is_empty());
+        let position = [
+            parse(tokens.next(), i + 1)?,
+            parse(tokens.next(), i + 1)?,
+            parse(tokens.next(), i + 1)?,
+        ];
+        let color = match tokens.next() {
+            None => None,
+            r => Some([
+                parse(r, i + 1)?,
+                parse(tokens.next(), i + 1)?,
+                parse(tokens.next(), i + 1)?,
+            ]),
+        };
+
+        points.push(Point { position, color });
+    }
+
+    Ok(points)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Self, TreeError> {
+        Ok(match name {
+            "char" | "int8" => Scalar::I8,
+            "uchar" | "uint8" => Scalar::U8,
+            "short" | "int16" => Scalar::I16,
+            "ushort" | "uint16" => Scalar::U16,
+            "int" | "int32" => Scalar::I32,
+            "uint" | "uint32" => Scalar::U32,
+            "float" | "float32" => Scalar::F32,
+            "double" | "float64" => Scalar::F64,
+            _ => return Err(malformed(format!("Unknown PLY type {name}"))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn read(self, input: &mut &[u8], format: Format) -> Result<f64, TreeError> {
+        let size = self.size();
+        if input.len() < size {
+            return Err(malformed("Unexpected end of PLY data"));
+        }
+        let (head, tail) = input.split_at(size);
+        *input = tail;
+
+        macro_rules! read {
+            ($t:ty) => {{
+                let bytes = head.try_into().unwrap();
+                (if format == Format::BinaryBigEndian {
+                    <$t>::from_be_bytes(bytes)
+                } else {
+                    <$t>::from_le_bytes(bytes)
+                }) as f64
+            }};
+        }
+
+        Ok(match self {
+            Scalar::I8 => read!(i8),
+            Scalar::U8 => read!(u8),
+            Scalar::I16 => read!(i16),
+            Scalar::U16 => read!(u16),
+            Scalar::I32 => read!(i32),
+            Scalar::U32 => read!(u32),
+            Scalar::F32 => read!(f32),
+            Scalar::F64 => read!(f64),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    scalar: Scalar,
+    /// Type of the list length, for list properties.
+    list: Option<Scalar>,
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+/// Reads a `PLY` point cloud.
+///
+/// Supports `ascii`, `binary_little_endian` and `binary_big_endian` formats.
+/// Points are read from the `x`, `y`, `z` and optional `red`, `green`, `blue`
+/// properties of the `vertex` element. Other elements and properties are skipped.
+pub fn read_ply(reader: impl Read) -> Result<Vec<Point>, TreeError> {
+    let bytes = read_all(reader)?;
+
+    let Some(end) = bytes
+        .windows(10)
+        .position(|w| w == b"end_header")
+        .map(|i| i + 10)
+    else {
+        return Err(malformed("Missing end_header"));
+    };
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|err| malformed(err.to_string()))?;
+
+    // Data starts on the line after the header
+    let mut data = &bytes[end..];
+    if data.starts_with(b"\r\n") {
+        data = &data[2..];
+    } else if data.starts_with(b"\n") {
+        data = &data[1..];
+    }
+
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        return Err(malformed("Missing ply magic"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("format") => {
+                format = Some(match tokens.next() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    f => return Err(malformed(format!("Unknown PLY format {f:?}"))),
+                })
+            }
+
+            Some("element") => {
+                let name = tokens.next().unwrap_or_default().to_string();
+                let count = parse(tokens.next(), 0)?;
+                elements.push(Element {
+                    name,
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+
+            Some("property") => {
+                let Some(element) = elements.last_mut() else {
+                    return Err(malformed("Property outside of an element"));
+                };
+                let ty = tokens.next().unwrap_or_default();
+                let property = if ty == "list" {
+                    let count = Scalar::parse(tokens.next().unwrap_or_default())?;
+                    let scalar = Scalar::parse(tokens.next().unwrap_or_default())?;
+                    Property {
+                        name: tokens.next().unwrap_or_default().to_string(),
+                        scalar,
+                        list: Some(count),
+                    }
+                } else {
+                    Property {
+                        name: tokens.next().unwrap_or_default().to_string(),
+                        scalar: Scalar::parse(ty)?,
+                        list: None,
+                    }
+                };
+                element.properties.push(property);
+            }
+
+            _ => (), // comment, obj_info, end_header
+        }
+    }
+
+    let format = format.ok_or_else(|| malformed("Missing PLY format"))?;
+    match format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(data).map_err(|err| malformed(err.to_string()))?;
+            let mut rows = text.lines().filter(|l| !l.trim().is_empty());
+            let points = read_elements(&elements, data.len(), |values, element, row| {
+                let Some(line) = rows.next() else {
+                    return Err(malformed(format!("Missing {element} {row}")));
+                };
+                let mut tokens = line.split_whitespace();
+                for property in values.iter_mut() {
+                    property.clear();
+                    let count = match property.list {
+                        Some(_) => parse::<usize>(tokens.next(), row)?,
+                        None => 1,
+                    };
+                    for _ in 0..count {
+                        property.values.push(parse(tokens.next(), row)?);
+                    }
+                }
+                Ok(())
+            })?;
+
+            if rows.next().is_some() {
+                return Err(malformed("More rows than declared in the header"));
+            }
+            Ok(points)
+        }
+
+        _ => {
+            let points = read_elements(&elements, data.len(), |values, _, _| {
+                for property in values.iter_mut() {
+                    property.clear();
+                    let count = match property.list {
+                        Some(count) => count.read(&mut data, format)? as usize,
+                        None => 1,
+                    };
+                    for _ in 0..count {
+                        let value = property.scalar.read(&mut data, format)?;
+                        property.values.push(value);
+                    }
+                }
+                Ok(())
+            })?;
+
+            if !data.is_empty() {
+                return Err(malformed("More data than declared in the header"));
+            }
+            Ok(points)
+        }
+    }
+}
+
+/// Values of a property in the current row.
+struct Values {
+    list: Option<Scalar>,
+    scalar: Scalar,
+    values: Vec<f64>,
+}
+
+impl Values {
+    fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+/// Reads the rows of all `elements` with `read_row`,
+/// collecting the vertices.
+///
+/// Every property takes at least a byte, so the counts declared in the header
+/// are trusted for the allocation only up to the `data_len`.
+fn read_elements(
+    elements: &[Element],
+    data_len: usize,
+    mut read_row: impl FnMut(&mut [Values], &str, usize) -> Result<(), TreeError>,
+) -> Result<Vec<Point>, TreeError> {
+    let mut points = Vec::new();
+    for element in elements {
+        let mut values: Vec<Values> = element
+            .properties
+            .iter()
+            .map(|p| Values {
+                list: p.list,
+                scalar: p.scalar,
+                values: Vec::with_capacity(1),
+            })
+            .collect();
+
+        let index = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let vertex = element.name == "vertex";
+        let position = [index("x"), index("y"), index("z")];
+        let color = [index("red"), index("green"), index("blue")];
+        if vertex && position.iter().any(Option::is_none) {
+            return Err(malformed("Vertex element without x, y, z properties"));
+        }
+
+        if vertex {
+            points.reserve(element.count.min(data_len / element.properties.len()));
+        }
+
+        for row in 0..element.count {
+            read_row(&mut values, &element.name, row)?;
+            if !vertex {
+                continue;
+            }
+
+            let value = |i: usize| values[i].values.first().copied().unwrap_or_default();
+            let position = position.map(|i| value(i.unwrap()));
+            let color = match color {
+                [Some(r), Some(g), Some(b)] => Some([r, g, b].map(|i| value(i) as u8)),
+                _ => None,
+            };
+            points.push(Point { position, color });
+        }
+    }
+
+    Ok(points)
+}
+
+/// Writes the tree elements as an `ascii` `PLY` point cloud.
+///
+/// Each element becomes a vertex at it's cell center.
+pub fn write_ply<U, T>(
+    tree: &Octree<U, T>,
+    grid: &Grid,
+    writer: impl Write,
+) -> Result<(), TreeError>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+{
+    write_ply_points(tree, grid, None::<fn(&T) -> [u8; 3]>, writer)
+}
+
+/// Writes the tree elements as an `ascii` `PLY` point cloud with colors.
+///
+/// Each element becomes a vertex at it's cell center, colored by `color`.
+pub fn write_ply_with_colors<U, T>(
+    tree: &Octree<U, T>,
+    grid: &Grid,
+    color: impl Fn(&T) -> [u8; 3],
+    writer: impl Write,
+) -> Result<(), TreeError>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+{
+    write_ply_points(tree, grid, Some(color), writer)
+}
+
+fn write_ply_points<U, T>(
+    tree: &Octree<U, T>,
+    grid: &Grid,
+    color: Option<impl Fn(&T) -> [u8; 3]>,
+    mut writer: impl Write,
+) -> Result<(), TreeError>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+{
+    let mut out = String::with_capacity(tree.len() * 32 + 200);
+    out.push_str("ply\nformat ascii 1.0\ncomment oktree\n");
+    out.push_str(&format!("element vertex {}\n", tree.len()));
+    out.push_str("property float x\nproperty float y\nproperty float z\n");
+    if color.is_some() {
+        out.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+    }
+    out.push_str("end_header\n");
+
+    for element in tree.iter() {
+        let [x, y, z] = grid.center(element.volume().min);
+        out.push_str(&format!("{x} {y} {z}"));
+        if let Some(color) = color.as_ref() {
+            let [r, g, b] = color(element);
+            out.push_str(&format!(" {r} {g} {b}"));
+        }
+        out.push('\n');
+    }
+
+    writer
+        .write_all(out.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|err| TreeError::Io(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::{Aabb, TUVec3u16};
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Cell {
+        position: TUVec3<u16>,
+        count: usize,
+        color: [f64; 3],
+    }
+
+    impl Position for Cell {
+        type U = u16;
+        fn position(&self) -> TUVec3<u16> {
+            self.position
+        }
+    }
+
+    fn cloud() -> Vec<Point> {
+        vec![
+            Point {
+                position: [1.1, 1.2, 1.3],
+                color: Some([10, 0, 0]),
+            },
+            Point {
+                position: [1.9, 1.9, 1.9],
+                color: Some([20, 0, 255]),
+            },
+            Point {
+                position: [-1.0, 1.0, 1.0],
+                color: None,
+            },
+            Point {
+                position: [2.0, 1.0, 1.0],
+                color: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_grid() {
+        let grid = Grid::new([-1.0, 0.0, 0.0], 0.5);
+        assert_eq!(
+            grid.cell::<u8>([-1.0, 0.0, 0.0]),
+            Some(TUVec3::new(0, 0, 0))
+        );
+        assert_eq!(
+            grid.cell::<u8>([0.74, 0.5, 0.26]),
+            Some(TUVec3::new(3, 1, 0))
+        );
+        assert_eq!(grid.cell::<u8>([-1.1, 0.0, 0.0]), None);
+        assert_eq!(grid.cell::<u8>([500.0, 0.0, 0.0]), None);
+        assert_eq!(grid.center(TUVec3::new(3u8, 1, 0)), [0.75, 0.75, 0.25]);
+    }
+
+    #[test]
+    fn test_quantize() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(16), 16));
+        let grid = Grid::new([0.0; 3], 1.0);
+
+        let color = |p: &Point| p.color.unwrap_or_default().map(f64::from);
+        let stats = quantize(
+            &mut tree,
+            &grid,
+            cloud(),
+            |position, p| Cell {
+                position,
+                count: 1,
+                color: color(p),
+            },
+            |cell, p| {
+                cell.count += 1;
+                let n = cell.count as f64;
+                let c = color(p);
+                for (channel, c) in cell.color.iter_mut().zip(c) {
+                    *channel += (c - *channel) / n;
+                }
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            stats,
+            Quantized {
+                cells: 2,
+                merged: 1,
+                skipped: 1
+            }
+        );
+
+        let cell = tree.get(&TUVec3::new(1, 1, 1)).unwrap();
+        assert_eq!(cell.count, 2);
+        assert_eq!(cell.color, [15.0, 0.0, 127.5]);
+        assert_eq!(tree.get(&TUVec3::new(2, 1, 1)).unwrap().count, 1);
+    }
+
+    #[test]
+    fn test_xyz() {
+        let text = "# comment\n1.1 1.2 1.3 10 0 0\n\n1.9,1.9,1.9,20,0,255\n-1 1 1\n2.0\t1.0\t1.0\n";
+        assert_eq!(read_xyz(text.as_bytes()).unwrap(), cloud());
+
+        assert!(matches!(
+            read_xyz("1.0 2.0\n".as_bytes()),
+            Err(TreeError::MalformedPointCloud(_))
+        ));
+        assert!(matches!(
+            read_xyz("1.0 2.0 a\n".as_bytes()),
+            Err(TreeError::MalformedPointCloud(_))
+        ));
+    }
+
+    #[test]
+    fn test_ply_ascii() {
+        let ply = "ply\nformat ascii 1.0\ncomment test\n\
+            element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+            property list uchar int dummy\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+            1.1 1.2 1.3 0 10 0 0\n1.9 1.9 1.9 2 1 2 20 0 255\n\
+            -1 1 1 1 5 0 0 0\n2.0 1.0 1.0 0 0 0 0\n3 0 1 2\n";
+
+        let mut expected = cloud();
+        expected[2].color = Some([0, 0, 0]);
+        expected[3].color = Some([0, 0, 0]);
+        assert_eq!(read_ply(ply.as_bytes()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_ply_count_mismatch() {
+        let header = |count: &str| {
+            format!(
+                "ply\nformat ascii 1.0\nelement vertex {count}\n\
+                property float x\nproperty float y\nproperty float z\nend_header\n1 2 3\n"
+            )
+        };
+        assert!(read_ply(header("1").as_bytes()).is_ok());
+
+        for count in ["99999999999999999", "2", "0"] {
+            assert!(matches!(
+                read_ply(header(count).as_bytes()),
+                Err(TreeError::MalformedPointCloud(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_ply_binary() {
+        for (format, big) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut ply = format!(
+                "ply\nformat {format} 1.0\nelement face 1\nproperty list uchar int vertex_indices\n\
+                element vertex 2\nproperty double x\nproperty float y\nproperty short z\nend_header\n"
+            )
+            .into_bytes();
+
+            // Face
+            ply.push(2);
+            for i in [7i32, 8] {
+                ply.extend(if big {
+                    i.to_be_bytes()
+                } else {
+                    i.to_le_bytes()
+                });
+            }
+
+            // Vertices
+            for (x, y, z) in [(1.5f64, 2.5f32, 3i16), (-4.0, 5.0, -6)] {
+                ply.extend(if big {
+                    x.to_be_bytes()
+                } else {
+                    x.to_le_bytes()
+                });
+                ply.extend(if big {
+                    y.to_be_bytes()
+                } else {
+                    y.to_le_bytes()
+                });
+                ply.extend(if big {
+                    z.to_be_bytes()
+                } else {
+                    z.to_le_bytes()
+                });
+            }
+
+            let points = read_ply(ply.as_slice()).unwrap();
+            assert_eq!(
+                points.iter().map(|p| p.position).collect::<Vec<_>>(),
+                vec![[1.5, 2.5, 3.0], [-4.0, 5.0, -6.0]]
+            );
+
+            ply.pop();
+            assert!(matches!(
+                read_ply(ply.as_slice()),
+                Err(TreeError::MalformedPointCloud(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_write_ply() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        tree.insert(TUVec3u16::new(1, 2, 3)).unwrap();
+        tree.insert(TUVec3u16::new(4, 5, 6)).unwrap();
+        let grid = Grid::new([10.0, 0.0, 0.0], 2.0);
+
+        let mut bytes = Vec::new();
+        write_ply_with_colors(&tree, &grid, |_| [1, 2, 3], &mut bytes).unwrap();
+        let points = read_ply(bytes.as_slice()).unwrap();
+        assert_eq!(
+            points,
+            vec![
+                Point {
+                    position: [13.0, 5.0, 7.0],
+                    color: Some([1, 2, 3])
+                },
+                Point {
+                    position: [19.0, 11.0, 13.0],
+                    color: Some([1, 2, 3])
+                }
+            ]
+        );
+
+        let mut restored = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        let mut bytes = Vec::new();
+        write_ply(&tree, &grid, &mut bytes).unwrap();
+        let stats = quantize(
+            &mut restored,
+            &grid,
+            read_ply(bytes.as_slice()).unwrap(),
+            |position, _| TUVec3u16(position),
+            |_, _| (),
+        )
+        .unwrap();
+        assert_eq!(stats.cells, 2);
+        assert!(restored.find(&TUVec3::new(4, 5, 6)).is_some());
+    }
+}
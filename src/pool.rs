@@ -1,22 +1,22 @@
 //! [`Pool`] implementation.
 
 use std::{
-    array::from_fn,
+    fmt::Display,
     iter::Enumerate,
+    marker::PhantomData,
     ops::{Index, IndexMut},
 };
 
-use smallvec::SmallVec;
-
 use crate::{
     bounding::{Aabb, Unsigned},
     node::{Node, NodeType},
-    ElementId, NodeId, TreeError, Volume,
+    ElementId, NodeId, TreeError,
 };
 
 /// [`PoolItem`] data structure that combines both the garbage flag
 /// and the actual item together for better cache locality.
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) enum PoolItem<T> {
     Filled(T),
     Tombstone(T),
@@ -38,60 +38,91 @@ impl<T: std::fmt::Debug> std::fmt::Debug for PoolItem<T> {
     }
 }
 
-/// [`Pool`] data structure.
+/// Id type used to index a [`Pool`].
 ///
-/// When element is removed no memory deallocation happens.
-/// Removed elements are only marked as deleted and their memory could be reused.  
-#[derive(Clone)]
-pub struct Pool<T> {
-    pub(crate) vec: Vec<PoolItem<T>>,
-    pub(crate) garbage: Vec<usize>,
+/// Implemented for [`ElementId`] and [`NodeId`], so a side [`Pool`]
+/// can share the id space of the [`tree`](crate::tree::Octree) elements or nodes.
+pub trait PoolId: Copy + Display + From<usize> + Into<usize> {
+    /// Converts a pool index into an id.
+    ///
+    /// Fails with [`TreeError::IndexOverflow`] if the index doesn't fit.
+    fn try_from_index(index: usize) -> Result<Self, TreeError>;
 }
 
-impl<U: Unsigned> Default for Pool<Node<U>> {
-    fn default() -> Self {
-        let root = Node::default();
-        let vec = vec![root.into()];
-
-        Pool {
-            vec,
-            garbage: Default::default(),
-        }
+impl PoolId for ElementId {
+    #[inline(always)]
+    fn try_from_index(index: usize) -> Result<Self, TreeError> {
+        ElementId::try_from_index(index)
     }
 }
-impl<U: Unsigned> Pool<Node<U>> {
-    /// Clears all the items in the pool
-    pub fn clear(&mut self) {
-        self.vec.clear();
-        self.vec.push(Node::default().into());
-        self.garbage.clear();
-    }
 
-    /// Clears all the items in the pool and initiates it with an aabb.
-    pub fn clear_with_aabb(&mut self, aabb: Aabb<U>) {
-        self.vec.clear();
-        self.vec.push(Node::from_aabb(aabb, None).into());
-        self.garbage.clear();
+impl PoolId for NodeId {
+    #[inline(always)]
+    fn try_from_index(index: usize) -> Result<Self, TreeError> {
+        NodeId::try_from_index(index)
     }
 }
 
-impl<T: Volume> Default for Pool<T> {
+/// [`Pool`] data structure.
+///
+/// Generic slot map, indexed by a [`PoolId`] (`I`).
+/// When element is removed no memory deallocation happens.
+/// Removed elements are only marked as deleted and their memory could be reused.
+///
+/// Ids carry no generation, so an id of a removed element
+/// refers to the next element, inserted into the same slot.
+///
+/// ```rust
+/// use oktree::prelude::*;
+///
+/// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16u8).unwrap());
+/// let mut names: Pool<&str> = Pool::new();
+///
+/// let id = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+/// assert_eq!(names.insert("first"), id);
+/// assert_eq!(names[id], "first");
+/// ```
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        try_from = "crate::serialization::RawPool<T>",
+        bound(deserialize = "T: serde::Deserialize<'de>")
+    )
+)]
+pub struct Pool<T, I = ElementId> {
+    pub(crate) vec: Vec<PoolItem<T>>,
+    pub(crate) garbage: Vec<usize>,
+    /// Set for the pools, allocating their items in blocks of 8, like the tree nodes.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) blocks: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) _id: PhantomData<I>,
+}
+
+impl<T, I> Default for Pool<T, I> {
     fn default() -> Self {
         Pool {
             vec: Default::default(),
             garbage: Default::default(),
+            blocks: false,
+            _id: PhantomData,
         }
     }
 }
-impl<T: Volume> Pool<T> {
-    /// Clears all the items in the pool
-    pub fn clear(&mut self) {
-        self.vec.clear();
-        self.garbage.clear();
+
+impl<T: Clone, I> Clone for Pool<T, I> {
+    fn clone(&self) -> Self {
+        Pool {
+            vec: self.vec.clone(),
+            garbage: self.garbage.clone(),
+            blocks: self.blocks,
+            _id: PhantomData,
+        }
     }
 }
 
-impl<T: std::fmt::Debug> std::fmt::Debug for Pool<T> {
+impl<T: std::fmt::Debug, I> std::fmt::Debug for Pool<T, I> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Pool")
             .field("vec", &self.vec)
@@ -100,131 +131,99 @@ impl<T: std::fmt::Debug> std::fmt::Debug for Pool<T> {
     }
 }
 
-impl Default for Pool<SmallVec<[NodeId; 1]>> {
-    fn default() -> Self {
-        Pool {
-            vec: Default::default(),
-            garbage: Default::default(),
-        }
-    }
-}
-impl Pool<SmallVec<[NodeId; 1]>> {
-    /// Clears all the items in the pool
-    pub fn clear(&mut self) {
-        self.vec.clear();
-        self.garbage.clear();
-    }
-}
-
-/// Indexing a [`pool`](Pool) of [`nodes`](Node) with [`NodeId`]
+/// Indexing a [`pool`](Pool) with it's [`id`](PoolId).
 ///
 /// ```ignore
 /// let node = &tree.nodes[NodeId(42)];
+/// let element = &tree.elements[ElementId(42)];
 /// // let node = &tree.nodes[ElementId(42)]; // Error
 /// ```
-impl<U: Unsigned> Index<NodeId> for Pool<Node<U>> {
-    type Output = Node<U>;
+impl<T, I: PoolId> Index<I> for Pool<T, I> {
+    type Output = T;
 
-    fn index(&self, index: NodeId) -> &Self::Output {
-        debug_assert!(!self.is_garbage(index), "Indexing garbage node: {index}");
+    fn index(&self, index: I) -> &Self::Output {
+        debug_assert!(!self.is_garbage(index), "Indexing garbaged item: {index}");
         self.get_unchecked(index)
     }
 }
 
-/// Mutable Indexing a [`pool`](Pool) of [`nodes`](Node) with [`NodeId`]
+/// Mutable Indexing a [`pool`](Pool) with it's [`id`](PoolId).
 ///
 /// ```ignore
 /// let mut node = &mut tree.nodes[NodeId(42)];
+/// let mut element = &mut tree.elements[ElementId(42)];
 /// // let mut node = &mut tree.nodes[ElementId(42)]; // Error
 /// ```
-impl<U: Unsigned> IndexMut<NodeId> for Pool<Node<U>> {
-    fn index_mut(&mut self, index: NodeId) -> &mut Self::Output {
+impl<T, I: PoolId> IndexMut<I> for Pool<T, I> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
         debug_assert!(
             !self.is_garbage(index),
-            "Mut Indexing garbaged node: {index}"
+            "Mut Indexing garbaged item: {index}"
         );
         self.get_mut_unchecked(index)
     }
 }
 
-/// Indexing a [`pool`](Pool) of `T: Position` with [`ElementId`]
-///
-/// ```ignore
-/// let element = &tree.element[ElementId(42)];
-/// // let element = &tree.element[NodeId(42)]; // Error
-/// ```
-impl<T: Volume> Index<ElementId> for Pool<T> {
-    type Output = T;
-
-    fn index(&self, index: ElementId) -> &Self::Output {
-        debug_assert!(
-            !self.is_garbage(index),
-            "Indexing garbaged element: {index}"
-        );
-        self.get_unchecked(index)
+impl<T, I> Pool<T, I> {
+    /// Construct an empty [`Pool`].
+    pub fn new() -> Self {
+        Default::default()
     }
-}
 
-/// Mutable Indexing a [`pool`](Pool) of `T: Position` with [`ElementId`]
-///
-/// ```ignore
-/// let mut element = &mut tree.element[ElementId(42)];
-/// // let mut element = &mut tree.element[NodeId(42)]; // Error
-/// ```
-impl<T: Volume> IndexMut<ElementId> for Pool<T> {
-    fn index_mut(&mut self, index: ElementId) -> &mut Self::Output {
-        debug_assert!(
-            !self.is_garbage(index),
-            "Mut Indexing garbaged element: {index}"
-        );
-        self.get_mut_unchecked(index)
+    /// Construct an empty [`Pool`] with capacity.
+    ///
+    /// Helps to reduce the amount of the memory reallocations.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Pool {
+            vec: Vec::with_capacity(capacity),
+            garbage: Default::default(),
+            blocks: false,
+            _id: PhantomData,
+        }
     }
-}
 
-/// Indexing a [`pool`](Pool) of [`node ids`](NodeId) with [`ElementId`]
-///
-/// ```ignore
-/// let node_id = &tree.map[ElementId(42)];
-/// // let node_id = &tree.map[NodeId(42)]; // Error
-/// ```
-impl Index<ElementId> for Pool<NodeId> {
-    type Output = NodeId;
-
-    fn index(&self, index: ElementId) -> &Self::Output {
-        debug_assert!(
-            !self.is_garbage(index),
-            "Indexing garbaged element: {index}"
-        );
-        self.get_unchecked(index)
+    /// Returns the number of slots the pool can hold without reallocating.
+    ///
+    /// Deleted slots are reused before the pool grows.
+    #[inline(always)]
+    pub fn capacity(&self) -> usize {
+        self.vec.capacity()
     }
-}
 
-/// Mutable Indexing a [`pool`](Pool) of [`node ids`](NodeId) with [`ElementId`]
-///
-/// ```ignore
-/// let mut node_id = &mut tree.map[ElementId(42)];
-/// // let mut node_id = &mut tree.map[NodeId(42)]; // Error
-/// ```
-impl IndexMut<ElementId> for Pool<NodeId> {
-    fn index_mut(&mut self, index: ElementId) -> &mut Self::Output {
-        debug_assert!(
-            !self.is_garbage(index),
-            "Mut Indexing garbaged element: {index}"
-        );
-        self.get_mut_unchecked(index)
+    /// Reserves capacity for at least `additional` more items.
+    pub fn reserve(&mut self, additional: usize) {
+        self.vec
+            .reserve(additional.saturating_sub(self.garbage_len()));
     }
-}
 
-impl<T> Pool<T> {
-    #[inline(always)]
-    fn _insert(&mut self, t: T) -> usize {
-        if let Some(idx) = self.garbage.pop() {
-            self.vec[idx] = PoolItem::Filled(t);
-            idx
+    /// Shrinks the capacity of the pool as much as possible.
+    ///
+    /// Trailing deleted slots are released, so ids of the actual items are preserved.
+    /// Pools of the tree nodes release only the whole blocks of 8 children.
+    pub fn shrink_to_fit(&mut self) {
+        if self.blocks {
+            while self.vec.len() > 8
+                && self.vec[self.vec.len() - 8..]
+                    .iter()
+                    .all(|item| !matches!(item, PoolItem::Filled(_)))
+            {
+                self.vec.truncate(self.vec.len() - 8);
+            }
         } else {
-            self.vec.push(PoolItem::Filled(t));
-            self.vec.len() - 1
+            while let Some(PoolItem::Tombstone(_) | PoolItem::Empty) = self.vec.last() {
+                self.vec.pop();
+            }
         }
+        let len = self.vec.len();
+        self.garbage.retain(|&idx| idx < len);
+        self.vec.shrink_to_fit();
+        self.garbage.shrink_to_fit();
+    }
+
+    /// Clears all the items in the pool
+    pub fn clear(&mut self) {
+        self.vec.clear();
+        self.garbage.clear();
     }
 
     /// Restores all the garbage elements back to real elements. Effectively
@@ -287,119 +286,217 @@ impl<T> Pool<T> {
         self.garbage.len()
     }
 
+    /// Returns the estimated size of the heap memory, allocated by the pool.
+    ///
+    /// Heap memory, owned by the items themselves, is not counted.
+    pub fn heap_bytes(&self) -> usize {
+        self.vec.capacity() * std::mem::size_of::<PoolItem<T>>()
+            + self.garbage.capacity() * std::mem::size_of::<usize>()
+    }
+
+    #[inline(always)]
+    pub fn has_garbage(&self) -> bool {
+        !self.garbage.is_empty()
+    }
+
     /// Returns a [`PoolIterator`], which iterates over an actual elements.
     ///
     /// Elements marked as deleted are skipped.
-    pub fn iter(&self) -> PoolIterator<T> {
+    pub fn iter(&self) -> PoolIterator<'_, T> {
         PoolIterator::new(self)
     }
 
     /// Returns a [`PoolIteratorMut`], which iterates over an actual elements.
     ///
     /// Elements marked as deleted are skipped.
-    pub fn iter_mut(&mut self) -> PoolIteratorMut<T> {
+    pub fn iter_mut(&mut self) -> PoolIteratorMut<'_, T> {
         PoolIteratorMut::new(self)
     }
 
-    /// Returns a [`PoolIterator`], which iterates over an actual elements and element ids
+    /// Returns a [`PoolElementIterator`], which iterates over an actual elements and their ids.
     ///
     /// Elements marked as deleted are skipped.
-    pub fn iter_elements(&self) -> PoolElementIterator<T> {
+    pub fn iter_elements(&self) -> PoolElementIterator<'_, T, I> {
         PoolElementIterator::new(self)
     }
-}
 
-impl<T> IntoIterator for Pool<T> {
-    type Item = T;
-    type IntoIter = PoolIntoIterator<T>;
+    /// Returns a [`PoolElementIteratorMut`], which iterates over an actual
+    /// mutable elements and their ids.
+    ///
+    /// Elements marked as deleted are skipped.
+    pub fn iter_elements_mut(&mut self) -> PoolElementIteratorMut<'_, T, I> {
+        PoolElementIteratorMut::new(self)
+    }
 
-    fn into_iter(self) -> Self::IntoIter {
-        PoolIntoIterator::new(self)
+    /// Removes all the elements from the pool, returning them with their ids.
+    ///
+    /// The capacity of the pool is preserved.
+    pub fn drain(&mut self) -> PoolDrain<'_, T, I> {
+        PoolDrain::new(self)
     }
 }
 
-impl<U: Unsigned> Pool<Node<U>> {
-    /// Construct a [`Pool`] of [`nodes`](Node) from [`Aabb`].
-    ///
-    /// Node will adopt aabb's dimensions.
-    pub(crate) fn from_aabb(aabb: Aabb<U>) -> Self {
-        let root = Node::from_aabb(aabb, None);
-        let vec = vec![root.into()];
-        Pool {
-            vec,
-            garbage: Default::default(),
+impl<T, I: PoolId> Pool<T, I> {
+    #[inline(always)]
+    fn _insert(&mut self, t: T) -> usize {
+        if let Some(idx) = self.garbage.pop() {
+            self.vec[idx] = PoolItem::Filled(t);
+            idx
+        } else {
+            self.vec.push(PoolItem::Filled(t));
+            self.vec.len() - 1
         }
     }
 
-    /// Construct a [`Pool`] of [`nodes`](Node).
+    /// Insert an item into the pool, returning it's id.
     ///
-    /// Helps to reduce the amount of the memory reallocations.
-    pub(crate) fn with_capacity(capacity: usize) -> Self {
-        let root = Node::default();
-        let mut vec = Vec::with_capacity(capacity);
-        vec.push(root.into());
-
-        Pool {
-            vec,
-            garbage: Default::default(),
-        }
+    /// Ids of the deleted items are reused.
+    ///
+    /// Panics if the id overflows. Use [`try_insert`](Pool::try_insert) to handle the overflow.
+    #[inline(always)]
+    pub fn insert(&mut self, t: T) -> I {
+        self._insert(t).into()
     }
 
-    /// Construct a [`Pool`] of [`nodes`](Node) from [`Aabb`] with capacity.
+    /// Insert an item into the pool, returning it's id.
     ///
-    /// Node will adopt aabb's dimensions.
-    /// Helps to reduce the amount of the memory reallocations.
-    pub(crate) fn from_aabb_with_capacity(aabb: Aabb<U>, capacity: usize) -> Self {
-        let root = Node::from_aabb(aabb, None);
-        let mut vec = Vec::with_capacity(capacity);
-        vec.push(root.into());
-
-        Pool {
-            vec,
-            garbage: Default::default(),
+    /// Ids of the deleted items are reused.
+    /// Fails with [`TreeError::IndexOverflow`] if the id space is exhausted.
+    #[inline(always)]
+    pub fn try_insert(&mut self, t: T) -> Result<I, TreeError> {
+        if self.garbage.is_empty() {
+            I::try_from_index(self.vec.len())?;
         }
+        Ok(self._insert(t).into())
     }
 
+    /// Remove an item from the pool, returning it if it was present.
+    ///
+    /// The slot is released and it's id could be reused.
+    /// Returns `None` for the ids out of the pool.
     #[inline(always)]
-    pub(crate) fn insert(&mut self, t: Node<U>) -> NodeId {
-        self._insert(t).into()
-    }
+    pub fn remove(&mut self, element: impl Into<I>) -> Option<T> {
+        let index: usize = element.into().into();
+        let slot = self.vec.get_mut(index)?;
 
-    #[inline(always)]
-    pub(crate) fn branch(&mut self, parent: NodeId) -> [NodeId; 8] {
-        let aabbs = self[parent].aabb.split();
-        from_fn(|i| self.insert(Node::from_aabb(aabbs[i], Some(parent))))
+        let mut ret = None;
+
+        let mut item = PoolItem::Empty;
+        std::mem::swap(slot, &mut item);
+        *slot = match item {
+            PoolItem::Filled(item) => {
+                ret = Some(item);
+                self.garbage.push(index);
+                PoolItem::Empty
+            }
+            PoolItem::Tombstone(item) => {
+                ret = Some(item);
+                PoolItem::Empty
+            }
+            PoolItem::Empty => PoolItem::Empty,
+        };
+        ret
     }
 
-    pub(crate) fn maybe_collapse(&mut self, parent: NodeId) {
-        let mut current = Some(parent);
-        while let Some(parent) = current.take() {
-            if let NodeType::Branch(ref branch) = self[parent].ntype {
-                if branch
-                    .children
-                    .iter()
-                    .all(|&child| self[child].ntype == NodeType::Empty)
-                {
-                    for child in branch.children {
-                        self.tombstone(child);
+    /// Retains only the elements specified by the predicate.
+    ///
+    /// Rejected elements are removed and their ids could be reused.
+    /// Pools of the tree nodes keep their blocks of 8 children whole:
+    /// a block is removed only when all of it's items are rejected
+    /// and the item before the first block is always retained.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(I, &mut T) -> bool,
+    {
+        if self.blocks {
+            let blocks = self.vec.get_mut(1..).unwrap_or_default().chunks_mut(8);
+            for (first, block) in (1..).step_by(8).zip(blocks) {
+                let mut rejected = true;
+                for (i, item) in block.iter_mut().enumerate() {
+                    if let PoolItem::Filled(ref mut t) = item {
+                        rejected &= !f((first + i).into(), t);
                     }
-                    self[parent].ntype = NodeType::Empty;
-                    current = self[parent].parent;
+                }
+
+                let filled = block.iter().any(|item| matches!(item, PoolItem::Filled(_)));
+                if rejected && filled {
+                    block.fill_with(|| PoolItem::Empty);
+                    self.garbage.extend(first..first + block.len());
+                }
+            }
+            return;
+        }
+
+        for (idx, item) in self.vec.iter_mut().enumerate() {
+            if let PoolItem::Filled(ref mut t) = item {
+                if !f(idx.into(), t) {
+                    *item = PoolItem::Empty;
+                    self.garbage.push(idx);
                 }
             }
         }
     }
-}
 
-impl<T> Pool<T> {
+    /// Insert a contiguous block of 8 items, returning the id of the first one.
+    ///
+    /// Garbage is reused only as whole blocks, so pools using blocks
+    /// must release their items with [`tombstone_block`](Pool::tombstone_block) only.
+    pub(crate) fn try_insert_block(
+        &mut self,
+        mut f: impl FnMut(usize) -> T,
+    ) -> Result<I, TreeError> {
+        self.blocks = true;
+        if self.garbage.len() >= 8 {
+            let start = self.garbage.len() - 8;
+            let first = self.garbage[start];
+            if !self.garbage[start..]
+                .iter()
+                .enumerate()
+                .all(|(i, &idx)| idx == first + i && idx < self.vec.len())
+            {
+                return Err(TreeError::CorruptGarbage(format!(
+                    "Garbage is not a contiguous block: {:?}",
+                    &self.garbage[start..]
+                )));
+            }
+            self.garbage.truncate(start);
+            for i in 0..8 {
+                self.vec[first + i] = PoolItem::Filled(f(i));
+            }
+            Ok(first.into())
+        } else {
+            let first = self.vec.len();
+            I::try_from_index(first + 7)?;
+            self.vec.extend((0..8).map(|i| PoolItem::Filled(f(i))));
+            Ok(first.into())
+        }
+    }
+
+    /// Marks a block of 8 items, inserted by [`try_insert_block`](Pool::try_insert_block), as deleted.
+    pub(crate) fn tombstone_block(&mut self, first: I) {
+        self.blocks = true;
+        let first: usize = first.into();
+        for index in first..first + 8 {
+            let mut item = PoolItem::Empty;
+            std::mem::swap(&mut self.vec[index], &mut item);
+            self.vec[index] = match item {
+                PoolItem::Filled(item) => PoolItem::Tombstone(item),
+                item => item,
+            };
+            self.garbage.push(index);
+        }
+    }
+
     #[inline(always)]
-    pub(crate) fn tombstone(&mut self, element: impl Into<ElementId>) {
-        let element = Into::<ElementId>::into(element);
-        let index: usize = element.into();
+    pub(crate) fn tombstone(&mut self, element: impl Into<I>) {
+        let index: usize = element.into().into();
+        let Some(slot) = self.vec.get_mut(index) else {
+            return;
+        };
 
         let mut item = PoolItem::Empty;
-        std::mem::swap(&mut self.vec[index], &mut item);
-        self.vec[index] = match item {
+        std::mem::swap(slot, &mut item);
+        *slot = match item {
             PoolItem::Filled(item) => {
                 self.garbage.push(index);
                 PoolItem::Tombstone(item)
@@ -409,34 +506,16 @@ impl<T> Pool<T> {
         };
     }
 
+    /// Returns `true` if the pool holds an actual element with this id.
     #[inline(always)]
-    pub(crate) fn remove(&mut self, element: impl Into<ElementId>) -> Option<T> {
-        let element = Into::<ElementId>::into(element);
-        let index: usize = element.into();
-
-        let mut ret = None;
-
-        let mut item = PoolItem::Empty;
-        std::mem::swap(&mut self.vec[index], &mut item);
-        self.vec[index] = match item {
-            PoolItem::Filled(item) => {
-                ret = Some(item);
-                self.garbage.push(index);
-                PoolItem::Empty
-            }
-            PoolItem::Tombstone(item) => {
-                ret = Some(item);
-                PoolItem::Empty
-            }
-            PoolItem::Empty => PoolItem::Empty,
-        };
-        ret
+    pub fn contains(&self, element: impl Into<I>) -> bool {
+        self.get(element).is_some()
     }
 
     #[inline(always)]
-    pub fn get(&self, element: impl Into<ElementId>) -> Option<&T> {
-        let element = Into::<ElementId>::into(element);
-        self.vec.get(element.0 as usize).and_then(|item| {
+    pub fn get(&self, element: impl Into<I>) -> Option<&T> {
+        let index: usize = element.into().into();
+        self.vec.get(index).and_then(|item| {
             if let PoolItem::Filled(ref item) = item {
                 Some(item)
             } else {
@@ -446,9 +525,9 @@ impl<T> Pool<T> {
     }
 
     #[inline(always)]
-    pub fn get_mut(&mut self, element: impl Into<ElementId>) -> Option<&mut T> {
-        let element = Into::<ElementId>::into(element);
-        self.vec.get_mut(element.0 as usize).and_then(|item| {
+    pub fn get_mut(&mut self, element: impl Into<I>) -> Option<&mut T> {
+        let index: usize = element.into().into();
+        self.vec.get_mut(index).and_then(|item| {
             if let PoolItem::Filled(ref mut item) = item {
                 Some(item)
             } else {
@@ -458,9 +537,10 @@ impl<T> Pool<T> {
     }
 
     #[inline(always)]
-    pub fn get_unchecked(&self, element: impl Into<ElementId>) -> &T {
-        let element = Into::<ElementId>::into(element);
-        if let PoolItem::Filled(ref item) = self.vec[element.0 as usize] {
+    pub fn get_unchecked(&self, element: impl Into<I>) -> &T {
+        let element = element.into();
+        let index: usize = element.into();
+        if let PoolItem::Filled(ref item) = self.vec[index] {
             item
         } else {
             unreachable!("Accessing garbaged element: {element}")
@@ -468,56 +548,126 @@ impl<T> Pool<T> {
     }
 
     #[inline(always)]
-    pub fn get_mut_unchecked(&mut self, element: impl Into<ElementId>) -> &mut T {
-        let element = Into::<ElementId>::into(element);
-        if let PoolItem::Filled(ref mut item) = self.vec[element.0 as usize] {
+    pub fn get_mut_unchecked(&mut self, element: impl Into<I>) -> &mut T {
+        let element = element.into();
+        let index: usize = element.into();
+        if let PoolItem::Filled(ref mut item) = self.vec[index] {
             item
         } else {
             unreachable!("Accessing garbaged element: {element}")
         }
     }
 
+    /// Returns `true` if the element with this id was removed.
+    ///
+    /// Ids out of the pool were never inserted, so they are not garbage.
     #[inline(always)]
-    pub fn is_garbage(&self, element: impl Into<ElementId>) -> bool {
-        let idx: usize = Into::<ElementId>::into(element).into();
-        match &self.vec[idx] {
-            PoolItem::Filled(_) => false,
-            PoolItem::Tombstone(_) => true,
-            PoolItem::Empty => true,
+    pub fn is_garbage(&self, element: impl Into<I>) -> bool {
+        let idx: usize = element.into().into();
+        match self.vec.get(idx) {
+            Some(PoolItem::Filled(_)) | None => false,
+            Some(PoolItem::Tombstone(_)) => true,
+            Some(PoolItem::Empty) => true,
         }
     }
 
-    #[inline(always)]
-    pub fn has_garbage(&self) -> bool {
-        !self.garbage.is_empty()
+    /// Converts every item, keeping the ids and the garbage.
+    pub(crate) fn map<V>(self, mut f: impl FnMut(T) -> V) -> Pool<V, I> {
+        Pool {
+            vec: self
+                .vec
+                .into_iter()
+                .map(|item| match item {
+                    PoolItem::Filled(item) => PoolItem::Filled(f(item)),
+                    PoolItem::Tombstone(item) => PoolItem::Tombstone(f(item)),
+                    PoolItem::Empty => PoolItem::Empty,
+                })
+                .collect(),
+            garbage: self.garbage,
+            blocks: self.blocks,
+            _id: PhantomData,
+        }
     }
 }
 
-impl<T: Volume> Pool<T> {
-    pub(crate) fn with_capacity(capacity: usize) -> Self {
-        Pool {
-            vec: Vec::with_capacity(capacity),
-            garbage: Default::default(),
-        }
+impl<T, I> IntoIterator for Pool<T, I> {
+    type Item = T;
+    type IntoIter = PoolIntoIterator<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        PoolIntoIterator::new(self)
     }
+}
 
-    #[inline(always)]
-    pub(crate) fn insert(&mut self, t: T) -> ElementId {
-        self._insert(t).into()
+impl<U: Unsigned> Pool<Node<U>, NodeId> {
+    /// Clears all the items in the pool and initiates it with an aabb.
+    pub fn clear_with_aabb(&mut self, aabb: Aabb<U>) {
+        self.reset_with_aabb(aabb, ());
     }
 }
 
-impl Pool<NodeId> {
-    pub(crate) fn with_capacity(capacity: usize) -> Self {
-        Pool {
-            vec: Vec::with_capacity(capacity),
-            garbage: Default::default(),
-        }
+impl<U: Unsigned, A: Copy> Pool<Node<U, A>, NodeId> {
+    /// Construct a [`Pool`] of [`nodes`](Node) from [`Aabb`].
+    ///
+    /// Node will adopt aabb's dimensions.
+    pub(crate) fn from_aabb(aabb: Aabb<U>, empty: A) -> Self {
+        let mut pool = Pool::default();
+        pool.vec.push(Node::from_aabb(aabb, None, empty).into());
+        pool.blocks = true;
+        pool
     }
 
+    /// Construct a [`Pool`] of [`nodes`](Node) from [`Aabb`] with capacity.
+    ///
+    /// Node will adopt aabb's dimensions.
+    /// Helps to reduce the amount of the memory reallocations.
+    pub(crate) fn from_aabb_with_capacity(aabb: Aabb<U>, capacity: usize, empty: A) -> Self {
+        let mut pool = Pool::with_capacity(capacity);
+        pool.vec.push(Node::from_aabb(aabb, None, empty).into());
+        pool.blocks = true;
+        pool
+    }
+
+    /// Clears all the items in the pool and initiates it with an aabb
+    /// and an empty aggregate.
+    pub(crate) fn reset_with_aabb(&mut self, aabb: Aabb<U>, empty: A) {
+        self.clear();
+        self.vec.push(Node::from_aabb(aabb, None, empty).into());
+    }
+
+    /// Allocates a contiguous block of 8 children for the `parent` node.
+    ///
+    /// Returns the id of the first child.
     #[inline(always)]
-    pub(crate) fn insert(&mut self, t: NodeId) -> ElementId {
-        self._insert(t).into()
+    pub(crate) fn branch(&mut self, parent: NodeId, empty: A) -> Result<NodeId, TreeError> {
+        let aabbs = self[parent].aabb.split();
+        self.try_insert_block(|i| Node::from_aabb(aabbs[i], Some(parent), empty))
+    }
+
+    /// Collapses the `parent` and it's ancestors while all their children are empty.
+    ///
+    /// Returns the last visited node, which is the topmost node
+    /// whose subtree was changed.
+    pub(crate) fn maybe_collapse(&mut self, parent: NodeId) -> NodeId {
+        let mut last = parent;
+        let mut current = Some(parent);
+        while let Some(parent) = current.take() {
+            last = parent;
+            if let NodeType::Branch(ref branch) = self[parent].ntype {
+                if branch
+                    .children()
+                    .iter()
+                    .all(|&child| self[child].ntype == NodeType::Empty)
+                {
+                    let first_child = branch.first_child;
+                    self[parent].aggregate = self[first_child].aggregate;
+                    self.tombstone_block(first_child);
+                    self[parent].ntype = NodeType::Empty;
+                    current = self[parent].parent;
+                }
+            }
+        }
+        last
     }
 }
 
@@ -532,7 +682,7 @@ pub struct PoolIterator<'pool, T> {
 }
 
 impl<'pool, T> PoolIterator<'pool, T> {
-    fn new(pool: &'pool Pool<T>) -> Self {
+    fn new<I>(pool: &'pool Pool<T, I>) -> Self {
         PoolIterator {
             inner: pool.vec.iter(),
             garbage_len: pool.garbage_len(),
@@ -601,7 +751,7 @@ pub struct PoolIteratorMut<'pool, T> {
 }
 
 impl<'pool, T> PoolIteratorMut<'pool, T> {
-    fn new(pool: &'pool mut Pool<T>) -> Self {
+    fn new<I>(pool: &'pool mut Pool<T, I>) -> Self {
         Self {
             garbage_len: pool.garbage_len(),
             inner: pool.vec.iter_mut(),
@@ -664,30 +814,41 @@ impl<'pool, T> std::iter::FusedIterator for PoolIteratorMut<'pool, T> where
 ///
 /// Yields only an actual elements.
 /// Elements marked as removed are skipped.
-#[derive(Clone)]
-pub struct PoolElementIterator<'pool, T> {
+pub struct PoolElementIterator<'pool, T, I = ElementId> {
     inner: Enumerate<std::slice::Iter<'pool, PoolItem<T>>>,
     garbage_len: usize,
+    _id: PhantomData<I>,
+}
+
+impl<T, I> Clone for PoolElementIterator<'_, T, I> {
+    fn clone(&self) -> Self {
+        PoolElementIterator {
+            inner: self.inner.clone(),
+            garbage_len: self.garbage_len,
+            _id: PhantomData,
+        }
+    }
 }
 
-impl<'pool, T> PoolElementIterator<'pool, T> {
-    fn new(pool: &'pool Pool<T>) -> Self {
+impl<'pool, T, I> PoolElementIterator<'pool, T, I> {
+    fn new(pool: &'pool Pool<T, I>) -> Self {
         PoolElementIterator {
             inner: pool.vec.iter().enumerate(),
             garbage_len: pool.garbage_len(),
+            _id: PhantomData,
         }
     }
 }
 
-impl<'pool, T> Iterator for PoolElementIterator<'pool, T> {
-    type Item = (ElementId, &'pool T);
+impl<'pool, T, I: PoolId> Iterator for PoolElementIterator<'pool, T, I> {
+    type Item = (I, &'pool T);
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
             let next = self.inner.next()?;
             match next.1 {
                 PoolItem::Filled(item) => {
-                    return Some((ElementId(next.0 as u32), item));
+                    return Some((next.0.into(), item));
                 }
                 PoolItem::Empty => continue,
                 PoolItem::Tombstone(_) => continue,
@@ -704,13 +865,13 @@ impl<'pool, T> Iterator for PoolElementIterator<'pool, T> {
     }
 }
 
-impl<T> DoubleEndedIterator for PoolElementIterator<'_, T> {
+impl<T, I: PoolId> DoubleEndedIterator for PoolElementIterator<'_, T, I> {
     fn next_back(&mut self) -> Option<Self::Item> {
         loop {
             let next = self.inner.next_back()?;
             match next.1 {
                 PoolItem::Filled(item) => {
-                    return Some((ElementId(next.0 as u32), item));
+                    return Some((next.0.into(), item));
                 }
                 PoolItem::Empty => continue,
                 PoolItem::Tombstone(_) => continue,
@@ -719,17 +880,138 @@ impl<T> DoubleEndedIterator for PoolElementIterator<'_, T> {
     }
 }
 
-impl<T> ExactSizeIterator for PoolElementIterator<'_, T> {
+impl<T, I: PoolId> ExactSizeIterator for PoolElementIterator<'_, T, I> {
     fn len(&self) -> usize {
         self.inner.len() - self.garbage_len
     }
 }
 
-impl<'pool, T> std::iter::FusedIterator for PoolElementIterator<'pool, T> where
+impl<'pool, T, I: PoolId> std::iter::FusedIterator for PoolElementIterator<'pool, T, I> where
     std::slice::Iter<'pool, PoolItem<T>>: std::iter::FusedIterator
 {
 }
 
+/// Mutable iterator for a [`Pool`] that includes element IDs
+///
+/// Yields only an actual elements.
+/// Elements marked as removed are skipped.
+pub struct PoolElementIteratorMut<'pool, T, I = ElementId> {
+    inner: Enumerate<std::slice::IterMut<'pool, PoolItem<T>>>,
+    garbage_len: usize,
+    _id: PhantomData<I>,
+}
+
+impl<'pool, T, I> PoolElementIteratorMut<'pool, T, I> {
+    fn new(pool: &'pool mut Pool<T, I>) -> Self {
+        PoolElementIteratorMut {
+            garbage_len: pool.garbage_len(),
+            inner: pool.vec.iter_mut().enumerate(),
+            _id: PhantomData,
+        }
+    }
+}
+
+impl<'pool, T, I: PoolId> Iterator for PoolElementIteratorMut<'pool, T, I> {
+    type Item = (I, &'pool mut T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = self.inner.next()?;
+            match next.1 {
+                PoolItem::Filled(item) => {
+                    return Some((next.0.into(), item));
+                }
+                PoolItem::Empty => continue,
+                PoolItem::Tombstone(_) => continue,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let hint = self.inner.size_hint();
+        (
+            hint.0.saturating_sub(self.garbage_len),
+            hint.1.map(|x| x.saturating_sub(self.garbage_len)),
+        )
+    }
+}
+
+impl<T, I: PoolId> DoubleEndedIterator for PoolElementIteratorMut<'_, T, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = self.inner.next_back()?;
+            match next.1 {
+                PoolItem::Filled(item) => {
+                    return Some((next.0.into(), item));
+                }
+                PoolItem::Empty => continue,
+                PoolItem::Tombstone(_) => continue,
+            }
+        }
+    }
+}
+
+impl<T, I: PoolId> ExactSizeIterator for PoolElementIteratorMut<'_, T, I> {
+    fn len(&self) -> usize {
+        self.inner.len() - self.garbage_len
+    }
+}
+
+impl<'pool, T, I: PoolId> std::iter::FusedIterator for PoolElementIteratorMut<'pool, T, I> where
+    std::slice::IterMut<'pool, PoolItem<T>>: std::iter::FusedIterator
+{
+}
+
+/// Draining iterator for a [`Pool`] that includes element IDs
+///
+/// Yields only an actual elements.
+/// Elements marked as removed are dropped.
+/// The pool is empty once the iterator is dropped.
+pub struct PoolDrain<'pool, T, I = ElementId> {
+    inner: Enumerate<std::vec::Drain<'pool, PoolItem<T>>>,
+    garbage_len: usize,
+    _id: PhantomData<I>,
+}
+
+impl<'pool, T, I> PoolDrain<'pool, T, I> {
+    fn new(pool: &'pool mut Pool<T, I>) -> Self {
+        let garbage_len = pool.garbage_len();
+        pool.garbage.clear();
+        PoolDrain {
+            inner: pool.vec.drain(..).enumerate(),
+            garbage_len,
+            _id: PhantomData,
+        }
+    }
+}
+
+impl<T, I: PoolId> Iterator for PoolDrain<'_, T, I> {
+    type Item = (I, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = self.inner.next()?;
+            match next.1 {
+                PoolItem::Filled(item) => {
+                    return Some((next.0.into(), item));
+                }
+                PoolItem::Empty | PoolItem::Tombstone(_) => {
+                    self.garbage_len = self.garbage_len.saturating_sub(1);
+                    continue;
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let hint = self.inner.size_hint();
+        (
+            hint.0.saturating_sub(self.garbage_len),
+            hint.1.map(|x| x.saturating_sub(self.garbage_len)),
+        )
+    }
+}
+
 /// IntoIterator for a [`Pool`] that includes elements
 ///
 /// Yields only an actual elements.
@@ -741,7 +1023,7 @@ pub struct PoolIntoIterator<T> {
 }
 
 impl<T> PoolIntoIterator<T> {
-    fn new(pool: Pool<T>) -> Self {
+    fn new<I>(pool: Pool<T, I>) -> Self {
         PoolIntoIterator {
             garbage_len: pool.garbage_len(),
             inner: pool.vec.into_iter(),
@@ -803,7 +1085,7 @@ impl<T> std::iter::FusedIterator for PoolIntoIterator<T> where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::prelude::*;
+    use crate::{prelude::*, tests::DummyCell, IdType};
 
     struct DummyNotClonableNotSend<'a> {
         pos: TUVec3<u8>,
@@ -835,7 +1117,7 @@ mod tests {
     fn test_remove() {
         let mut pool = Pool::<TUVec3u8>::with_capacity(16);
         for i in 0..16 {
-            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i as u32));
+            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i.into()));
             assert_eq!(pool.len(), (i + 1) as usize);
             assert_eq!(pool.garbage_len(), 0_usize);
         }
@@ -857,6 +1139,11 @@ mod tests {
             assert_eq!(pool.len(), (15 - i) as usize);
             assert_eq!(pool.garbage_len(), (i + 1) as usize);
         }
+
+        assert_eq!(pool.remove(ElementId(16)), None);
+        assert!(!pool.is_garbage(ElementId(16)));
+        pool.tombstone(ElementId(16));
+        assert_eq!(pool.garbage_len(), 16);
     }
 
     #[test]
@@ -864,7 +1151,7 @@ mod tests {
         let mut pool = Pool::<TUVec3u8>::with_capacity(16);
 
         for i in 0..16 {
-            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i as u32));
+            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i.into()));
         }
 
         for i in 0..4 {
@@ -886,7 +1173,7 @@ mod tests {
         let mut pool = Pool::<TUVec3u8>::with_capacity(16);
 
         for i in 0..16 {
-            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i as u32));
+            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i.into()));
         }
 
         pool.tombstone(ElementId(4));
@@ -907,7 +1194,7 @@ mod tests {
         let mut pool = Pool::<TUVec3u8>::with_capacity(16);
 
         for i in 0..16 {
-            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i as u32));
+            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i.into()));
         }
 
         pool.remove(ElementId(4));
@@ -928,7 +1215,7 @@ mod tests {
         let mut pool = Pool::<TUVec3u8>::with_capacity(16);
 
         for i in 0..16 {
-            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i as u32));
+            assert_eq!(pool.insert(TUVec3u8::new(i, i, i)), ElementId(i.into()));
         }
 
         pool.tombstone(ElementId(4));
@@ -946,4 +1233,184 @@ mod tests {
         assert_eq!(pool.len(), 13);
         assert_eq!(pool.garbage_len(), 3);
     }
+    #[test]
+    fn test_retain() {
+        let mut pool = Pool::<u8>::new();
+        for i in 0..16 {
+            assert_eq!(pool.insert(i), ElementId(i.into()));
+        }
+
+        pool.retain(|id, item| {
+            *item += 1;
+            id.0 % 2 == 0
+        });
+
+        assert_eq!(pool.len(), 8);
+        assert_eq!(pool.garbage_len(), 8);
+        assert!(pool.contains(ElementId(2)));
+        assert!(!pool.contains(ElementId(3)));
+        assert_eq!(pool[ElementId(4)], 5);
+
+        assert_eq!(pool.insert(100), ElementId(15));
+    }
+
+    #[test]
+    fn test_blocks() {
+        let mut pool = Pool::<u8, NodeId>::new();
+        pool.insert(0);
+        for _ in 0..3 {
+            pool.try_insert_block(|i| i as u8).unwrap();
+        }
+        assert_eq!(pool.len(), 25);
+
+        // Block 9..17 has a filled item, so it's retained
+        pool.retain(|id, _| usize::from(id) == 9 || usize::from(id) < 9);
+        assert_eq!(pool.len(), 9 + 8);
+        assert_eq!(pool.garbage, (17..25).collect::<Vec<_>>());
+
+        pool.shrink_to_fit();
+        assert_eq!(pool.vec.len(), 17);
+        assert_eq!(pool.garbage_len(), 0);
+
+        pool.tombstone_block(NodeId(1));
+        pool.garbage.swap(0, 1);
+        assert!(matches!(
+            pool.try_insert_block(|i| i as u8),
+            Err(TreeError::CorruptGarbage(_))
+        ));
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut pool = Pool::<u8, NodeId>::with_capacity(16);
+        for i in 0..8 {
+            assert_eq!(pool.insert(i), NodeId(i.into()));
+        }
+        pool.remove(NodeId(3));
+        pool.tombstone(NodeId(5));
+
+        let drained: Vec<_> = pool.drain().collect();
+        assert_eq!(
+            drained,
+            vec![
+                (NodeId(0), 0),
+                (NodeId(1), 1),
+                (NodeId(2), 2),
+                (NodeId(4), 4),
+                (NodeId(6), 6),
+                (NodeId(7), 7)
+            ]
+        );
+        assert!(pool.is_empty());
+        assert_eq!(pool.garbage_len(), 0);
+        assert!(pool.capacity() >= 16);
+    }
+
+    #[test]
+    fn test_iter_elements() {
+        let mut pool = Pool::<u8>::new();
+        for i in 0..4 {
+            pool.insert(i);
+        }
+        pool.remove(ElementId(1));
+
+        for (id, item) in pool.iter_elements_mut() {
+            *item += id.0 as u8;
+        }
+
+        assert_eq!(
+            pool.iter_elements().collect::<Vec<_>>(),
+            vec![(ElementId(0), &0), (ElementId(2), &4), (ElementId(3), &6)]
+        );
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut pool = Pool::<u8>::new();
+        pool.reserve(32);
+        assert!(pool.capacity() >= 32);
+
+        for i in 0..32 {
+            pool.insert(i);
+        }
+        for i in 16..32 {
+            pool.remove(ElementId(i));
+        }
+        pool.remove(ElementId(4));
+
+        pool.shrink_to_fit();
+        assert_eq!(pool.len(), 15);
+        assert_eq!(pool.garbage_len(), 1);
+        assert_eq!(pool.vec.len(), 16);
+        assert_eq!(pool[ElementId(15)], 15);
+
+        assert_eq!(pool.insert(4), ElementId(4));
+        assert_eq!(pool.insert(16), ElementId(16));
+    }
+
+    #[test]
+    fn test_side_data() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8u8), 8));
+        let mut names = Pool::<&str>::new();
+
+        let c1 = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+        assert_eq!(names.insert("c1"), c1);
+        let c2 = tree.insert(TUVec3u8::new(2, 2, 2)).unwrap();
+        assert_eq!(names.insert("c2"), c2);
+
+        tree.remove(c1).unwrap();
+        assert_eq!(names.remove(c1), Some("c1"));
+
+        let c3 = tree.insert(TUVec3u8::new(3, 3, 3)).unwrap();
+        assert_eq!(names.insert("c3"), c3);
+        assert_eq!(c3, c1);
+
+        assert_eq!(names[c2], "c2");
+        assert_eq!(names[c3], "c3");
+    }
+
+    #[test]
+    fn test_child_blocks() {
+        assert_eq!(
+            std::mem::size_of::<NodeType>(),
+            2 * std::mem::size_of::<IdType>()
+        );
+
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8u8), 8));
+
+        let c1 = tree.insert(DummyCell::new(TUVec3::splat(1))).unwrap();
+        let c2 = tree.insert(DummyCell::new(TUVec3::splat(2))).unwrap();
+        let c3 = tree.insert(DummyCell::new(TUVec3::splat(15))).unwrap();
+        assert_eq!(tree.nodes.len(), 25);
+
+        let NodeType::Branch(root) = tree.nodes[tree.root].ntype else {
+            panic!("root is not a branch");
+        };
+        assert_eq!(root.first_child, NodeId(1));
+        for (i, child) in root.children().into_iter().enumerate() {
+            assert_eq!(child, NodeId(1 + i as IdType));
+            assert_eq!(tree.nodes[child].parent, Some(tree.root));
+            assert_eq!(
+                tree.nodes[child].aabb,
+                tree.nodes[tree.root].aabb.split()[i]
+            );
+        }
+
+        tree.remove(c1).unwrap();
+        tree.remove(c2).unwrap();
+        assert_eq!(tree.nodes.len(), 9);
+        assert_eq!(tree.nodes.garbage_len(), 16);
+
+        // Both freed blocks are reused, the third one is appended
+        tree.insert(DummyCell::new(TUVec3::splat(14))).unwrap();
+        assert_eq!(tree.nodes.len(), 33);
+        assert_eq!(tree.nodes.garbage_len(), 0);
+        assert_eq!(tree.find(&TUVec3::splat(14)), Some(c2));
+        assert_eq!(tree.find(&TUVec3::splat(15)), Some(c3));
+
+        let NodeType::Branch(branch) = tree.nodes[root.x1_y1_z1()].ntype else {
+            panic!("octant is not a branch");
+        };
+        assert_eq!(branch.first_child, NodeId(9));
+    }
 }
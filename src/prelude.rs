@@ -2,7 +2,10 @@
 
 pub use crate::{
     bounding::{Aabb, TUVec3, TUVec3u128, TUVec3u16, TUVec3u32, TUVec3u64, TUVec3u8, Unsigned},
+    compact::CompactOctree,
+    linear::LinearOctree,
     node::NodeType,
+    pool::Pool,
     tree::Octree,
     ElementId, NodeId, Position, TreeError, Volume,
 };
@@ -0,0 +1,267 @@
+//! [`serde`] support.
+//!
+//! [`Octree`], [`Pool`], [`Node`], [`NodeType`], [`Aabb`], [`TUVec3`],
+//! [`ElementId`] and [`NodeId`] implement `Serialize` and `Deserialize`.
+//!
+//! Deserialization [`validates`](Octree::validate) the tree structure.
+//! Corrupted input results in a [`TreeError`] wrapped into the deserializer's error,
+//! never in a corrupted tree.
+//!
+//! ```rust
+//! use oktree::prelude::*;
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16u8).unwrap());
+//! let c1_id = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//!
+//! let json = serde_json::to_string(&tree).unwrap();
+//! let tree: Octree<u8, TUVec3u8> = serde_json::from_str(&json).unwrap();
+//!
+//! assert_eq!(tree.find(&TUVec3::new(1, 1, 1)), Some(c1_id));
+//! ```
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, Unsigned},
+    node::Node,
+    pool::{Pool, PoolItem},
+    tree::Octree,
+    ElementId, NodeId, TreeError, Volume,
+};
+
+/// Unvalidated [`Pool`] as it comes from the deserializer.
+#[doc(hidden)]
+#[derive(Deserialize)]
+pub struct RawPool<T> {
+    vec: Vec<PoolItem<T>>,
+    garbage: Vec<usize>,
+}
+
+impl<T, I> TryFrom<RawPool<T>> for Pool<T, I> {
+    type Error = TreeError;
+
+    /// Checks that every garbage index points to a removed item exactly once.
+    fn try_from(raw: RawPool<T>) -> Result<Self, Self::Error> {
+        let RawPool { vec, garbage } = raw;
+
+        let mut seen = vec![false; vec.len()];
+        for &index in garbage.iter() {
+            match vec.get(index) {
+                None => {
+                    return Err(TreeError::CorruptGarbage(format!(
+                        "Garbage index {index} is out of the pool of length {}",
+                        vec.len()
+                    )))
+                }
+                Some(PoolItem::Filled(_)) => {
+                    return Err(TreeError::CorruptGarbage(format!(
+                        "Garbage index {index} points to a filled item"
+                    )))
+                }
+                Some(_) if seen[index] => {
+                    return Err(TreeError::CorruptGarbage(format!(
+                        "Garbage index {index} is duplicated"
+                    )))
+                }
+                Some(_) => seen[index] = true,
+            }
+        }
+
+        Ok(Pool {
+            vec,
+            garbage,
+            blocks: false,
+            _id: Default::default(),
+        })
+    }
+}
+
+/// Unvalidated [`Octree`] as it comes from the deserializer.
+#[doc(hidden)]
+#[derive(Deserialize)]
+pub struct RawOctree<U: Unsigned, T> {
+    aabb: Option<Aabb<U>>,
+    elements: Pool<T>,
+    nodes: Pool<Node<U>, NodeId>,
+    root: NodeId,
+    #[serde(default)]
+    buckets: HashMap<NodeId, Vec<ElementId>>,
+    #[serde(default)]
+    max_depth: Option<usize>,
+    #[serde(default)]
+    min_size: Option<U>,
+}
+
+impl<U, T, A> TryFrom<RawOctree<U, T>> for Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    type Error = TreeError;
+
+    fn try_from(raw: RawOctree<U, T>) -> Result<Self, Self::Error> {
+        let RawOctree {
+            aabb,
+            elements,
+            nodes,
+            root,
+            buckets,
+            max_depth,
+            min_size,
+        } = raw;
+
+        let min_size = min_size.unwrap_or(U::one());
+        if min_size == U::zero() {
+            return Err(TreeError::InvalidStructure(
+                "Minimum node size is zero".into(),
+            ));
+        }
+
+        let mut nodes = nodes.map(|node| Node {
+            aabb: node.aabb,
+            ntype: node.ntype,
+            parent: node.parent,
+            aggregate: A::empty(),
+        });
+        nodes.blocks = true;
+        let mut tree = Octree {
+            aabb,
+            elements,
+            nodes,
+            root,
+            buckets,
+            max_depth: max_depth.unwrap_or(usize::MAX),
+            min_size,
+        };
+        if let Err(mut violations) = tree.validate() {
+            return Err(violations.swap_remove(0));
+        }
+        tree.refresh_aggregates();
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bounding::{TUVec3, TUVec3u8},
+        node::NodeType,
+        ElementId,
+    };
+
+    type Tree = Octree<u8, TUVec3u8>;
+
+    fn tree() -> Tree {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8));
+        for i in 0..16 {
+            tree.insert(TUVec3u8::new(i, 15 - i, i / 2)).unwrap();
+        }
+        tree.remove(ElementId(3)).unwrap();
+        tree.remove(ElementId(4)).unwrap();
+        tree
+    }
+
+    fn roundtrip(tree: &Tree) -> Result<Tree, String> {
+        let json = serde_json::to_string(tree).unwrap();
+        serde_json::from_str(&json).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let tree = tree();
+        let restored = roundtrip(&tree).unwrap();
+
+        assert_eq!(restored.len(), tree.len());
+        assert_eq!(restored.nodes.garbage, tree.nodes.garbage);
+        assert_eq!(restored.elements.garbage, tree.elements.garbage);
+        for (e, element) in tree.iter_elements() {
+            assert_eq!(restored.find(&element.0), Some(e));
+        }
+
+        let mut restored = restored;
+        assert_eq!(restored.remove(ElementId(5)), Ok(()));
+        assert!(restored.insert(TUVec3u8::new(4, 11, 2)).is_ok());
+
+        let tree = Tree::default();
+        let restored = roundtrip(&tree).unwrap();
+        assert!(restored.is_empty());
+    }
+
+    #[test]
+    fn test_buckets() {
+        let mut tree =
+            Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8)).with_max_depth(1);
+        for i in 0..16 {
+            tree.insert(TUVec3u8::new(i, 15 - i, i / 2)).unwrap();
+        }
+        let restored = roundtrip(&tree).unwrap();
+        assert_eq!(restored.max_depth(), 1);
+        assert_eq!(restored.buckets, tree.buckets);
+
+        let bucket = *tree.buckets.keys().next().unwrap();
+        tree.buckets.get_mut(&bucket).unwrap().truncate(1);
+        let err = roundtrip(&tree).unwrap_err();
+        assert!(err.contains("less than 2 elements"), "{err}");
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(serde_json::to_string(&ElementId(42)).unwrap(), "42");
+        assert_eq!(serde_json::from_str::<NodeId>("7").unwrap(), NodeId(7));
+    }
+
+    #[test]
+    fn test_corrupt_garbage() {
+        let mut tree = tree();
+        tree.elements.garbage.push(0);
+        let err = roundtrip(&tree).unwrap_err();
+        assert!(err.contains("garbage is corrupted"), "{err}");
+
+        let mut tree = self::tree();
+        tree.remove(ElementId(5)).unwrap();
+        tree.remove(ElementId(6)).unwrap();
+        assert!(!tree.nodes.garbage.is_empty());
+        tree.nodes.garbage.pop();
+        let err = roundtrip(&tree).unwrap_err();
+        assert!(err.contains("contiguous block"), "{err}");
+    }
+
+    #[test]
+    fn test_corrupt_leaf() {
+        let mut tree = tree();
+        let leaf = tree
+            .nodes
+            .iter_elements()
+            .find_map(|(id, node)| match node.ntype {
+                NodeType::Leaf(_) => Some(id),
+                _ => None,
+            });
+        tree.nodes[leaf.unwrap()].ntype = NodeType::Leaf(ElementId(3));
+        let err = roundtrip(&tree).unwrap_err();
+        assert!(err.contains("points to a missing"), "{err}");
+    }
+
+    #[test]
+    fn test_corrupt_child() {
+        let mut tree = tree();
+        let child = NodeId(5);
+        tree.nodes[child].aabb = tree.nodes[tree.root].aabb;
+        let err = roundtrip(&tree).unwrap_err();
+        assert!(err.contains("doesn't match the octant"), "{err}");
+
+        let mut tree = self::tree();
+        tree.nodes[child].parent = Some(child);
+        let err = roundtrip(&tree).unwrap_err();
+        assert!(err.contains("not linked to it's parent"), "{err}");
+
+        let mut tree = self::tree();
+        tree.nodes[tree.root].ntype = NodeType::Empty;
+        let err = roundtrip(&tree).unwrap_err();
+        assert!(err.contains("unreachable from the root"), "{err}");
+    }
+}
@@ -0,0 +1,223 @@
+//! Tree statistics and memory report.
+//!
+//! ```rust
+//! use oktree::prelude::*;
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! let c1_id = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(14, 14, 14)).unwrap();
+//! tree.remove(c1_id).unwrap();
+//!
+//! let stats = tree.stats();
+//! assert_eq!(stats.leaves, 1);
+//! assert_eq!(stats.empty, 7);
+//! assert_eq!(stats.element_garbage, 1);
+//! assert_eq!(stats.max_depth(), 1);
+//! ```
+
+use std::{collections::HashMap, mem::size_of};
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::Unsigned,
+    node::NodeType,
+    tree::{leaf_elements, Octree},
+    ElementId, NodeId, Volume,
+};
+
+/// Snapshot of the tree's shape and memory usage.
+///
+/// Returned by [`Octree::stats`].
+#[derive(Default, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    /// Number of the [`Empty`](NodeType::Empty) nodes.
+    pub empty: usize,
+
+    /// Number of the [`Leaf`](NodeType::Leaf) nodes.
+    pub leaves: usize,
+
+    /// Number of the [`Bucket`](NodeType::Bucket) nodes.
+    pub buckets: usize,
+
+    /// Number of the [`Branch`](NodeType::Branch) nodes.
+    pub branches: usize,
+
+    /// Number of nodes at each depth. Root has depth `0`.
+    pub depth_histogram: Vec<usize>,
+
+    /// Number of the stored elements.
+    pub elements: usize,
+
+    /// Average number of elements per leaf or bucket.
+    ///
+    /// Elements, spanning several leaves, are counted in each of them.
+    pub elements_per_leaf: f64,
+
+    /// Number of elements, stored in more than one leaf or bucket.
+    pub multi_leaf_elements: usize,
+
+    /// Number of the removed elements, waiting for reuse.
+    pub element_garbage: usize,
+
+    /// Number of the removed nodes, waiting for reuse.
+    pub node_garbage: usize,
+
+    /// Estimated heap bytes of the element [`Pool`](crate::pool::Pool).
+    pub element_bytes: usize,
+
+    /// Estimated heap bytes of the node [`Pool`](crate::pool::Pool) and the bucket elements.
+    pub node_bytes: usize,
+}
+
+impl Stats {
+    /// Total number of the nodes.
+    pub fn nodes(&self) -> usize {
+        self.empty + self.leaves + self.buckets + self.branches
+    }
+
+    /// Depth of the deepest node.
+    pub fn max_depth(&self) -> usize {
+        self.depth_histogram.len().saturating_sub(1)
+    }
+
+    /// Estimated heap bytes of the whole tree.
+    pub fn heap_bytes(&self) -> usize {
+        self.element_bytes + self.node_bytes
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Collects the [`Stats`] of the tree.
+    ///
+    /// Walks every node, so it's `O(nodes)`.
+    /// Heap sizes are estimated from the capacities of the pools
+    /// and don't include the memory, owned by the elements themselves.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            elements: self.elements.len(),
+            element_garbage: self.elements.garbage_len(),
+            node_garbage: self.nodes.garbage_len(),
+            element_bytes: self.elements.heap_bytes(),
+            node_bytes: self.nodes.heap_bytes() + buckets_heap_bytes(&self.buckets),
+            ..Default::default()
+        };
+
+        let mut stored = vec![0u8; self.elements.vec.len()];
+        let mut references = 0;
+        let mut stack = vec![(self.root, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            if stats.depth_histogram.len() <= depth {
+                stats.depth_histogram.resize(depth + 1, 0);
+            }
+            stats.depth_histogram[depth] += 1;
+
+            let n = &self.nodes[node];
+            match n.ntype {
+                NodeType::Empty => stats.empty += 1,
+
+                NodeType::Leaf(_) | NodeType::Bucket => {
+                    match n.ntype {
+                        NodeType::Leaf(_) => stats.leaves += 1,
+                        _ => stats.buckets += 1,
+                    }
+
+                    for &e in leaf_elements(&self.buckets, node, &n.ntype) {
+                        let count = &mut stored[usize::from(e)];
+                        *count = count.saturating_add(1);
+                        references += 1;
+                    }
+                }
+
+                NodeType::Branch(branch) => {
+                    stats.branches += 1;
+                    stack.extend(branch.children().map(|child| (child, depth + 1)));
+                }
+            }
+        }
+
+        stats.multi_leaf_elements = stored.iter().filter(|&&count| count > 1).count();
+        if stats.leaves + stats.buckets > 0 {
+            stats.elements_per_leaf = references as f64 / (stats.leaves + stats.buckets) as f64;
+        }
+
+        stats
+    }
+}
+
+fn buckets_heap_bytes(buckets: &HashMap<NodeId, Vec<ElementId>>) -> usize {
+    buckets.capacity() * size_of::<(NodeId, Vec<ElementId>)>()
+        + buckets
+            .values()
+            .map(|bucket| bucket.capacity() * size_of::<ElementId>())
+            .sum::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::{Aabb, TUVec3, TUVec3u8};
+    use crate::tests::{empty_tree, DummyVolume};
+
+    #[test]
+    fn test_stats() {
+        let mut tree = empty_tree();
+        assert_eq!(
+            tree.stats(),
+            Stats {
+                empty: 1,
+                depth_histogram: vec![1],
+                node_bytes: tree.stats().node_bytes,
+                ..Default::default()
+            }
+        );
+
+        tree.insert(DummyVolume::new(Aabb::new_unchecked(TUVec3::splat(8), 2)))
+            .unwrap();
+        tree.insert(DummyVolume::new(Aabb::new_unchecked(
+            TUVec3::new(1, 1, 1),
+            1,
+        )))
+        .unwrap();
+        let removed = tree
+            .insert(DummyVolume::new(Aabb::new_unchecked(
+                TUVec3::new(14, 14, 14),
+                1,
+            )))
+            .unwrap();
+        tree.remove(removed).unwrap();
+
+        let stats = tree.stats();
+        assert_eq!(stats.nodes(), tree.nodes.len());
+        // The octant of the removed element keeps the spanning one
+        assert_eq!(stats.branches, 3);
+        assert_eq!(stats.leaves, 9);
+        assert_eq!(stats.empty, 13);
+        assert_eq!(stats.depth_histogram, vec![1, 8, 16]);
+        assert_eq!(stats.max_depth(), 2);
+        assert_eq!(stats.elements, 2);
+        assert_eq!(stats.multi_leaf_elements, 1);
+        assert_eq!(stats.elements_per_leaf, 1.0);
+        assert_eq!(stats.element_garbage, 1);
+        assert!(stats.heap_bytes() > 0);
+    }
+
+    #[test]
+    fn test_buckets() {
+        let mut tree =
+            Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8)).with_max_depth(0);
+        for i in 0..4 {
+            tree.insert(TUVec3u8::new(i, i, i)).unwrap();
+        }
+
+        let stats = tree.stats();
+        assert_eq!(stats.buckets, 1);
+        assert_eq!(stats.elements_per_leaf, 4.0);
+        assert_eq!(stats.multi_leaf_elements, 0);
+    }
+}
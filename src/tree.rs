@@ -1,6 +1,9 @@
 //! [Octree] implementation
 
+use std::collections::HashMap;
+
 use crate::{
+    aggregate::Aggregate,
     bounding::{Aabb, TUVec3, Unsigned},
     node::{Branch, Node, NodeType},
     pool::{Pool, PoolElementIterator, PoolIntoIterator, PoolItem, PoolIterator, PoolIteratorMut},
@@ -15,22 +18,69 @@ use smallvec::SmallVec;
 /// such as intersections, ray casting e.t.c
 /// All coordinates should be positive and integer ([`Unsigned`](num::Unsigned)),
 /// due to applied optimisations.
-#[derive(Default, Clone)]
-pub struct Octree<U, T>
+///
+/// Optional [`Aggregate`] type parameter keeps the aggregated values in the nodes,
+/// see [`aggregate`](crate::aggregate) module.
+///
+/// Space is subdivided until the [`unit`](Aabb::unit) nodes by default.
+/// Set the [`max depth`](Octree::with_max_depth) or the [`min size`](Octree::with_min_size)
+/// to stop earlier. Leaves at the limit keep several elements in a [`bucket`](Octree::bucket).
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        try_from = "crate::serialization::RawOctree<U, T>",
+        bound(
+            serialize = "U: serde::Serialize, T: serde::Serialize",
+            deserialize = "U: serde::Deserialize<'de>, T: serde::Deserialize<'de>, A: Aggregate<T>"
+        )
+    )
+)]
+pub struct Octree<U, T, A = ()>
 where
     U: Unsigned,
     T: Volume<U = U>,
+    A: Aggregate<T>,
 {
     /// aabb used for clearing the octree
-    aabb: Option<Aabb<U>>,
+    pub(crate) aabb: Option<Aabb<U>>,
 
     /// [`Pool`] of stored elements. Access it by [`ElementId`]
     pub(crate) elements: Pool<T>,
 
     /// [`Pool`] of tree [`Nodes`](crate::node::Node). Access it by [`NodeId`]
-    pub(crate) nodes: Pool<Node<U>>,
+    pub(crate) nodes: Pool<Node<U, A>, NodeId>,
 
     pub(crate) root: NodeId,
+
+    /// Elements of the [`bucket`](NodeType::Bucket) nodes
+    pub(crate) buckets: HashMap<NodeId, Vec<ElementId>>,
+
+    /// Nodes at this depth are not subdivided
+    pub(crate) max_depth: usize,
+
+    /// Nodes, smaller than twice this size, are not subdivided
+    pub(crate) min_size: U,
+}
+
+impl<U, T, A> Default for Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    fn default() -> Self {
+        Octree {
+            aabb: None,
+            elements: Default::default(),
+            nodes: Pool::from_aabb(Aabb::default(), A::empty()),
+            root: Default::default(),
+            buckets: Default::default(),
+            max_depth: usize::MAX,
+            min_size: U::one(),
+        }
+    }
 }
 
 impl<U, T> Octree<U, T>
@@ -43,12 +93,7 @@ where
     /// `aabb` should be positive and it's dimensions should be the power of 2.
     /// The root node will adopt aabb's dimensions.
     pub fn from_aabb(aabb: Aabb<U>) -> Self {
-        Octree {
-            aabb: Some(aabb),
-            elements: Default::default(),
-            nodes: Pool::from_aabb(aabb),
-            root: Default::default(),
-        }
+        Self::from_aabb_with_aggregate(aabb)
     }
 
     /// Construct a tree with capacity for it's pools.
@@ -57,9 +102,9 @@ where
     pub fn with_capacity(capacity: usize) -> Self {
         Octree {
             aabb: None,
-            elements: Pool::<T>::with_capacity(capacity),
-            nodes: Pool::<Node<U>>::with_capacity(capacity),
-            root: Default::default(),
+            elements: Pool::with_capacity(capacity),
+            nodes: Pool::from_aabb_with_capacity(Aabb::default(), capacity, ()),
+            ..Default::default()
         }
     }
 
@@ -69,14 +114,118 @@ where
     /// Helps to reduce the amount of the memory reallocations.
     /// The root node will adopt aabb's dimensions.
     pub fn from_aabb_with_capacity(aabb: Aabb<U>, capacity: usize) -> Self {
+        Self::from_aabb_with_capacity_and_aggregate(aabb, capacity)
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Construct a tree with an [`Aggregate`] from [`Aabb`].
+    ///
+    /// `aabb` should be positive and it's dimensions should be the power of 2.
+    /// The root node will adopt aabb's dimensions.
+    pub fn from_aabb_with_aggregate(aabb: Aabb<U>) -> Self {
         Octree {
             aabb: Some(aabb),
-            elements: Pool::<T>::with_capacity(capacity),
-            nodes: Pool::<Node<U>>::from_aabb_with_capacity(aabb, capacity),
-            root: Default::default(),
+            nodes: Pool::from_aabb(aabb, A::empty()),
+            ..Default::default()
         }
     }
 
+    /// Construct a tree with an [`Aggregate`] from [`Aabb`] and capacity.
+    ///
+    /// `aabb` should be positive and it's dimensions should be the power of 2.
+    /// Helps to reduce the amount of the memory reallocations.
+    /// The root node will adopt aabb's dimensions.
+    pub fn from_aabb_with_capacity_and_aggregate(aabb: Aabb<U>, capacity: usize) -> Self {
+        Octree {
+            aabb: Some(aabb),
+            elements: Pool::with_capacity(capacity),
+            nodes: Pool::from_aabb_with_capacity(aabb, capacity, A::empty()),
+            ..Default::default()
+        }
+    }
+
+    /// Limits the depth of the tree. Root has depth `0`.
+    ///
+    /// Leaves at the `max_depth` are not subdivided and keep several elements.
+    /// Applies to the subsequent insertions.
+    ///
+    /// ```rust
+    /// use oktree::prelude::*;
+    ///
+    /// let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(16), 16).unwrap()).with_max_depth(2);
+    /// tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+    /// tree.insert(TUVec3u8::new(2, 2, 2)).unwrap();
+    ///
+    /// assert_eq!(tree.depth(), 2);
+    /// ```
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Limits the size of the nodes.
+    ///
+    /// Nodes are not subdivided into the children smaller than `min_size`
+    /// and keep several elements. Applies to the subsequent insertions.
+    pub fn with_min_size(mut self, min_size: U) -> Self {
+        self.min_size = min_size.max(U::one());
+        self
+    }
+
+    /// Returns the max depth of the tree, set by [`with_max_depth`](Octree::with_max_depth).
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Returns the min node size of the tree, set by [`with_min_size`](Octree::with_min_size).
+    pub fn min_size(&self) -> U {
+        self.min_size
+    }
+
+    /// Returns the current depth of the tree, the depth of it's deepest node.
+    ///
+    /// Tree with the only root node has depth `0`.
+    pub fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut stack: SmallVec<[(NodeId, usize); 32]> = SmallVec::new();
+        stack.push((self.root, 0));
+        while let Some((node, d)) = stack.pop() {
+            depth = depth.max(d);
+            if let NodeType::Branch(branch) = self.nodes[node].ntype {
+                stack.extend(branch.children().into_iter().map(|child| (child, d + 1)));
+            }
+        }
+        depth
+    }
+
+    /// Returns the elements of the [`bucket`](NodeType::Bucket) `node`.
+    ///
+    /// Returns an empty slice for the other nodes.
+    pub fn bucket(&self, node: NodeId) -> &[ElementId] {
+        self.buckets
+            .get(&node)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Returns the elements of the leaf or the bucket `node`.
+    pub(crate) fn leaf_elements(&self, node: NodeId) -> &[ElementId] {
+        leaf_elements(&self.buckets, node, &self.nodes[node].ntype)
+    }
+
+    /// Can the leaf `node` at the `depth` be subdivided.
+    #[inline]
+    fn can_split(&self, node: NodeId, depth: usize) -> bool {
+        let size = self.nodes[node].aabb.size();
+        depth < self.max_depth && size / (U::one() + U::one()) >= self.min_size
+    }
+
     /// Insert an element into a tree.
     ///
     /// Recursively subdivide the space, creating new [`nodes`](crate::node::Node)
@@ -94,26 +243,35 @@ where
     pub fn insert(&mut self, elem: T) -> Result<ElementId, TreeError> {
         let volume = elem.volume();
         if self.nodes[self.root].aabb.overlaps(&volume) {
-            let element = self.elements.insert(elem);
+            let element = self.elements.try_insert(elem)?;
 
             let mut insertions: SmallVec<[Insertion<U>; 10]> = SmallVec::new();
             insertions.push(Insertion {
                 element,
                 node: self.root,
                 volume,
+                depth: 0,
             });
 
+            // Nodes, whose type was changed. Their aggregates are refreshed at the end.
+            let mut changed: SmallVec<[NodeId; 10]> = SmallVec::new();
+
             let mut was_inserted = false;
             while let Some(insertion) = insertions.pop() {
-                match self._insert(insertion, &mut insertions) {
+                match self._insert(insertion, &mut insertions, &mut changed) {
                     Ok(e) => was_inserted |= e == Some(element),
                     Err(err) => {
-                        self.elements.tombstone(element);
+                        changed.into_iter().for_each(|node| self.refresh_up(node));
+                        // Some leaves may already point to the element. Removal clears them
+                        // and collapses the branches left empty, before tombstoning it.
+                        let _ = self.remove(element);
                         return Err(err);
                     }
                 }
             }
 
+            changed.into_iter().for_each(|node| self.refresh_up(node));
+
             if !was_inserted {
                 self.elements.tombstone(element);
                 return Err(TreeError::AlreadyOccupied(format!(
@@ -136,17 +294,20 @@ where
         &mut self,
         insertion: Insertion<U>,
         insertions: &mut SmallVec<[Insertion<U>; C]>,
+        changed: &mut SmallVec<[NodeId; C]>,
     ) -> Result<Option<ElementId>, TreeError> {
         let Insertion {
             element,
             node,
             volume,
+            depth,
         } = insertion;
 
         let n = &mut self.nodes[node];
         match n.ntype {
             NodeType::Empty => {
                 n.ntype = NodeType::Leaf(element);
+                changed.push(node);
                 Ok(Some(element))
             }
 
@@ -161,25 +322,50 @@ where
                     return Ok(None);
                 }
 
-                let children = self.nodes.branch(node);
+                if !self.can_split(node, depth) {
+                    self.nodes[node].ntype = NodeType::Bucket;
+                    self.buckets.insert(node, vec![e, element]);
+                    changed.push(node);
+                    return Ok(Some(element));
+                }
+
+                let first_child = self.nodes.branch(node, A::empty())?;
                 let n = &mut self.nodes[node];
 
-                n.ntype = NodeType::Branch(Branch::new(children));
+                n.ntype = NodeType::Branch(Branch::new(first_child));
+                changed.push(node);
                 insertions.push(insertion);
                 insertions.push(Insertion {
                     element: e,
                     node,
                     volume: e1,
+                    depth,
                 });
                 Ok(None)
             }
 
+            NodeType::Bucket => {
+                let elements = &self.elements;
+                let bucket = self.buckets.get_mut(&node).unwrap();
+                if bucket
+                    .iter()
+                    .any(|&e| elements[e].volume().overlaps(&volume))
+                {
+                    return Ok(None);
+                }
+
+                bucket.push(element);
+                changed.push(node);
+                Ok(Some(element))
+            }
+
             NodeType::Branch(branch) => {
                 branch.walk_children_exclusive(&self.nodes, &volume, |child| {
                     insertions.push(Insertion {
                         element,
                         node: child,
                         volume,
+                        depth: depth + 1,
                     });
                 });
                 Ok(None)
@@ -249,15 +435,33 @@ where
             NodeType::Empty => Ok(()),
 
             NodeType::Leaf(e) if e == element => {
-                self.nodes[node].ntype = NodeType::Empty;
+                let n = &mut self.nodes[node];
+                n.ntype = NodeType::Empty;
+                n.aggregate = A::empty();
                 if let Some(parent) = parent {
-                    self.nodes.maybe_collapse(parent);
+                    let changed = self.nodes.maybe_collapse(parent);
+                    self.refresh_up(changed);
                 }
                 Ok(())
             }
 
             NodeType::Leaf(_) => Ok(()),
 
+            NodeType::Bucket => {
+                let bucket = self.buckets.get_mut(&node).unwrap();
+                let Some(i) = bucket.iter().position(|&e| e == element) else {
+                    return Ok(());
+                };
+
+                bucket.swap_remove(i);
+                if let [last] = bucket[..] {
+                    self.buckets.remove(&node);
+                    self.nodes[node].ntype = NodeType::Leaf(last);
+                }
+                self.refresh_up(node);
+                Ok(())
+            }
+
             NodeType::Branch(branch) => {
                 branch.walk_children_inclusive(&self.nodes, &volume, |child| {
                     removals.push(Removal {
@@ -276,11 +480,9 @@ where
     /// reused for new elements without causing any memory reallocations.
     pub fn clear(&mut self) {
         self.elements.clear();
-        if let Some(aabb) = self.aabb {
-            self.nodes.clear_with_aabb(aabb);
-        } else {
-            self.nodes.clear();
-        }
+        self.buckets.clear();
+        self.nodes
+            .reset_with_aabb(self.aabb.unwrap_or_default(), A::empty());
         self.root = Default::default();
     }
 
@@ -327,6 +529,12 @@ where
                     }
                 }
 
+                NodeType::Bucket => self
+                    .bucket(node)
+                    .iter()
+                    .copied()
+                    .find(|&e| self.elements[e].volume().contains(point)),
+
                 NodeType::Branch(ref branch) => {
                     node = branch.find_child(point, self.nodes[node].aabb.center());
                     continue;
@@ -345,6 +553,9 @@ where
     }
 
     /// Returns the element if element exists and not garbaged.
+    ///
+    /// Call [`refresh_aggregate`](Octree::refresh_aggregate) after changing the element
+    /// in a tree with an [`Aggregate`].
     pub fn get_element_mut(&mut self, element: ElementId) -> Option<&mut T> {
         if self.elements.is_garbage(element) {
             None
@@ -364,6 +575,9 @@ where
     }
 
     /// Returns the element if element exists and not garbaged.
+    ///
+    /// Call [`refresh_aggregate`](Octree::refresh_aggregate) after changing the element
+    /// in a tree with an [`Aggregate`].
     pub fn get_mut(&mut self, point: &TUVec3<U>) -> Option<&mut T> {
         let element = self.find(point)?;
         if self.elements.is_garbage(element) {
@@ -406,12 +620,15 @@ where
     }
 
     /// Returns an mutable iterator over the elements in the tree.
+    ///
+    /// Call [`refresh_aggregates`](Octree::refresh_aggregates) after changing the elements
+    /// in a tree with an [`Aggregate`].
     pub fn iter_mut(&mut self) -> PoolIteratorMut<'_, T> {
         self.elements.iter_mut()
     }
 
     /// Returns an iterator over the nodes in the tree.
-    pub fn iter_nodes(&self) -> PoolIterator<Node<U>> {
+    pub fn iter_nodes(&self) -> PoolIterator<'_, Node<U, A>> {
         self.nodes.iter()
     }
 
@@ -421,7 +638,7 @@ where
     }
 }
 
-impl<U: Unsigned, T: Volume<U = U>> std::iter::IntoIterator for Octree<U, T> {
+impl<U: Unsigned, T: Volume<U = U>, A: Aggregate<T>> std::iter::IntoIterator for Octree<U, T, A> {
     type Item = T;
     type IntoIter = PoolIntoIterator<T>;
 
@@ -430,24 +647,43 @@ impl<U: Unsigned, T: Volume<U = U>> std::iter::IntoIterator for Octree<U, T> {
     }
 }
 
-impl<U: Unsigned, T: Volume<U = U>> std::fmt::Debug for Octree<U, T>
+impl<U: Unsigned, T: Volume<U = U>, A: Aggregate<T>> std::fmt::Debug for Octree<U, T, A>
 where
     T: std::fmt::Debug,
+    A: std::fmt::Debug,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Octree")
             .field("elements", &self.elements)
             .field("nodes", &self.nodes)
             .field("root", &self.root)
+            .field("buckets", &self.buckets)
             .finish()
     }
 }
 
+/// Returns the elements of the leaf or the bucket `node` of the `ntype`.
+///
+/// Takes the buckets only, so the elements could be borrowed mutably meanwhile.
+#[inline]
+pub(crate) fn leaf_elements<'a>(
+    buckets: &'a HashMap<NodeId, Vec<ElementId>>,
+    node: NodeId,
+    ntype: &'a NodeType,
+) -> &'a [ElementId] {
+    match ntype {
+        NodeType::Leaf(e) => std::slice::from_ref(e),
+        NodeType::Bucket => buckets.get(&node).map(Vec::as_slice).unwrap_or_default(),
+        _ => &[],
+    }
+}
+
 #[derive(Debug)]
 struct Insertion<U: Unsigned> {
     element: ElementId,
     node: NodeId,
     volume: Aabb<U>,
+    depth: usize,
 }
 
 #[derive(Debug)]
@@ -455,3 +691,96 @@ struct Removal {
     parent: Option<NodeId>,
     node: NodeId,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::DummyCell;
+
+    #[test]
+    fn test_limits() {
+        let aabb = Aabb::new_unchecked(TUVec3::splat(8u8), 8);
+        let mut tree = Octree::from_aabb(aabb).with_max_depth(1);
+        assert_eq!(tree.depth(), 0);
+
+        let c1 = tree.insert(DummyCell::new(TUVec3::splat(1))).unwrap();
+        let c2 = tree.insert(DummyCell::new(TUVec3::splat(2))).unwrap();
+        let c3 = tree.insert(DummyCell::new(TUVec3::splat(3))).unwrap();
+        assert!(tree.insert(DummyCell::new(TUVec3::splat(2))).is_err());
+        assert_eq!(tree.depth(), 1);
+        assert_eq!(tree.nodes.len(), 9);
+
+        let bucket = NodeId(1);
+        assert_eq!(tree.nodes[bucket].ntype, NodeType::Bucket);
+        assert_eq!(tree.bucket(bucket), &[c1, c2, c3]);
+        assert_eq!(tree.find(&TUVec3::splat(2)), Some(c2));
+        assert_eq!(tree.find(&TUVec3::splat(4)), None);
+
+        tree.remove(c1).unwrap();
+        assert_eq!(tree.bucket(bucket), &[c3, c2]);
+        tree.remove(c2).unwrap();
+        assert_eq!(tree.nodes[bucket].ntype, NodeType::Leaf(c3));
+        assert!(tree.bucket(bucket).is_empty());
+        tree.remove(c3).unwrap();
+        assert_eq!(tree.nodes[tree.root].ntype, NodeType::Empty);
+
+        let mut tree = Octree::from_aabb(aabb).with_min_size(4);
+        assert_eq!(tree.min_size(), 4);
+        tree.insert(DummyCell::new(TUVec3::splat(1))).unwrap();
+        tree.insert(DummyCell::new(TUVec3::splat(14))).unwrap();
+        tree.insert(DummyCell::new(TUVec3::splat(2))).unwrap();
+        assert_eq!(tree.depth(), 2);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.with_min_size(0).min_size(), 1);
+
+        // Doubled min size overflows
+        let mut tree = Octree::from_aabb(aabb).with_min_size(200);
+        tree.insert(DummyCell::new(TUVec3::splat(1))).unwrap();
+        tree.insert(DummyCell::new(TUVec3::splat(2))).unwrap();
+        assert_eq!(tree.depth(), 0);
+        assert_eq!(tree.nodes[tree.root].ntype, NodeType::Bucket);
+    }
+
+    #[test]
+    #[cfg(all(feature = "index-u16", not(feature = "index-u64")))]
+    fn test_tree_index_overflow() {
+        use crate::{
+            tests::{random_point, RANGE},
+            IdType,
+        };
+
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(RANGE / 2), RANGE / 2));
+
+        let overflow = (0..RANGE).find_map(|_| match tree.insert(random_point()) {
+            Err(TreeError::IndexOverflow(_)) => Some(()),
+            _ => None,
+        });
+        assert_eq!(overflow, Some(()));
+        assert!(tree.nodes.vec.len() <= IdType::MAX as usize + 1);
+    }
+
+    #[test]
+    #[cfg(all(feature = "index-u16", not(feature = "index-u64")))]
+    fn test_tree_index_overflow_rollback() {
+        use crate::tests::{DummyVolume, RANGE};
+        use rand::Rng;
+
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(RANGE / 2), RANGE / 2));
+        let mut rnd = rand::thread_rng();
+
+        let mut overflows = 0;
+        while overflows < 16 {
+            let min = TUVec3::new(
+                rnd.gen_range(0..RANGE - 64),
+                rnd.gen_range(0..RANGE - 64),
+                rnd.gen_range(0..RANGE - 64),
+            );
+            let max = min + TUVec3::splat(rnd.gen_range(1..64));
+            let volume = DummyVolume::new(Aabb::from_min_max(min, max));
+            if let Err(TreeError::IndexOverflow(_)) = tree.insert(volume) {
+                overflows += 1;
+            }
+        }
+        assert_eq!(tree.validate(), Ok(()));
+    }
+}
@@ -0,0 +1,374 @@
+//! Structural integrity checks.
+//!
+//! [`Octree::validate`] walks the whole tree and reports every broken invariant,
+//! not only the first one. Useful in tests, after deserialization
+//! or after a manual tinkering with the tree.
+//!
+//! ```rust
+//! use oktree::prelude::*;
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! let c1_id = tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(14, 14, 14)).unwrap();
+//! tree.remove(c1_id).unwrap();
+//!
+//! assert_eq!(tree.validate(), Ok(()));
+//! ```
+
+use std::collections::HashSet;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::Unsigned,
+    node::NodeType,
+    pool::{Pool, PoolItem},
+    tree::Octree,
+    TreeError, Volume,
+};
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Checks the structural invariants of the tree:
+    /// - garbage of both pools points to the removed items exactly once,
+    ///   freed nodes form contiguous blocks of 8 children,
+    /// - root is a filled node without a parent, matching the tree's [`Aabb`](crate::bounding::Aabb),
+    /// - children of every branch tile it's [`Aabb`](crate::bounding::Aabb) exactly
+    ///   and are linked back to it,
+    /// - no removed node is reachable and every filled node is reachable from the root,
+    /// - leaves and buckets point to filled elements, overlapping them,
+    /// - buckets hold at least 2 distinct, non-overlapping elements,
+    /// - every filled element is stored in at least one leaf or bucket,
+    /// - no branch with only empty children remains uncollapsed.
+    ///
+    /// Returns the list of all found violations.
+    /// Violations of the garbage are reported as [`TreeError::CorruptGarbage`],
+    /// the rest as [`TreeError::InvalidStructure`].
+    ///
+    /// Walks every node, so it's `O(nodes + elements)`.
+    pub fn validate(&self) -> Result<(), Vec<TreeError>> {
+        let mut violations = Vec::new();
+        validate_garbage(&self.elements, "Element", &mut violations);
+        validate_garbage(&self.nodes, "Node", &mut violations);
+        validate_node_blocks(&self.nodes.garbage, &mut violations);
+        self.validate_nodes(&mut violations);
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    fn validate_nodes(&self, violations: &mut Vec<TreeError>) {
+        let invalid = TreeError::InvalidStructure;
+        let root = self.root;
+
+        let Some(root_node) = self.nodes.get(root) else {
+            violations.push(invalid(format!("Root {root} is not a filled node")));
+            return;
+        };
+
+        if root_node.parent.is_some() {
+            violations.push(invalid(format!("Root {root} has a parent")));
+        }
+
+        if !root_node.aabb.min.lt(&root_node.aabb.max).all() {
+            violations.push(invalid(format!(
+                "Root {root} has an empty {}",
+                root_node.aabb
+            )));
+        }
+
+        if self.aabb.is_some_and(|aabb| aabb != root_node.aabb) {
+            violations.push(invalid(format!(
+                "Tree's aabb doesn't match the root's {}",
+                root_node.aabb
+            )));
+        }
+
+        let mut stored = vec![false; self.elements.vec.len()];
+        let mut visited = vec![false; self.nodes.vec.len()];
+        visited[usize::from(root)] = true;
+
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            let n = &self.nodes[node];
+
+            match n.ntype {
+                NodeType::Empty => (),
+
+                NodeType::Leaf(e) => match self.elements.get(e) {
+                    None => {
+                        violations.push(invalid(format!("Leaf {node} points to a missing {e}")))
+                    }
+                    Some(element) if !element.volume().overlaps(&n.aabb) => {
+                        violations.push(invalid(format!("{e} is outside of it's leaf {node}")))
+                    }
+                    Some(_) => stored[usize::from(e)] = true,
+                },
+
+                NodeType::Bucket => {
+                    let Some(bucket) = self.buckets.get(&node) else {
+                        violations.push(invalid(format!("Bucket {node} has no elements")));
+                        continue;
+                    };
+
+                    if bucket.len() < 2 {
+                        violations
+                            .push(invalid(format!("Bucket {node} holds less than 2 elements")));
+                    }
+
+                    for (i, &e) in bucket.iter().enumerate() {
+                        let Some(element) = self.elements.get(e) else {
+                            violations
+                                .push(invalid(format!("Bucket {node} points to a missing {e}")));
+                            continue;
+                        };
+
+                        if !element.volume().overlaps(&n.aabb) {
+                            violations
+                                .push(invalid(format!("{e} is outside of it's bucket {node}")));
+                        }
+
+                        if let Some(&other) = bucket[..i].iter().find(|&&other| {
+                            other == e
+                                || self
+                                    .elements
+                                    .get(other)
+                                    .is_some_and(|o| o.volume().overlaps(&element.volume()))
+                        }) {
+                            violations.push(invalid(format!(
+                                "{e} overlaps {other} in the bucket {node}"
+                            )));
+                        }
+
+                        stored[usize::from(e)] = true;
+                    }
+                }
+
+                NodeType::Branch(branch) => {
+                    let first: usize = branch.first_child.into();
+                    if first % 8 != 1 || first + 8 > self.nodes.vec.len() {
+                        violations.push(invalid(format!(
+                            "Branch {node} has an invalid first child {}",
+                            branch.first_child
+                        )));
+                        continue;
+                    }
+
+                    let center = n.aabb.center();
+                    let mut empty = true;
+                    for (i, child) in branch.children().into_iter().enumerate() {
+                        let Some(c) = self.nodes.get(child) else {
+                            if self.nodes.garbage.contains(&usize::from(child)) {
+                                violations.push(TreeError::CorruptGarbage(format!(
+                                    "Removed node {child} is reachable from {node}"
+                                )));
+                            } else {
+                                violations.push(invalid(format!(
+                                    "Child {child} of {node} is not a filled node"
+                                )));
+                            }
+                            empty = false;
+                            continue;
+                        };
+
+                        if c.parent != Some(node) {
+                            violations.push(invalid(format!(
+                                "Child {child} is not linked to it's parent {node}"
+                            )));
+                        }
+
+                        if c.aabb != n.aabb._split(i, center) {
+                            violations.push(invalid(format!(
+                                "Child {child} doesn't match the octant {i} of it's parent {node}"
+                            )));
+                        }
+
+                        empty &= c.ntype == NodeType::Empty;
+
+                        if std::mem::replace(&mut visited[usize::from(child)], true) {
+                            violations.push(invalid(format!(
+                                "Child {child} of {node} is reachable twice"
+                            )));
+                        } else {
+                            stack.push(child);
+                        }
+                    }
+
+                    if empty {
+                        violations.push(invalid(format!(
+                            "Branch {node} has only empty children and should be collapsed"
+                        )));
+                    }
+                }
+            }
+        }
+
+        let unreachable = self
+            .nodes
+            .iter_elements()
+            .filter(|(node, _)| !visited[usize::from(*node)])
+            .count();
+        if unreachable > 0 {
+            violations.push(invalid(format!(
+                "{unreachable} nodes are unreachable from the root"
+            )));
+        }
+
+        if let Some(node) = self.buckets.keys().find(|&&node| {
+            !self
+                .nodes
+                .get(node)
+                .is_some_and(|n| n.ntype == NodeType::Bucket)
+        }) {
+            violations.push(invalid(format!(
+                "{node} has bucket elements, but is not a bucket"
+            )));
+        }
+
+        for (e, _) in self
+            .elements
+            .iter_elements()
+            .filter(|(e, _)| !stored[usize::from(*e)])
+        {
+            violations.push(invalid(format!("{e} is not stored in any leaf")));
+        }
+    }
+}
+
+/// Checks that every garbage index points to a removed item exactly once.
+fn validate_garbage<T, I>(pool: &Pool<T, I>, name: &str, violations: &mut Vec<TreeError>) {
+    let mut seen = HashSet::new();
+    for &index in pool.garbage.iter() {
+        let info = if index >= pool.vec.len() {
+            format!(
+                "{name} garbage index {index} is out of the pool of length {}",
+                pool.vec.len()
+            )
+        } else if !seen.insert(index) {
+            format!("{name} garbage index {index} is duplicated")
+        } else if matches!(pool.vec[index], PoolItem::Filled(_)) {
+            format!("{name} garbage index {index} points to a filled item")
+        } else {
+            continue;
+        };
+        violations.push(TreeError::CorruptGarbage(info));
+    }
+}
+
+/// Freed child blocks are pushed to the garbage as 8 consecutive indices.
+fn validate_node_blocks(garbage: &[usize], violations: &mut Vec<TreeError>) {
+    for block in garbage.chunks(8) {
+        let first = block[0];
+        let contiguous = block.len() == 8
+            && first % 8 == 1
+            && block
+                .iter()
+                .enumerate()
+                .all(|(i, &index)| index == first + i);
+
+        if !contiguous {
+            violations.push(TreeError::CorruptGarbage(format!(
+                "Node garbage {block:?} is not a contiguous block of 8 children"
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bounding::{Aabb, TUVec3, TUVec3u8},
+        tests::empty_tree,
+        ElementId, NodeId,
+    };
+
+    fn tree() -> Octree<u8, TUVec3u8> {
+        let mut tree = empty_tree();
+        for i in 0..16 {
+            tree.insert(TUVec3u8::new(i, 15 - i, i / 2)).unwrap();
+        }
+        tree.remove(ElementId(3)).unwrap();
+        tree.remove(ElementId(4)).unwrap();
+        tree
+    }
+
+    #[test]
+    fn test_valid() {
+        let mut tree = tree();
+        assert_eq!(tree.validate(), Ok(()));
+
+        for i in 0..16 {
+            let _ = tree.remove(ElementId(i));
+            assert_eq!(tree.validate(), Ok(()));
+        }
+
+        let mut limited =
+            Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(8), 8)).with_max_depth(1);
+        for i in 0..16 {
+            limited.insert(TUVec3u8::new(i, 15 - i, i / 2)).unwrap();
+        }
+        limited.remove(ElementId(0)).unwrap();
+        assert_eq!(limited.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_violations() {
+        let mut tree = tree();
+        let leaf = tree
+            .nodes
+            .iter_elements()
+            .find_map(|(id, node)| match node.ntype {
+                NodeType::Leaf(_) => Some(id),
+                _ => None,
+            })
+            .unwrap();
+        tree.nodes[leaf].ntype = NodeType::Leaf(ElementId(3));
+        tree.nodes[NodeId(5)].parent = None;
+        tree.elements.garbage.push(0);
+
+        let violations = tree.validate().unwrap_err();
+        let messages: Vec<_> = violations.iter().map(|err| err.to_string()).collect();
+        assert_eq!(violations.len(), 4, "{messages:?}");
+        assert!(matches!(violations[0], TreeError::CorruptGarbage(_)));
+        for expected in [
+            "points to a missing",
+            "not linked to it's parent",
+            "not stored in any leaf",
+        ] {
+            assert!(
+                messages.iter().any(|m| m.contains(expected)),
+                "{messages:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_branch() {
+        let mut tree = tree();
+        let NodeType::Branch(branch) = tree.nodes[tree.root].ntype else {
+            panic!("root is not a branch");
+        };
+        for child in branch.children() {
+            tree.nodes[child].ntype = NodeType::Empty;
+        }
+
+        let messages: Vec<_> = tree
+            .validate()
+            .unwrap_err()
+            .iter()
+            .map(|err| err.to_string())
+            .collect();
+        assert!(messages[0].contains("should be collapsed"), "{messages:?}");
+        assert!(
+            messages[1].contains("unreachable from the root"),
+            "{messages:?}"
+        );
+    }
+}
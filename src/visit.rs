@@ -0,0 +1,237 @@
+//! Node [`Visitor`] with depth, pruning and post-order callbacks.
+//!
+//! [`Octree::walk`] drives the visitor through the tree in depth-first order,
+//! children in the octant order, using an explicit heap stack.
+//! Custom queries, like level of detail selection or occlusion culling,
+//! could be written as visitors without reimplementing the traversal.
+//!
+//! ```rust
+//! use oktree::{node::Node, prelude::*, visit::{Visit, Visitor}};
+//!
+//! /// Collects the elements of the leaves, not smaller than `size`.
+//! struct Coarse {
+//!     size: u8,
+//!     found: Vec<ElementId>,
+//! }
+//!
+//! impl Visitor<u8> for Coarse {
+//!     fn enter_node(&mut self, _: NodeId, node: &Node<u8>, _: usize) -> Visit {
+//!         if node.aabb.size() < self.size {
+//!             Visit::Skip
+//!         } else {
+//!             Visit::Continue
+//!         }
+//!     }
+//!
+//!     fn visit_leaf(&mut self, _: NodeId, elements: &[ElementId], _: usize) -> Visit {
+//!         self.found.extend_from_slice(elements);
+//!         Visit::Continue
+//!     }
+//! }
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(2, 2, 2)).unwrap();
+//! let c3_id = tree.insert(TUVec3u8::new(14, 14, 14)).unwrap();
+//!
+//! let mut coarse = Coarse { size: 8, found: Vec::new() };
+//! assert!(tree.walk(&mut coarse));
+//! assert_eq!(coarse.found, vec![c3_id]);
+//! ```
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::Unsigned,
+    node::{Node, NodeType},
+    tree::Octree,
+    ElementId, NodeId, Volume,
+};
+
+/// Decision of a [`Visitor`] about the current node.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Visit {
+    /// Descend into the node's children or elements.
+    #[default]
+    Continue,
+
+    /// Don't descend into the node's children or elements.
+    Skip,
+
+    /// Stop the walk immediately. No more callbacks are invoked.
+    Stop,
+}
+
+/// Callbacks of the [`Octree::walk`].
+///
+/// Every callback has an empty default implementation, which continues the walk.
+pub trait Visitor<U: Unsigned, A = ()> {
+    /// Called, when the node is reached. Root has depth `0`.
+    ///
+    /// [`Skip`](Visit::Skip) prunes the node's subtree,
+    /// [`visit_leaf`](Self::visit_leaf) isn't called for the skipped leaves.
+    fn enter_node(&mut self, node: NodeId, n: &Node<U, A>, depth: usize) -> Visit {
+        let _ = (node, n, depth);
+        Visit::Continue
+    }
+
+    /// Called for the entered [`Leaf`](NodeType::Leaf) and [`Bucket`](NodeType::Bucket) nodes
+    /// with the elements they store.
+    ///
+    /// Elements, spanning several leaves, are visited in each of them.
+    /// Only [`Stop`](Visit::Stop) has an effect here.
+    fn visit_leaf(&mut self, node: NodeId, elements: &[ElementId], depth: usize) -> Visit {
+        let _ = (node, elements, depth);
+        Visit::Continue
+    }
+
+    /// Called after the whole subtree of an entered node is walked,
+    /// also for the skipped nodes.
+    fn leave_node(&mut self, node: NodeId, n: &Node<U, A>, depth: usize) {
+        let _ = (node, n, depth);
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Walks the tree depth-first, invoking the `visitor`'s callbacks.
+    ///
+    /// Children are entered in the octant order.
+    /// Uses a heap stack, so it doesn't overflow on the deep trees.
+    ///
+    /// Returns `false` if the walk was [`stopped`](Visit::Stop).
+    pub fn walk(&self, visitor: &mut impl Visitor<U, A>) -> bool {
+        // Second field is the depth, third is set for the nodes to leave.
+        let mut stack = vec![(self.root, 0, false)];
+        while let Some((node, depth, leave)) = stack.pop() {
+            let n = &self.nodes[node];
+            if leave {
+                visitor.leave_node(node, n, depth);
+                continue;
+            }
+
+            match visitor.enter_node(node, n, depth) {
+                Visit::Stop => return false,
+
+                Visit::Skip => visitor.leave_node(node, n, depth),
+
+                Visit::Continue => match n.ntype {
+                    NodeType::Empty => visitor.leave_node(node, n, depth),
+
+                    NodeType::Leaf(_) | NodeType::Bucket => {
+                        if visitor.visit_leaf(node, self.leaf_elements(node), depth) == Visit::Stop
+                        {
+                            return false;
+                        }
+                        visitor.leave_node(node, n, depth);
+                    }
+
+                    NodeType::Branch(branch) => {
+                        stack.push((node, depth, true));
+                        stack.extend(
+                            branch
+                                .children()
+                                .into_iter()
+                                .rev()
+                                .map(|child| (child, depth + 1, false)),
+                        );
+                    }
+                },
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::corners_tree;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+        skip_depth: Option<usize>,
+        stop_at: Option<ElementId>,
+    }
+
+    impl Visitor<u8> for Recorder {
+        fn enter_node(&mut self, node: NodeId, _: &Node<u8>, depth: usize) -> Visit {
+            self.events
+                .push(format!("enter {} {depth}", usize::from(node)));
+            if self.skip_depth == Some(depth) {
+                Visit::Skip
+            } else {
+                Visit::Continue
+            }
+        }
+
+        fn visit_leaf(&mut self, node: NodeId, elements: &[ElementId], _: usize) -> Visit {
+            self.events
+                .push(format!("leaf {} {elements:?}", usize::from(node)));
+            if elements.iter().any(|&e| Some(e) == self.stop_at) {
+                Visit::Stop
+            } else {
+                Visit::Continue
+            }
+        }
+
+        fn leave_node(&mut self, node: NodeId, _: &Node<u8>, depth: usize) {
+            self.events
+                .push(format!("leave {} {depth}", usize::from(node)));
+        }
+    }
+
+    #[test]
+    fn test_order() {
+        let tree = corners_tree();
+        let mut recorder = Recorder::default();
+        assert!(tree.walk(&mut recorder));
+
+        let events = recorder.events;
+        assert_eq!(events.len(), 9 * 2 + 2);
+        assert_eq!(
+            events[..4],
+            [
+                "enter 0 0",
+                "enter 1 1",
+                "leaf 1 [ElementId(0)]",
+                "leave 1 1"
+            ]
+        );
+        assert_eq!(events[4..6], ["enter 2 1", "leave 2 1"]);
+        assert_eq!(
+            events[events.len() - 4..],
+            [
+                "enter 8 1",
+                "leaf 8 [ElementId(1)]",
+                "leave 8 1",
+                "leave 0 0"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skip_stop() {
+        let tree = corners_tree();
+        let mut recorder = Recorder {
+            skip_depth: Some(0),
+            ..Default::default()
+        };
+        assert!(tree.walk(&mut recorder));
+        assert_eq!(recorder.events, ["enter 0 0", "leave 0 0"]);
+
+        let mut recorder = Recorder {
+            stop_at: Some(ElementId(0)),
+            ..Default::default()
+        };
+        assert!(!tree.walk(&mut recorder));
+        assert_eq!(
+            recorder.events,
+            ["enter 0 0", "enter 1 1", "leaf 1 [ElementId(0)]"]
+        );
+    }
+}
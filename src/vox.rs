@@ -0,0 +1,393 @@
+//! [MagicaVoxel](https://ephtracy.github.io/) `.vox` import and export.
+//!
+//! Enabled by the `vox` feature.
+//!
+//! [`read_vox`] loads every model of a file into it's own [`Octree`] of [`VoxCell`]s.
+//! [`write_vox`] stores an [`Octree`] of any [`Position`] elements as a single model.
+//!
+//! ```rust
+//! use oktree::{prelude::*, vox::{read_vox, write_vox, VoxCell}};
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8u16).unwrap());
+//! tree.insert(VoxCell::new(TUVec3u8::new(1, 2, 3), 42)).unwrap();
+//!
+//! let mut bytes = Vec::new();
+//! write_vox(&tree, None, |cell| cell.index, &mut bytes).unwrap();
+//!
+//! let vox = read_vox(bytes.as_slice()).unwrap();
+//! let cell = vox.models[0].get(&TUVec3::new(1, 2, 3)).unwrap();
+//! assert_eq!(cell.index, 42);
+//! ```
+
+use std::io::{Read, Write};
+
+use num::cast;
+
+use crate::{
+    bounding::{Aabb, TUVec3, TUVec3u8, Unsigned},
+    tree::Octree,
+    Position, TreeError,
+};
+
+/// 256 `RGBA` colors. Color index `i` refers to `palette[i - 1]`.
+pub type Palette = [[u8; 4]; 256];
+
+const VERSION: u32 = 150;
+
+/// Voxel of a `.vox` model.
+///
+/// Position is limited by the `256x256x256` model size,
+/// but the tree uses `u16` coordinates to cover it entirely.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoxCell {
+    pub position: TUVec3u8,
+
+    /// Palette color index, `1..=255`.
+    pub index: u8,
+}
+
+impl VoxCell {
+    pub fn new(position: TUVec3u8, index: u8) -> Self {
+        VoxCell { position, index }
+    }
+}
+
+impl Position for VoxCell {
+    type U = u16;
+
+    fn position(&self) -> TUVec3<u16> {
+        let p = self.position.0;
+        TUVec3::new(p.x as u16, p.y as u16, p.z as u16)
+    }
+}
+
+/// Content of a `.vox` file.
+#[derive(Default, Debug, Clone)]
+pub struct Vox {
+    /// Models in the file order.
+    pub models: Vec<Octree<u16, VoxCell>>,
+
+    /// Sizes of the models.
+    pub sizes: Vec<TUVec3<u16>>,
+
+    /// Custom palette, if the file has one.
+    pub palette: Option<Box<Palette>>,
+}
+
+fn malformed(info: impl Into<String>) -> TreeError {
+    TreeError::MalformedVox(info.into())
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], TreeError> {
+    if input.len() < n {
+        return Err(malformed(format!(
+            "Unexpected end of file. Expected {n} bytes, found {}",
+            input.len()
+        )));
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+fn take_u32(input: &mut &[u8]) -> Result<u32, TreeError> {
+    Ok(u32::from_le_bytes(take(input, 4)?.try_into().unwrap()))
+}
+
+struct Chunk<'a> {
+    id: [u8; 4],
+    content: &'a [u8],
+    children: &'a [u8],
+}
+
+/// Splits the next chunk off the `input`.
+fn take_chunk<'a>(input: &mut &'a [u8]) -> Result<Chunk<'a>, TreeError> {
+    let id = take(input, 4)?.try_into().unwrap();
+    let content = take_u32(input)? as usize;
+    let children = take_u32(input)? as usize;
+    Ok(Chunk {
+        id,
+        content: take(input, content)?,
+        children: take(input, children)?,
+    })
+}
+
+/// Reads a MagicaVoxel `.vox` file.
+///
+/// Each model becomes an [`Octree`], large enough to fit the model's size.
+/// Fails with [`TreeError::MalformedVox`] if the file is not valid.
+pub fn read_vox(mut reader: impl Read) -> Result<Vox, TreeError> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|err| TreeError::Io(err.to_string()))?;
+
+    let mut input = bytes.as_slice();
+    if take(&mut input, 4)? != b"VOX " {
+        return Err(malformed("Missing VOX header"));
+    }
+    let _version = take_u32(&mut input)?;
+
+    let Chunk {
+        id, mut children, ..
+    } = take_chunk(&mut input)?;
+    if &id != b"MAIN" {
+        return Err(malformed("Missing MAIN chunk"));
+    }
+
+    let mut vox = Vox::default();
+    let mut size = None;
+    while !children.is_empty() {
+        let Chunk {
+            id, mut content, ..
+        } = take_chunk(&mut children)?;
+        match &id {
+            b"SIZE" => {
+                let x = take_u32(&mut content)?;
+                let y = take_u32(&mut content)?;
+                let z = take_u32(&mut content)?;
+                if [x, y, z].iter().any(|&s| s == 0 || s > 256) {
+                    return Err(malformed(format!("Invalid model size {x}x{y}x{z}")));
+                }
+                size = Some(TUVec3::new(x as u16, y as u16, z as u16));
+            }
+
+            b"XYZI" => {
+                let Some(size) = size.take() else {
+                    return Err(malformed("XYZI chunk without SIZE"));
+                };
+                vox.models.push(read_model(size, content)?);
+                vox.sizes.push(size);
+            }
+
+            b"RGBA" => {
+                let mut palette = Box::new([[0; 4]; 256]);
+                for color in palette.iter_mut() {
+                    color.copy_from_slice(take(&mut content, 4)?);
+                }
+                vox.palette = Some(palette);
+            }
+
+            // PACK, scene graph, materials e.t.c.
+            _ => (),
+        }
+    }
+
+    Ok(vox)
+}
+
+fn read_model(size: TUVec3<u16>, mut content: &[u8]) -> Result<Octree<u16, VoxCell>, TreeError> {
+    let count = take_u32(&mut content)? as usize;
+    if count > content.len() / 4 {
+        return Err(malformed(format!(
+            "XYZI chunk declares {count} voxels, but holds only {} bytes",
+            content.len()
+        )));
+    }
+
+    let side = size.x.max(size.y).max(size.z).next_power_of_two().max(4);
+    let aabb = Aabb::new(TUVec3::splat(side / 2), side / 2)?;
+    let mut tree = Octree::from_aabb_with_capacity(aabb, count);
+
+    for _ in 0..count {
+        let [x, y, z, index] = take(&mut content, 4)?.try_into().unwrap();
+        let cell = VoxCell::new(TUVec3u8::new(x, y, z), index);
+        if !cell.position().lt(&size).all() {
+            return Err(malformed(format!(
+                "Voxel {} is outside of the model size {size}",
+                cell.position.0
+            )));
+        }
+
+        tree.insert(cell)
+            .map_err(|err| malformed(err.to_string()))?;
+    }
+
+    Ok(tree)
+}
+
+/// Writes an [`Octree`] as a single model MagicaVoxel `.vox` file.
+///
+/// `color` returns the palette index of the element.
+/// Fails with [`TreeError::OutOfTreeBounds`] if an element doesn't fit
+/// into the `256x256x256` model.
+pub fn write_vox<U, T>(
+    tree: &Octree<U, T>,
+    palette: Option<&Palette>,
+    color: impl Fn(&T) -> u8,
+    mut writer: impl Write,
+) -> Result<(), TreeError>
+where
+    U: Unsigned,
+    T: Position<U = U>,
+{
+    let mut size = [1u32; 3];
+    let mut voxels = Vec::with_capacity(tree.len() * 4 + 4);
+    voxels.extend_from_slice(&(tree.len() as u32).to_le_bytes());
+    for element in tree.iter() {
+        let p = element.position();
+        let xyz = [p.x, p.y, p.z].map(|c| cast::<U, u8>(c));
+        let [Some(x), Some(y), Some(z)] = xyz else {
+            return Err(TreeError::OutOfTreeBounds(format!(
+                "{p} doesn't fit into the 256x256x256 vox model"
+            )));
+        };
+
+        for (s, c) in size.iter_mut().zip([x, y, z]) {
+            *s = (*s).max(c as u32 + 1);
+        }
+        voxels.extend_from_slice(&[x, y, z, color(element)]);
+    }
+
+    let mut chunks = Vec::with_capacity(voxels.len() + 1100);
+    write_chunk(&mut chunks, b"SIZE", &size.map(u32::to_le_bytes).concat());
+    write_chunk(&mut chunks, b"XYZI", &voxels);
+    if let Some(palette) = palette {
+        write_chunk(&mut chunks, b"RGBA", palette.as_flattened());
+    }
+
+    let mut bytes = Vec::with_capacity(chunks.len() + 20);
+    bytes.extend_from_slice(b"VOX ");
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(b"MAIN");
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&chunks);
+
+    writer
+        .write_all(&bytes)
+        .and_then(|_| writer.flush())
+        .map_err(|err| TreeError::Io(err.to_string()))
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(content.len() as u32).to_le_bytes());
+    out.extend_from_slice(&0u32.to_le_bytes());
+    out.extend_from_slice(content);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::TUVec3u16;
+
+    fn cells() -> Vec<VoxCell> {
+        (0..20)
+            .map(|i| VoxCell::new(TUVec3u8::new(i, 19 - i, i / 2), i + 1))
+            .collect()
+    }
+
+    fn vox_file(models: &[(&[u32; 3], &[[u8; 4]])]) -> Vec<u8> {
+        let mut chunks = Vec::new();
+        write_chunk(&mut chunks, b"PACK", &(models.len() as u32).to_le_bytes());
+        for (size, voxels) in models {
+            write_chunk(&mut chunks, b"SIZE", &size.map(u32::to_le_bytes).concat());
+            let mut content = (voxels.len() as u32).to_le_bytes().to_vec();
+            content.extend_from_slice(voxels.as_flattened());
+            write_chunk(&mut chunks, b"XYZI", &content);
+        }
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&chunks);
+        bytes
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(16), 16));
+        for cell in cells() {
+            tree.insert(cell).unwrap();
+        }
+
+        let mut palette = [[0; 4]; 256];
+        palette[41] = [255, 0, 0, 255];
+
+        let mut bytes = Vec::new();
+        write_vox(&tree, Some(&palette), |cell| cell.index, &mut bytes).unwrap();
+
+        let vox = read_vox(bytes.as_slice()).unwrap();
+        assert_eq!(vox.models.len(), 1);
+        assert_eq!(vox.sizes, vec![TUVec3::new(20, 20, 10)]);
+        assert_eq!(vox.palette.unwrap()[41], [255, 0, 0, 255]);
+
+        let model = &vox.models[0];
+        assert_eq!(model.len(), 20);
+        assert_eq!(model.aabb, Some(Aabb::new_unchecked(TUVec3::splat(16), 16)));
+        for cell in cells() {
+            assert_eq!(model.get(&cell.position()), Some(&cell));
+        }
+    }
+
+    #[test]
+    fn test_positions() {
+        let mut tree = Octree::from_aabb(Aabb::new_unchecked(TUVec3::splat(512), 512));
+        tree.insert(TUVec3u16::new(255, 0, 3)).unwrap();
+
+        let mut bytes = Vec::new();
+        write_vox(&tree, None, |_| 1, &mut bytes).unwrap();
+        let vox = read_vox(bytes.as_slice()).unwrap();
+        assert_eq!(vox.sizes, vec![TUVec3::new(256, 1, 4)]);
+        assert_eq!(vox.models[0].len(), 1);
+        assert!(vox.palette.is_none());
+
+        tree.insert(TUVec3u16::new(256, 0, 3)).unwrap();
+        assert!(matches!(
+            write_vox(&tree, None, |_| 1, &mut bytes),
+            Err(TreeError::OutOfTreeBounds(_))
+        ));
+    }
+
+    #[test]
+    fn test_models() {
+        let bytes = vox_file(&[
+            (&[2, 2, 2], &[[0, 0, 0, 1], [1, 1, 1, 2]]),
+            (&[3, 1, 1], &[[2, 0, 0, 3]]),
+        ]);
+
+        let vox = read_vox(bytes.as_slice()).unwrap();
+        assert_eq!(vox.models.len(), 2);
+        assert_eq!(vox.models[0].len(), 2);
+        assert_eq!(vox.models[1].len(), 1);
+        assert_eq!(vox.models[1].get(&TUVec3::new(2, 0, 0)).unwrap().index, 3);
+        assert_eq!(
+            vox.models[1].aabb,
+            Some(Aabb::new_unchecked(TUVec3::splat(2), 2))
+        );
+    }
+
+    #[test]
+    fn test_malformed() {
+        let is_malformed =
+            |bytes: &[u8]| matches!(read_vox(bytes), Err(TreeError::MalformedVox(_)));
+
+        let bytes = vox_file(&[(&[2, 2, 2], &[[0, 0, 0, 1]])]);
+        assert!(read_vox(bytes.as_slice()).is_ok());
+        assert!(is_malformed(&bytes[..bytes.len() - 1]));
+        assert!(is_malformed(b"VOX"));
+        assert!(is_malformed(b"XOV \x96\0\0\0"));
+
+        // Voxel outside of the model
+        assert!(is_malformed(&vox_file(&[(&[2, 2, 2], &[[2, 0, 0, 1]])])));
+
+        // Duplicated voxel
+        assert!(is_malformed(&vox_file(&[(
+            &[2, 2, 2],
+            &[[1, 0, 0, 1], [1, 0, 0, 2]]
+        )])));
+
+        // Invalid size
+        assert!(is_malformed(&vox_file(&[(&[0, 2, 2], &[])])));
+        assert!(is_malformed(&vox_file(&[(&[257, 2, 2], &[])])));
+
+        // Voxel count exceeding the chunk
+        let mut bytes = vox_file(&[(&[2, 2, 2], &[[0, 0, 0, 1]])]);
+        let xyzi = bytes.windows(4).position(|id| id == b"XYZI").unwrap();
+        bytes[xyzi + 12..xyzi + 16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(is_malformed(&bytes));
+    }
+}
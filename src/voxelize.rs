@@ -0,0 +1,529 @@
+//! Triangle mesh voxelization.
+//!
+//! [`voxelize`] rasterizes the mesh surface into the tree cells,
+//! using the exact [`triangle / box`](triangle_overlaps) overlap test,
+//! and optionally [`fills`](Fill) the interior.
+//!
+//! Enable `obj` feature to load the meshes with `read_obj`.
+//!
+//! ```rust
+//! use oktree::{point_cloud::Grid, prelude::*, voxelize::{voxelize, Fill}};
+//!
+//! // Tetrahedron
+//! let [a, b, c, d] = [[1.0, 1.0, 1.0], [13.0, 1.0, 1.0], [1.0, 13.0, 1.0], [1.0, 1.0, 13.0]];
+//! let triangles = [[a, c, b], [a, b, d], [a, d, c], [b, c, d]];
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8u8).unwrap());
+//! let stats = voxelize(
+//!     &mut tree,
+//!     &Grid::new([0.0; 3], 1.0),
+//!     &triangles,
+//!     Fill::FloodFill,
+//!     TUVec3u8,
+//! )
+//! .unwrap();
+//!
+//! assert!(stats.interior > 0);
+//! assert!(tree.find(&TUVec3::new(2, 2, 2)).is_some());
+//! assert!(tree.find(&TUVec3::new(10, 10, 10)).is_none());
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use num::cast;
+
+use crate::{
+    bounding::{Aabb, TUVec3, Unsigned},
+    entry::Entry,
+    point_cloud::Grid,
+    tree::Octree,
+    Position, TreeError,
+};
+
+/// Triangle vertices.
+pub type Triangle = [[f64; 3]; 3];
+
+/// Interior filling strategy.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fill {
+    /// Surface cells only.
+    #[default]
+    Surface,
+
+    /// Cells, whose centers are inside of the mesh by the ray parity along the `z` axis.
+    /// Requires a closed mesh, but tolerates self intersections.
+    Parity,
+
+    /// Cells, unreachable from the outside without crossing the surface cells.
+    /// Tolerates small holes, but not the ones larger than a cell.
+    FloodFill,
+}
+
+/// Result of the [`voxelize`].
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Voxelized {
+    /// Number of the surface cells.
+    pub surface: usize,
+
+    /// Number of the interior cells.
+    pub interior: usize,
+
+    /// Number of the cells, skipped as already occupied in the tree.
+    pub occupied: usize,
+}
+
+/// Voxelizes the `triangles` into the `tree`.
+///
+/// Triangles are mapped to the tree cells by the `grid`.
+/// Cells are inserted in sorted order, each one constructed by `new`.
+/// Cells, already occupied in the tree, are skipped.
+/// Parts of the mesh outside of the tree are clipped.
+pub fn voxelize<U, T>(
+    tree: &mut Octree<U, T>,
+    grid: &Grid,
+    triangles: &[Triangle],
+    fill: Fill,
+    mut new: impl FnMut(TUVec3<U>) -> T,
+) -> Result<Voxelized, TreeError>
+where
+    U: Unsigned,
+    T: Position<U = U>,
+{
+    let aabb = tree.nodes[tree.root].aabb;
+    let bounds = [aabb.min, aabb.max]
+        .map(|v| [v.x, v.y, v.z].map(|c| cast::<U, i64>(c).unwrap_or(i64::MAX)));
+
+    // Tree space
+    let triangles: Vec<Triangle> = triangles
+        .iter()
+        .map(|t| t.map(|p| [0, 1, 2].map(|i| (p[i] - grid.origin[i]) / grid.voxel_size)))
+        .collect();
+
+    let surface = rasterize(&triangles, bounds);
+    let interior = match fill {
+        Fill::Surface => Vec::new(),
+        Fill::Parity => parity(&triangles, &surface, bounds),
+        Fill::FloodFill => flood_fill(&surface),
+    };
+
+    let mut stats = Voxelized {
+        surface: surface.len(),
+        interior: interior.len(),
+        occupied: 0,
+    };
+
+    let mut cells: Vec<[i64; 3]> = surface.into_iter().chain(interior).collect();
+    cells.sort_unstable();
+    for cell in cells {
+        let [x, y, z] = cell.map(|c| cast::<i64, U>(c).unwrap());
+        let cell = TUVec3::new(x, y, z);
+        match tree.entry(cell) {
+            Entry::Occupied(_) => stats.occupied += 1,
+            Entry::Vacant(entry) => {
+                entry.try_insert(new(cell))?;
+            }
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Exact triangle / box overlap test.
+///
+/// Separating axis test by Tomas Akenine-Möller.
+/// Triangles, touching the box boundary, overlap it.
+pub fn triangle_overlaps<U: Unsigned>(triangle: &Triangle, aabb: &Aabb<U>) -> bool {
+    let [min, max] =
+        [aabb.min, aabb.max].map(|v| [v.x, v.y, v.z].map(|c| cast::<U, f64>(c).unwrap()));
+    let center = [0, 1, 2].map(|i| (min[i] + max[i]) * 0.5);
+    let half = [0, 1, 2].map(|i| (max[i] - min[i]) * 0.5);
+    box_overlaps(triangle, center, half)
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn box_overlaps(triangle: &Triangle, center: [f64; 3], half: [f64; 3]) -> bool {
+    let v = triangle.map(|p| sub(p, center));
+
+    let separated = |axis: [f64; 3]| {
+        let p = v.map(|v| dot(v, axis));
+        let r = half[0] * axis[0].abs() + half[1] * axis[1].abs() + half[2] * axis[2].abs();
+        p[0].min(p[1]).min(p[2]) > r || p[0].max(p[1]).max(p[2]) < -r
+    };
+
+    // Box face normals
+    let units = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    if units.iter().any(|&axis| separated(axis)) {
+        return false;
+    }
+
+    // Triangle normal
+    let edges = [sub(v[1], v[0]), sub(v[2], v[1]), sub(v[0], v[2])];
+    if separated(cross(edges[0], edges[1])) {
+        return false;
+    }
+
+    // Edge cross products
+    !edges
+        .iter()
+        .any(|&edge| units.iter().any(|&axis| separated(cross(edge, axis))))
+}
+
+/// Surface cells of the `triangles` inside of the tree `bounds`.
+fn rasterize(triangles: &[Triangle], bounds: [[i64; 3]; 2]) -> HashSet<[i64; 3]> {
+    let mut surface = HashSet::new();
+    for triangle in triangles {
+        let lo = [0, 1, 2].map(|i| {
+            let c = triangle[0][i].min(triangle[1][i]).min(triangle[2][i]);
+            (c.floor() as i64).max(bounds[0][i])
+        });
+        let hi = [0, 1, 2].map(|i| {
+            let c = triangle[0][i].max(triangle[1][i]).max(triangle[2][i]);
+            (c.floor() as i64).min(bounds[1][i] - 1)
+        });
+
+        for x in lo[0]..=hi[0] {
+            for y in lo[1]..=hi[1] {
+                for z in lo[2]..=hi[2] {
+                    let center = [x, y, z].map(|c| c as f64 + 0.5);
+                    if box_overlaps(triangle, center, [0.5; 3]) {
+                        surface.insert([x, y, z]);
+                    }
+                }
+            }
+        }
+    }
+    surface
+}
+
+/// Non surface cells, whose centers are crossed by an odd number of triangles,
+/// casting a ray along the `z` axis.
+fn parity(
+    triangles: &[Triangle],
+    surface: &HashSet<[i64; 3]>,
+    bounds: [[i64; 3]; 2],
+) -> Vec<[i64; 3]> {
+    // Ray hits by column
+    let mut hits: HashMap<[i64; 2], Vec<f64>> = HashMap::new();
+    for triangle in triangles {
+        let [a, mut b, mut c] = *triangle;
+        let cross2 = |o: [f64; 3], p: [f64; 3], q: [f64; 3]| {
+            (p[0] - o[0]) * (q[1] - o[1]) - (p[1] - o[1]) * (q[0] - o[0])
+        };
+
+        // Vertical triangles are never crossed
+        let mut area = cross2(a, b, c);
+        if area == 0.0 {
+            continue;
+        }
+        if area < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+            area = -area;
+        }
+
+        // Points on an edge belong to exactly one of the triangles, sharing it
+        let owns = |from: [f64; 3], to: [f64; 3], w: f64| {
+            let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+            w > 0.0 || (w == 0.0 && (dy < 0.0 || (dy == 0.0 && dx < 0.0)))
+        };
+
+        let lo = [0, 1].map(|i| {
+            let c = a[i].min(b[i]).min(c[i]);
+            ((c - 0.5).ceil() as i64).max(bounds[0][i])
+        });
+        let hi = [0, 1].map(|i| {
+            let c = a[i].max(b[i]).max(c[i]);
+            ((c - 0.5).floor() as i64).min(bounds[1][i] - 1)
+        });
+
+        for x in lo[0]..=hi[0] {
+            for y in lo[1]..=hi[1] {
+                let p = [x as f64 + 0.5, y as f64 + 0.5, 0.0];
+                let wa = cross2(b, c, p);
+                let wb = cross2(c, a, p);
+                let wc = cross2(a, b, p);
+                if owns(b, c, wa) && owns(c, a, wb) && owns(a, b, wc) {
+                    let z = (wa * a[2] + wb * b[2] + wc * c[2]) / area;
+                    hits.entry([x, y]).or_default().push(z);
+                }
+            }
+        }
+    }
+
+    let mut interior = Vec::new();
+    for ([x, y], mut column) in hits {
+        column.sort_unstable_by(f64::total_cmp);
+        let lo = ((column[0] - 0.5).ceil() as i64).max(bounds[0][2]);
+        let hi = ((column[column.len() - 1] - 0.5).floor() as i64).min(bounds[1][2] - 1);
+        for z in lo..=hi {
+            let center = z as f64 + 0.5;
+            let crossed = column.partition_point(|&hit| hit < center);
+            if crossed % 2 == 1 && !surface.contains(&[x, y, z]) {
+                interior.push([x, y, z]);
+            }
+        }
+    }
+    interior
+}
+
+/// Non surface cells, unreachable from the outside of the surface bounding box
+/// through the face adjacent non surface cells.
+fn flood_fill(surface: &HashSet<[i64; 3]>) -> Vec<[i64; 3]> {
+    let Some(&first) = surface.iter().next() else {
+        return Vec::new();
+    };
+
+    // Surface bounding box, expanded by a cell of outside space
+    let mut lo = first;
+    let mut hi = first;
+    for cell in surface {
+        for i in 0..3 {
+            lo[i] = lo[i].min(cell[i] - 1);
+            hi[i] = hi[i].max(cell[i] + 1);
+        }
+    }
+    let size = [0, 1, 2].map(|i| (hi[i] - lo[i] + 1) as usize);
+    let index = |c: [i64; 3]| {
+        let [x, y, z] = [0, 1, 2].map(|i| (c[i] - lo[i]) as usize);
+        x + size[0] * (y + size[1] * z)
+    };
+
+    let mut outside = vec![false; size[0] * size[1] * size[2]];
+    let mut queue = VecDeque::from([lo]);
+    outside[index(lo)] = true;
+    while let Some(cell) = queue.pop_front() {
+        for i in 0..3 {
+            for d in [-1, 1] {
+                let mut next = cell;
+                next[i] += d;
+                if next[i] < lo[i] || next[i] > hi[i] {
+                    continue;
+                }
+                let n = index(next);
+                if !outside[n] && !surface.contains(&next) {
+                    outside[n] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    let mut interior = Vec::new();
+    for z in lo[2]..=hi[2] {
+        for y in lo[1]..=hi[1] {
+            for x in lo[0]..=hi[0] {
+                let cell = [x, y, z];
+                if !outside[index(cell)] && !surface.contains(&cell) {
+                    interior.push(cell);
+                }
+            }
+        }
+    }
+    interior
+}
+
+/// Reads the triangles of a Wavefront `.obj` mesh.
+///
+/// Only vertices and faces are used, polygons are triangulated as fans.
+/// Enabled by the `obj` feature.
+#[cfg(feature = "obj")]
+pub fn read_obj(mut reader: impl std::io::Read) -> Result<Vec<Triangle>, TreeError> {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .map_err(|err| TreeError::Io(err.to_string()))?;
+
+    let malformed = |info: String| TreeError::MalformedObj(info);
+
+    let mut vertices: Vec<[f64; 3]> = Vec::new();
+    let mut triangles = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line_number = i + 1;
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut coordinate = || {
+                    let token = tokens.next().unwrap_or_default();
+                    token.parse::<f64>().map_err(|_| {
+                        malformed(format!("Invalid vertex {token:?} at line {line_number}"))
+                    })
+                };
+                vertices.push([coordinate()?, coordinate()?, coordinate()?]);
+            }
+
+            Some("f") => {
+                let face = tokens
+                    .map(|token| {
+                        // `v`, `v/vt`, `v//vn` or `v/vt/vn`
+                        let index = token.split('/').next().unwrap_or_default();
+                        let resolved = match index.parse::<i64>() {
+                            Ok(index) if index > 0 => Some(index as usize - 1),
+                            Ok(index) if index < 0 => vertices.len().checked_sub((-index) as usize),
+                            _ => None,
+                        };
+                        resolved
+                            .filter(|&index| index < vertices.len())
+                            .map(|index| vertices[index])
+                            .ok_or_else(|| {
+                                malformed(format!(
+                                    "Invalid face index {token:?} at line {line_number}"
+                                ))
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if face.len() < 3 {
+                    return Err(malformed(format!(
+                        "Face with less than 3 vertices at line {line_number}"
+                    )));
+                }
+
+                for j in 1..face.len() - 1 {
+                    triangles.push([face[0], face[j], face[j + 1]]);
+                }
+            }
+
+            _ => (), // Comments, normals, texture coordinates, groups, materials
+        }
+    }
+
+    Ok(triangles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bounding::TUVec3u8;
+    use crate::tests::empty_tree;
+
+    fn cube(min: f64, max: f64) -> Vec<Triangle> {
+        let v = |x: usize, y: usize, z: usize| [x, y, z].map(|c| if c == 0 { min } else { max });
+        let mut triangles = Vec::new();
+        for axis in 0..3 {
+            for side in 0..2 {
+                let corner = |u: usize, w: usize| {
+                    let mut c = [0; 3];
+                    c[axis] = side;
+                    c[(axis + 1) % 3] = u;
+                    c[(axis + 2) % 3] = w;
+                    v(c[0], c[1], c[2])
+                };
+                triangles.push([corner(0, 0), corner(1, 0), corner(1, 1)]);
+                triangles.push([corner(0, 0), corner(1, 1), corner(0, 1)]);
+            }
+        }
+        triangles
+    }
+
+    #[test]
+    fn test_triangle_overlaps() {
+        let aabb = Aabb::from_min_max(TUVec3::new(2u8, 2, 2), TUVec3::new(4, 4, 4));
+
+        // Crossing the box
+        let t = [[0.0, 0.0, 3.0], [8.0, 0.0, 3.0], [0.0, 8.0, 3.0]];
+        assert!(triangle_overlaps(&t, &aabb));
+
+        // Touching the face
+        let t = [[4.0, 0.0, 0.0], [4.0, 8.0, 0.0], [4.0, 0.0, 8.0]];
+        assert!(triangle_overlaps(&t, &aabb));
+
+        // Bounding boxes overlap, but the triangle passes the corner
+        let t = [[0.0, 0.0, 12.5], [12.5, 0.0, 0.0], [0.0, 12.5, 0.0]];
+        assert!(!triangle_overlaps(&t, &aabb));
+        let t = [[0.0, 0.0, 11.5], [11.5, 0.0, 0.0], [0.0, 11.5, 0.0]];
+        assert!(triangle_overlaps(&t, &aabb));
+
+        // Edge passes by
+        let t = [[0.0, 3.5, 3.0], [3.5, 0.0, 3.0], [0.0, 0.0, -5.0]];
+        assert!(!triangle_overlaps(&t, &aabb));
+    }
+
+    #[test]
+    fn test_voxelize() {
+        let triangles = cube(1.25, 5.25);
+        let grid = Grid::new([0.0; 3], 1.0);
+
+        let mut surface = empty_tree();
+        let stats = voxelize(&mut surface, &grid, &triangles, Fill::Surface, TUVec3u8).unwrap();
+        assert_eq!(stats.surface, 125 - 27);
+        assert_eq!(stats.interior, 0);
+        assert_eq!(surface.len(), 98);
+        assert!(surface.find(&TUVec3::new(3, 3, 3)).is_none());
+
+        for fill in [Fill::Parity, Fill::FloodFill] {
+            let mut tree = empty_tree();
+            let stats = voxelize(&mut tree, &grid, &triangles, fill, TUVec3u8).unwrap();
+            assert_eq!(stats.interior, 27, "{fill:?}");
+            assert_eq!(tree.len(), 125, "{fill:?}");
+            assert!(tree.find(&TUVec3::new(3, 3, 3)).is_some());
+            assert!(tree.find(&TUVec3::new(0, 3, 3)).is_none());
+            assert!(tree.find(&TUVec3::new(6, 3, 3)).is_none());
+        }
+
+        // Grid mapping, clipping and occupied cells
+        let mut tree = empty_tree();
+        tree.insert(TUVec3u8::new(0, 0, 0)).unwrap();
+        let grid = Grid::new([-2.0; 3], 0.5);
+        let stats = voxelize(&mut tree, &grid, &cube(-2.9, 0.1), Fill::Parity, TUVec3u8).unwrap();
+        assert_eq!(stats.surface, 125 - 64);
+        assert_eq!(stats.interior, 64);
+        assert_eq!(stats.occupied, 1);
+        assert_eq!(tree.len(), 125);
+        assert!(tree.find(&TUVec3::new(4, 4, 4)).is_some());
+        assert!(tree.find(&TUVec3::new(5, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_flood_fill_leak() {
+        // Open box leaks the flood fill, but not the parity
+        let mut triangles = cube(1.25, 5.25);
+        triangles.truncate(10);
+
+        let grid = Grid::new([0.0; 3], 1.0);
+        let mut tree = empty_tree();
+        let stats = voxelize(&mut tree, &grid, &triangles, Fill::FloodFill, TUVec3u8).unwrap();
+        assert_eq!(stats.interior, 0);
+    }
+
+    #[cfg(feature = "obj")]
+    #[test]
+    fn test_read_obj() {
+        let obj = "# cube\n\
+            v 1.25 1.25 1.25\nv 5.25 1.25 1.25\nv 5.25 5.25 1.25\nv 1.25 5.25 1.25\n\
+            v 1.25 1.25 5.25\nv 5.25 1.25 5.25\nv 5.25 5.25 5.25\nv 1.25 5.25 5.25\n\
+            vn 0 0 1\n\
+            f 1 4 3 2\nf 5/1 6/1 7/1 8/1\nf 1//1 2//1 6//1 5//1\n\
+            f -5 -6 -2 -1\nf 1/1/1 5/1/1 8/1/1 4/1/1\nf 2 3 7 6\n";
+
+        let triangles = read_obj(obj.as_bytes()).unwrap();
+        assert_eq!(triangles.len(), 12);
+
+        let mut tree = empty_tree();
+        let grid = Grid::new([0.0; 3], 1.0);
+        voxelize(&mut tree, &grid, &triangles, Fill::Parity, TUVec3u8).unwrap();
+        assert_eq!(tree.len(), 125);
+
+        assert!(matches!(
+            read_obj("v 1 2\n".as_bytes()),
+            Err(TreeError::MalformedObj(_))
+        ));
+        assert!(matches!(
+            read_obj("v 1 2 3\nf 1 2 3\n".as_bytes()),
+            Err(TreeError::MalformedObj(_))
+        ));
+    }
+}
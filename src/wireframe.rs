@@ -0,0 +1,302 @@
+//! Wireframe geometry export of the node bounds.
+//!
+//! [`Octree::export_obj`] and [`Octree::export_ply`] write the edges
+//! of every node's [`Aabb`] as line segments and the element volumes as closed boxes,
+//! so the partitioning could be inspected in any mesh viewer, without a game engine.
+//! Coordinates are in the tree units.
+//!
+//! ```rust
+//! use oktree::{prelude::*, wireframe::Coloring};
+//!
+//! let mut tree = Octree::from_aabb(Aabb::new(TUVec3::splat(8), 8).unwrap());
+//! tree.insert(TUVec3u8::new(1, 1, 1)).unwrap();
+//! tree.insert(TUVec3u8::new(14, 14, 14)).unwrap();
+//!
+//! let mut obj = Vec::new();
+//! tree.export_obj(&mut obj, Coloring::Depth).unwrap();
+//!
+//! let obj = String::from_utf8(obj).unwrap();
+//! // 9 nodes and 2 elements, 8 corners each
+//! assert_eq!(obj.lines().filter(|line| line.starts_with("v ")).count(), 11 * 8);
+//! ```
+
+use std::io::Write;
+
+use crate::{
+    aggregate::Aggregate,
+    bounding::{Aabb, Unsigned},
+    node::NodeType,
+    tree::Octree,
+    TreeError, Volume,
+};
+
+/// Colors of the exported boxes.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Coloring {
+    /// No vertex colors are written.
+    #[default]
+    None,
+
+    /// Nodes are colored by their depth, cycling through 8 colors.
+    Depth,
+
+    /// Nodes are colored by their [`NodeType`].
+    NodeType,
+}
+
+/// Colors of the depths, repeated for the deeper nodes.
+const DEPTH_PALETTE: [[u8; 3]; 8] = [
+    [230, 25, 75],
+    [245, 130, 48],
+    [255, 225, 25],
+    [60, 180, 75],
+    [70, 240, 240],
+    [0, 130, 200],
+    [145, 30, 180],
+    [240, 50, 230],
+];
+
+const EMPTY_COLOR: [u8; 3] = [128, 128, 128];
+const LEAF_COLOR: [u8; 3] = [60, 180, 75];
+const BUCKET_COLOR: [u8; 3] = [245, 130, 48];
+const BRANCH_COLOR: [u8; 3] = [0, 130, 200];
+const ELEMENT_COLOR: [u8; 3] = [255, 255, 255];
+
+/// Corners of a box differing in one coordinate.
+const EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+/// Faces of a box, counter-clockwise when looking from outside.
+const FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+/// Box to export.
+struct Cuboid<U: Unsigned> {
+    aabb: Aabb<U>,
+    color: Option<[u8; 3]>,
+}
+
+impl<U: Unsigned> Cuboid<U> {
+    /// Corner `i` has the max `x` if bit `0` is set, max `y` for bit `1` and max `z` for bit `2`.
+    fn corners(&self) -> [[U; 3]; 8] {
+        let Aabb { min, max } = self.aabb;
+        std::array::from_fn(|i| {
+            [
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            ]
+        })
+    }
+}
+
+impl<U, T, A> Octree<U, T, A>
+where
+    U: Unsigned,
+    T: Volume<U = U>,
+    A: Aggregate<T>,
+{
+    /// Writes the node edges as lines (`l`) and the element volumes as boxes (`f`)
+    /// into a Wavefront `OBJ` file.
+    ///
+    /// Nodes and elements are written as the `nodes` and `elements` objects.
+    /// Colors are written as the widely supported `v x y z r g b` vertex extension.
+    pub fn export_obj(&self, mut writer: impl Write, coloring: Coloring) -> Result<(), TreeError> {
+        let (nodes, elements) = self.cuboids(coloring);
+
+        let mut out = String::with_capacity((nodes.len() + elements.len()) * 400 + 100);
+        out.push_str("# oktree\n");
+
+        let mut offset = 1;
+        for (name, cuboids, is_element) in [("nodes", &nodes, false), ("elements", &elements, true)]
+        {
+            out.push_str(&format!("o {name}\n"));
+            for cuboid in cuboids {
+                for [x, y, z] in cuboid.corners() {
+                    out.push_str(&format!("v {x} {y} {z}"));
+                    if let Some([r, g, b]) = cuboid.color {
+                        let [r, g, b] = [r, g, b].map(|c| c as f32 / 255.0);
+                        out.push_str(&format!(" {r} {g} {b}"));
+                    }
+                    out.push('\n');
+                }
+
+                if is_element {
+                    for [a, b, c, d] in FACES.map(|face| face.map(|i| offset + i)) {
+                        out.push_str(&format!("f {a} {b} {c} {d}\n"));
+                    }
+                } else {
+                    for [a, b] in EDGES.map(|edge| edge.map(|i| offset + i)) {
+                        out.push_str(&format!("l {a} {b}\n"));
+                    }
+                }
+                offset += 8;
+            }
+        }
+
+        writer
+            .write_all(out.as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(|err| TreeError::Io(err.to_string()))
+    }
+
+    /// Writes the node edges as `edge` elements and the element volumes as `face` elements
+    /// into an `ascii` `PLY` file.
+    ///
+    /// Colors are written as the `red`, `green` and `blue` vertex properties.
+    pub fn export_ply(&self, mut writer: impl Write, coloring: Coloring) -> Result<(), TreeError> {
+        let (nodes, elements) = self.cuboids(coloring);
+        let colors = coloring != Coloring::None;
+
+        let mut out = String::with_capacity((nodes.len() + elements.len()) * 300 + 300);
+        out.push_str("ply\nformat ascii 1.0\ncomment oktree\n");
+        out.push_str(&format!(
+            "element vertex {}\n",
+            (nodes.len() + elements.len()) * 8
+        ));
+        out.push_str("property float x\nproperty float y\nproperty float z\n");
+        if colors {
+            out.push_str("property uchar red\nproperty uchar green\nproperty uchar blue\n");
+        }
+        out.push_str(&format!("element face {}\n", elements.len() * FACES.len()));
+        out.push_str("property list uchar int vertex_indices\n");
+        out.push_str(&format!("element edge {}\n", nodes.len() * EDGES.len()));
+        out.push_str("property int vertex1\nproperty int vertex2\n");
+        out.push_str("end_header\n");
+
+        for cuboid in nodes.iter().chain(elements.iter()) {
+            for [x, y, z] in cuboid.corners() {
+                out.push_str(&format!("{x} {y} {z}"));
+                if let Some([r, g, b]) = cuboid.color {
+                    out.push_str(&format!(" {r} {g} {b}"));
+                }
+                out.push('\n');
+            }
+        }
+
+        let elements_offset = nodes.len() * 8;
+        for i in 0..elements.len() {
+            let offset = elements_offset + i * 8;
+            for [a, b, c, d] in FACES.map(|face| face.map(|i| offset + i)) {
+                out.push_str(&format!("4 {a} {b} {c} {d}\n"));
+            }
+        }
+
+        for i in 0..nodes.len() {
+            for [a, b] in EDGES.map(|edge| edge.map(|e| i * 8 + e)) {
+                out.push_str(&format!("{a} {b}\n"));
+            }
+        }
+
+        writer
+            .write_all(out.as_bytes())
+            .and_then(|_| writer.flush())
+            .map_err(|err| TreeError::Io(err.to_string()))
+    }
+
+    /// Returns the node and the element boxes.
+    fn cuboids(&self, coloring: Coloring) -> (Vec<Cuboid<U>>, Vec<Cuboid<U>>) {
+        let mut nodes = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![(self.root, 0)];
+        while let Some((node, depth)) = stack.pop() {
+            let n = &self.nodes[node];
+            let color = match coloring {
+                Coloring::None => None,
+                Coloring::Depth => Some(DEPTH_PALETTE[depth % DEPTH_PALETTE.len()]),
+                Coloring::NodeType => Some(match n.ntype {
+                    NodeType::Empty => EMPTY_COLOR,
+                    NodeType::Leaf(_) => LEAF_COLOR,
+                    NodeType::Bucket => BUCKET_COLOR,
+                    NodeType::Branch(_) => BRANCH_COLOR,
+                }),
+            };
+            nodes.push(Cuboid {
+                aabb: n.aabb,
+                color,
+            });
+
+            if let NodeType::Branch(branch) = n.ntype {
+                stack.extend(branch.children().into_iter().rev().map(|c| (c, depth + 1)));
+            }
+        }
+
+        let color = (coloring != Coloring::None).then_some(ELEMENT_COLOR);
+        let elements = self
+            .iter()
+            .map(|element| Cuboid {
+                aabb: element.volume(),
+                color,
+            })
+            .collect();
+
+        (nodes, elements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::corners_tree;
+
+    #[test]
+    fn test_obj() {
+        let mut obj = Vec::new();
+        corners_tree().export_obj(&mut obj, Coloring::None).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!(count("v "), 11 * 8);
+        assert_eq!(count("l "), 9 * 12);
+        assert_eq!(count("f "), 2 * 6);
+        assert!(obj.contains("v 16 16 16\n"));
+        // First element box follows the 9 node boxes
+        assert!(obj.contains("f 73 77 79 75\n"));
+
+        let mut obj = Vec::new();
+        corners_tree()
+            .export_obj(&mut obj, Coloring::NodeType)
+            .unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert!(obj.starts_with("# oktree\no nodes\nv 0 0 0 0 0.50980395 0.78431374\n"));
+    }
+
+    #[test]
+    fn test_ply() {
+        let mut ply = Vec::new();
+        corners_tree()
+            .export_ply(&mut ply, Coloring::Depth)
+            .unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+
+        let (header, body) = ply.split_once("end_header\n").unwrap();
+        assert!(header.contains("element vertex 88\n"));
+        assert!(header.contains("element face 12\n"));
+        assert!(header.contains("element edge 108\n"));
+        assert!(header.contains("property uchar red\n"));
+
+        let lines: Vec<_> = body.lines().collect();
+        assert_eq!(lines.len(), 88 + 12 + 108);
+        assert_eq!(lines[0], "0 0 0 230 25 75");
+        assert_eq!(lines[8], "0 0 0 245 130 48");
+        assert_eq!(lines[88], "4 72 76 78 74");
+        assert_eq!(lines[100], "0 1");
+    }
+}